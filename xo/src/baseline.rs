@@ -0,0 +1,99 @@
+//! Weak players to measure the real engines against.
+
+use crate::board::{Board, GameResult, Move};
+use crate::minimax::evaluate_for;
+use crate::rng::Rng;
+use crate::strategy::Strategy;
+
+/// Plays any legal move, uniformly at random.
+pub struct RandomPlayer {
+    rng: Rng,
+}
+
+impl RandomPlayer {
+    pub fn new(rng: Rng) -> RandomPlayer {
+        RandomPlayer { rng }
+    }
+}
+
+impl Strategy for RandomPlayer {
+    fn name(&self) -> String {
+        "Random".to_owned()
+    }
+
+    fn choose_move(&mut self, board: &Board) -> Option<Move> {
+        let moves = board.legal_moves();
+        if moves.is_empty() {
+            return None;
+        }
+        Some(moves[self.rng.below(moves.len())])
+    }
+}
+
+/// Looks one move ahead: wins on the spot if it can, otherwise plays the
+/// move the heuristic likes best, breaking ties at random.
+pub struct Greedy {
+    rng: Rng,
+}
+
+impl Greedy {
+    pub fn new(rng: Rng) -> Greedy {
+        Greedy { rng }
+    }
+}
+
+impl Strategy for Greedy {
+    fn name(&self) -> String {
+        "Greedy".to_owned()
+    }
+
+    fn choose_move(&mut self, board: &Board) -> Option<Move> {
+        let me = board.to_move();
+        let mut board = board.clone();
+        let mut best = Vec::new();
+        let mut best_score = i32::MIN;
+        for mv in board.legal_moves() {
+            board.play(mv).expect("legal moves can be played");
+            let score = match board.result() {
+                Some(GameResult::Win(p)) if p == me => i32::MAX,
+                Some(GameResult::Win(_)) => i32::MIN + 1,
+                Some(GameResult::Draw) => 0,
+                None => evaluate_for(&board, me),
+            };
+            board.undo();
+            if score > best_score {
+                best_score = score;
+                best.clear();
+            }
+            if score == best_score {
+                best.push(mv);
+            }
+        }
+        if best.is_empty() {
+            return None;
+        }
+        Some(best[self.rng.below(best.len())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn greedy_takes_a_win_and_random_stays_legal() {
+        // X: a1 b1, O: a2 b2, X to move
+        let mut board = Board::new(3, 3);
+        for &(r, c) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+            board.play(Move::new(r, c)).unwrap();
+        }
+        assert_eq!(Greedy::new(Rng::new(0)).choose_move(&board), Some(Move::new(0, 2)));
+
+        let mut random = RandomPlayer::new(Rng::new(0));
+        while board.result().is_none() {
+            let mv = random.choose_move(&board).unwrap();
+            board.play(mv).unwrap();
+        }
+        assert_eq!(random.choose_move(&board), None);
+    }
+}
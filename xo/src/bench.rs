@@ -0,0 +1,248 @@
+use std::time::Instant;
+
+use crate::bitboard::Bitboard;
+use crate::board::{Board, Move, Piece, Player, Variant};
+use crate::minimax::Minimax;
+use crate::strategy::Strategy;
+
+/// Times the search from the empty board and from a couple of openings,
+/// then raw move generation, printing nodes visited and nodes per second,
+/// and then move generation and the win check done by scanning cells
+/// against the same done with bitboards. Build with `--release` for
+/// numbers worth comparing.
+pub fn run(board: &Board, mut engine: Minimax) {
+    let mid = board.size() / 2;
+    let centre = board.square(mid, mid, mid);
+    // in ultimate a1 sends the reply to the top-left board, so answer in
+    // its centre rather than the centre of the whole board
+    let reply = match board.variant() {
+        Variant::Ultimate => Move::new(1, 1),
+        _ => centre,
+    };
+    let openings: Vec<Vec<Move>> = vec![vec![], vec![centre], vec![board.square(0, 0, 0), reply]];
+    // on a gravity board only the column is up to the player
+    let gravity = board.variant() == Variant::Gravity;
+
+    let layers = if board.layers() > 1 { format!("x{}", board.layers()) } else { String::new() };
+    println!(
+        "{}x{}{} board, {} in a row, {}",
+        board.size(),
+        board.rows(),
+        layers,
+        board.k(),
+        engine.name()
+    );
+    for opening in openings {
+        let mut position = board.clone();
+        for &square in &opening {
+            // where the rules give a choice of piece, take the first one
+            let mv = position
+                .legal_moves()
+                .into_iter()
+                .find(|mv| mv.with_piece(None) == square || gravity && mv.col == square.col)
+                .expect("bench openings are legal");
+            position.play(mv).expect("legal moves can be played");
+        }
+        let moves: Vec<String> = position.moves().iter().map(|mv| mv.to_string()).collect();
+
+        // empty table each time so earlier searches don't flatter later ones
+        engine.clear();
+        let start = Instant::now();
+        let result = engine.search(&position);
+        let secs = start.elapsed().as_secs_f64();
+        println!(
+            "  after [{}]: best {} score {} depth {} nodes {} in {:.3}s ({:.0} nodes/s)",
+            moves.join(" "),
+            result.best.map_or("-".to_owned(), |mv| mv.to_string()),
+            result.score,
+            result.depth,
+            result.nodes,
+            secs,
+            result.nodes as f64 / secs.max(1e-9)
+        );
+    }
+
+    // the board on its own, with no search around it: every line of play
+    // a few moves deep, going deeper until one depth takes a quarter second
+    let mut position = board.clone();
+    for depth in 1.. {
+        let start = Instant::now();
+        let nodes = perft(&mut position, depth);
+        let secs = start.elapsed().as_secs_f64();
+        println!(
+            "  perft {}: {} nodes in {:.3}s ({:.0} nodes/s)",
+            depth,
+            nodes,
+            secs,
+            nodes as f64 / secs.max(1e-9)
+        );
+        if secs > 0.25 || depth as usize >= position.legal_moves().len() {
+            break;
+        }
+    }
+
+    // the same again with nothing but the squares, as a list of cells
+    // the way the board used to be, walked out from each move for the
+    // win, against bitboards, checking just the lines through the move
+    if !matches!(board.variant(), Variant::Classic | Variant::Cube) || board.rules().picks_pieces() {
+        return;
+    }
+    let mut scan = Scan::new(board);
+    for depth in 1..=board.squares() as u32 {
+        let start = Instant::now();
+        let nodes = scan.cells(board.to_move(), depth);
+        let cell_secs = start.elapsed().as_secs_f64();
+        let start = Instant::now();
+        assert_eq!(scan.bits(board.to_move(), depth), nodes, "both ways see the same positions");
+        let bit_secs = start.elapsed().as_secs_f64();
+        println!(
+            "  moves and wins {}: {} nodes, cells {:.0} nodes/s, bitboards {:.0} nodes/s ({:.1}x)",
+            depth,
+            nodes,
+            nodes as f64 / cell_secs.max(1e-9),
+            nodes as f64 / bit_secs.max(1e-9),
+            cell_secs / bit_secs.max(1e-9)
+        );
+        if cell_secs.max(bit_secs) > 0.25 {
+            break;
+        }
+    }
+}
+
+/// Plays out every sequence of `depth` moves, counting the positions
+/// visited, so move generation, play, undo and the win check are all
+/// timed together.
+pub fn perft(board: &mut Board, depth: u32) -> u64 {
+    let mut nodes = 1;
+    if depth == 0 {
+        return nodes;
+    }
+    for mv in board.legal_moves() {
+        board.play(mv).expect("legal moves can be played");
+        nodes += perft(board, depth - 1);
+        board.undo();
+    }
+    nodes
+}
+
+/// A position's marks held two ways, to play out every line of play on
+/// either, doing nothing but finding the empty squares and checking for
+/// a win after each move.
+struct Scan {
+    players: usize,
+    k: usize,
+    /// Layers, rows and columns.
+    dims: (isize, isize, isize),
+    directions: &'static [(isize, isize, isize)],
+    cells: Vec<Option<Player>>,
+    /// For each square, the lines through it, as sets of squares.
+    through: Vec<Vec<Bitboard>>,
+    marks: Vec<Bitboard>,
+    filled: Bitboard,
+}
+
+impl Scan {
+    fn new(board: &Board) -> Scan {
+        let squares = board.squares();
+        let mut through = vec![Vec::new(); squares];
+        for (line, mask) in board.lines().iter().zip(board.line_masks()) {
+            for &square in line {
+                through[square].push(mask.clone());
+            }
+        }
+        let cells: Vec<_> = (0..squares)
+            .map(|i| match board.cell(i) {
+                Some(Piece::Mark(p)) => Some(p),
+                _ => None,
+            })
+            .collect();
+        let filled: Vec<_> = (0..squares).filter(|&i| cells[i].is_some()).collect();
+        let players = board.players();
+        Scan {
+            players,
+            k: board.k(),
+            dims: (board.layers() as isize, board.rows() as isize, board.size() as isize),
+            directions: board.directions(),
+            cells,
+            through,
+            marks: Player::all(players).map(|p| board.marks(p).clone()).collect(),
+            filled: Bitboard::with(squares, &filled),
+        }
+    }
+
+    /// Perft over the cells: every square looked at for the moves, and
+    /// the squares either side of the move stepped through for the win.
+    fn cells(&mut self, player: Player, depth: u32) -> u64 {
+        let mut nodes = 1;
+        if depth == 0 {
+            return nodes;
+        }
+        let empty: Vec<usize> = (0..self.cells.len()).filter(|&i| self.cells[i].is_none()).collect();
+        for i in empty {
+            self.cells[i] = Some(player);
+            nodes += if self.walk_line(i) { 1 } else { self.cells(player.next(self.players), depth - 1) };
+            self.cells[i] = None;
+        }
+        nodes
+    }
+
+    /// Whether square `i` is in K or more in a row, counting out from it
+    /// both ways along each direction.
+    fn walk_line(&self, i: usize) -> bool {
+        let (layers, rows, cols) = self.dims;
+        let i = i as isize;
+        let (l, r, c) = (i / (rows * cols), i / cols % rows, i % cols);
+        let mark = self.cells[i as usize];
+        let run = |(dl, dr, dc): (isize, isize, isize)| {
+            let (mut l, mut r, mut c) = (l + dl, r + dr, c + dc);
+            let mut count = 0;
+            while (0..layers).contains(&l)
+                && (0..rows).contains(&r)
+                && (0..cols).contains(&c)
+                && self.cells[((l * rows + r) * cols + c) as usize] == mark
+            {
+                count += 1;
+                l += dl;
+                r += dr;
+                c += dc;
+            }
+            count
+        };
+        self.directions.iter().any(|&(dl, dr, dc)| 1 + run((dl, dr, dc)) + run((-dl, -dr, -dc)) >= self.k)
+    }
+
+    /// Perft over the bitboards: the empty squares a word at a time, and
+    /// each line through the move tested against the marks in one go.
+    fn bits(&mut self, player: Player, depth: u32) -> u64 {
+        let mut nodes = 1;
+        if depth == 0 {
+            return nodes;
+        }
+        let empty: Vec<usize> = self.filled.iter_missing(self.cells.len()).collect();
+        for i in empty {
+            self.filled.insert(i);
+            self.marks[player.index()].insert(i);
+            let marks = &self.marks[player.index()];
+            let won = self.through[i].iter().any(|line| marks.is_superset(line));
+            nodes += if won { 1 } else { self.bits(player.next(self.players), depth - 1) };
+            self.marks[player.index()].remove(i);
+            self.filled.remove(i);
+        }
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_and_bitboards_count_what_the_board_does() {
+        for mut board in [Board::new(3, 3), Board::new(5, 4), Board::cube(3, 3)] {
+            let mut scan = Scan::new(&board);
+            let depth = if board.squares() == 9 { 9 } else { 3 };
+            let nodes = perft(&mut board, depth);
+            assert_eq!((scan.cells(Player::X, depth), scan.bits(Player::X, depth)), (nodes, nodes));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Sets of squares packed one bit per square, so a whole line can be
+//! checked against a player's marks a word at a time instead of square
+//! by square.
+
+use std::fmt;
+
+/// A set of squares, by the same indexes as `Board::cell`. Boards up to
+/// 8x8 fit in a single word; bigger ones take as many as they need.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Bitboard {
+    words: Vec<u64>,
+}
+
+impl Bitboard {
+    /// No squares, with room for `squares` of them.
+    pub fn empty(squares: usize) -> Bitboard {
+        Bitboard {
+            words: vec![0; squares.div_ceil(64)],
+        }
+    }
+
+    /// Exactly the squares listed.
+    pub fn with(squares: usize, set: &[usize]) -> Bitboard {
+        let mut bits = Bitboard::empty(squares);
+        for &i in set {
+            bits.insert(i);
+        }
+        bits
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.words[i / 64] & 1 << (i % 64) != 0
+    }
+
+    pub fn insert(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    pub fn remove(&mut self, i: usize) {
+        self.words[i / 64] &= !(1 << (i % 64));
+    }
+
+    /// Whether every square of `other` is in this set too.
+    pub fn is_superset(&self, other: &Bitboard) -> bool {
+        self.words.iter().zip(&other.words).all(|(&a, &b)| a & b == b)
+    }
+
+    /// How many squares the two sets share.
+    pub fn common(&self, other: &Bitboard) -> u32 {
+        self.words.iter().zip(&other.words).map(|(&a, &b)| (a & b).count_ones()).sum()
+    }
+
+    /// The squares in the set, lowest first.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(w, &word)| ones(word).map(move |b| w * 64 + b))
+    }
+
+    /// The squares below `squares` that are not in the set, lowest first.
+    pub fn iter_missing(&self, squares: usize) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(move |(w, &word)| {
+            // leave out the unused bits past the last square
+            let valid = if (w + 1) * 64 <= squares { !0 } else { (1 << (squares - w * 64)) - 1 };
+            ones(!word & valid).map(move |b| w * 64 + b)
+        })
+    }
+
+    /// The set moved square by square: `perm[i]` is where square `i` goes.
+    pub fn permuted(&self, perm: &[usize]) -> Bitboard {
+        let mut bits = Bitboard::empty(perm.len());
+        for i in self.iter() {
+            bits.insert(perm[i]);
+        }
+        bits
+    }
+}
+
+/// Positions of the set bits of `word`, lowest first.
+fn ones(mut word: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if word == 0 {
+            return None;
+        }
+        let bit = word.trailing_zeros() as usize;
+        word &= word - 1;
+        Some(bit)
+    })
+}
+
+impl fmt::Debug for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Every rotation and reflection of a board `size` squares across with
+/// `layers` layers, as square permutations for `Bitboard::permuted`: the
+/// eight symmetries of a square, or the 48 of a cube. The identity comes
+/// first.
+pub fn symmetries(size: usize, layers: usize) -> Vec<Vec<usize>> {
+    let axes: &[[usize; 3]] = if layers > 1 {
+        &[[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]]
+    } else {
+        // the layer stays put on a flat board
+        &[[0, 1, 2], [0, 2, 1]]
+    };
+    let flips = if layers > 1 { 8 } else { 4 };
+    let squares = layers * size * size;
+    let mut all = Vec::new();
+    for order in axes {
+        for flip in 0..flips {
+            let perm = (0..squares)
+                .map(|i| {
+                    let from = [i / (size * size), i / size % size, i % size];
+                    let mut to = [0; 3];
+                    for (axis, &source) in order.iter().enumerate() {
+                        // flip bits go column, row, layer, so flat boards never flip layers
+                        let flipped = flip & 1 << (2 - axis) != 0;
+                        to[axis] = if flipped { size - 1 - from[source] } else { from[source] };
+                    }
+                    (to[0] * size + to[1]) * size + to[2]
+                })
+                .collect();
+            all.push(perm);
+        }
+    }
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_span_words() {
+        let mut bits = Bitboard::empty(225);
+        for &i in &[0, 63, 64, 200, 224] {
+            bits.insert(i);
+        }
+        assert_eq!(bits.iter().collect::<Vec<_>>(), [0, 63, 64, 200, 224]);
+        assert_eq!(bits.iter_missing(225).count(), 220);
+        bits.remove(64);
+        assert!(!bits.contains(64));
+        assert!(bits.is_superset(&Bitboard::with(225, &[63, 200])));
+        assert!(!bits.is_superset(&Bitboard::with(225, &[63, 64])));
+        assert_eq!(bits.common(&Bitboard::with(225, &[0, 1, 224])), 2);
+    }
+
+    #[test]
+    fn symmetries_are_distinct_permutations() {
+        for &(size, layers, count) in &[(3, 1, 8), (4, 1, 8), (3, 3, 48)] {
+            let all = symmetries(size, layers);
+            assert_eq!(all.len(), count);
+            assert_eq!(all[0], (0..layers * size * size).collect::<Vec<_>>());
+            for perm in &all {
+                let mut sorted = perm.clone();
+                sorted.sort_unstable();
+                assert_eq!(sorted, all[0]);
+            }
+            let mut unique = all.clone();
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), count);
+        }
+        // the corner a1 visits all four corners, the centre stays put
+        let corners: Vec<usize> = symmetries(3, 1).iter().map(|p| p[0]).collect();
+        assert!(corners.iter().all(|c| [0, 2, 6, 8].contains(c)));
+        assert!(symmetries(3, 1).iter().all(|p| p[4] == 4));
+    }
+}
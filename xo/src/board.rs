@@ -0,0 +1,1314 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::bitboard::{self, Bitboard};
+use crate::rng::Rng;
+use crate::rules::Rules;
+
+/// Most players one board can take turns between.
+pub const MAX_PLAYERS: usize = 8;
+
+/// The mark each player puts on the board, by turn order, when nobody has
+/// picked their own.
+const LETTERS: [char; MAX_PLAYERS] = ['X', 'O', 'Y', 'Z', 'W', 'V', 'U', 'T'];
+
+/// A player, by turn order. X always moves first and O second; on boards
+/// with more players Y, Z and so on follow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Player(u8);
+
+impl Player {
+    pub const X: Player = Player(0);
+    pub const O: Player = Player(1);
+
+    /// The player moving `i`th in each round, from 0. Panics from
+    /// `MAX_PLAYERS` on.
+    pub fn nth(i: usize) -> Player {
+        assert!(i < MAX_PLAYERS, "at most {} players", MAX_PLAYERS);
+        Player(i as u8)
+    }
+
+    /// Every player in a game of `players`, in turn order.
+    pub fn all(players: usize) -> impl Iterator<Item = Player> {
+        (0..players).map(Player::nth)
+    }
+
+    /// Where the player comes in the turn order, from 0.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// Whose turn comes after this player's in a game of `players`.
+    pub fn next(self, players: usize) -> Player {
+        Player::nth((self.index() + 1) % players)
+    }
+
+    /// Whose turn came before.
+    pub fn previous(self, players: usize) -> Player {
+        Player::nth((self.index() + players - 1) % players)
+    }
+
+    /// The opponent, in a two-player game.
+    pub fn other(self) -> Player {
+        self.next(2)
+    }
+
+    pub fn letter(self) -> char {
+        LETTERS[self.index()]
+    }
+
+    /// The player whose mark is `c`, either case.
+    pub fn from_letter(c: char) -> Option<Player> {
+        let c = c.to_ascii_uppercase();
+        LETTERS.iter().position(|&l| l == c).map(Player::nth)
+    }
+}
+
+impl fmt::Display for Player {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.letter())
+    }
+}
+
+/// What fills a square: the mark of whoever played there, unless the
+/// rules let players put down the other mark, or numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Piece {
+    Mark(Player),
+    Number(u8),
+}
+
+impl Piece {
+    /// Distinct small number per piece, below `PIECE_CODES`, for hashing.
+    fn code(self) -> usize {
+        match self {
+            Piece::Mark(p) => p.index(),
+            Piece::Number(n) => MAX_PLAYERS + n as usize,
+        }
+    }
+}
+
+/// One more than the largest `Piece::code`: every player's mark and the
+/// numbers up to 9.
+const PIECE_CODES: usize = MAX_PLAYERS + 10;
+
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Piece::Mark(p) => write!(f, "{}", p),
+            Piece::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// Widest board we can label: columns are the letters a to z.
+pub const MAX_SIZE: usize = 26;
+
+/// A square on the board, zero-based.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    /// Cube boards only: which layer, counting from the top.
+    pub layer: Option<usize>,
+    pub row: usize,
+    pub col: usize,
+    /// What to put there, when the rules give a choice. `None` is the
+    /// mover's own mark.
+    pub piece: Option<Piece>,
+}
+
+impl Move {
+    pub fn new(row: usize, col: usize) -> Move {
+        Move {
+            layer: None,
+            row,
+            col,
+            piece: None,
+        }
+    }
+
+    /// A square on one layer of a cube.
+    pub fn layered(layer: usize, row: usize, col: usize) -> Move {
+        Move {
+            layer: Some(layer),
+            ..Move::new(row, col)
+        }
+    }
+
+    /// The same square, putting down `piece` instead.
+    pub fn with_piece(self, piece: Option<Piece>) -> Move {
+        Move { piece, ..self }
+    }
+}
+
+impl fmt::Display for Move {
+    // same order the player types it in: layer number on a cube, then
+    // column letter, then row number
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(layer) = self.layer {
+            write!(f, "{}", layer + 1)?;
+        }
+        write!(f, "{}{}", column_letter(self.col), self.row + 1)?;
+        if let Some(piece) = self.piece {
+            write!(f, "={}", piece)?;
+        }
+        Ok(())
+    }
+}
+
+/// Letter naming a column, or `?` past the last one we can name.
+pub fn column_letter(col: usize) -> char {
+    if col < MAX_SIZE {
+        (b'a' + col as u8) as char
+    } else {
+        '?'
+    }
+}
+
+/// Why a move was refused, whether it didn't parse or didn't fit the board.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MoveError {
+    Empty,
+    /// Didn't look like a move at all.
+    Unrecognised(String),
+    /// The column as typed, letter or number.
+    ColumnOutOfRange(String),
+    /// Rows are counted from 1, as typed.
+    RowOutOfRange(usize),
+    /// Layers too.
+    LayerOutOfRange(usize),
+    /// A square on a cube without saying which layer.
+    NoLayer,
+    SquareTaken(Move),
+    /// The square is free but the rules of this variant rule it out.
+    Forbidden(String),
+    GameOver,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::Empty => write!(f, "no move entered"),
+            MoveError::Unrecognised(s) => write!(f, "couldn't read \"{}\" as a move", s),
+            MoveError::ColumnOutOfRange(c) => write!(f, "column {} out of range", c),
+            MoveError::RowOutOfRange(r) => write!(f, "row {} out of range", r),
+            MoveError::LayerOutOfRange(l) => write!(f, "layer {} out of range", l),
+            MoveError::NoLayer => write!(f, "no layer given (2b3 is b3 on layer 2)"),
+            MoveError::SquareTaken(mv) => write!(f, "square {} already taken", mv),
+            MoveError::Forbidden(why) => write!(f, "{}", why),
+            MoveError::GameOver => write!(f, "the game is already over"),
+        }
+    }
+}
+
+impl Error for MoveError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    Win(Player),
+    Draw,
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameResult::Win(p) => write!(f, "{} wins", p),
+            GameResult::Draw => write!(f, "draw"),
+        }
+    }
+}
+
+/// Which game is played on the squares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// K in a row anywhere on the board.
+    Classic,
+    /// A 3x3 grid of 3x3 boards. Win a small board with three in a row,
+    /// and the game with three small boards in a row. Where you play
+    /// inside a small board picks the small board your opponent plays in
+    /// next, unless that one is already decided.
+    Ultimate,
+    /// K in a row on an N by N by N cube: along a row, column or pillar,
+    /// or any diagonal, including those from corner to corner of the cube.
+    Cube,
+    /// Connect Four: N columns of N - 1 rows, standing upright. Players
+    /// pick a column and the piece drops to the lowest empty square.
+    Gravity,
+}
+
+impl Variant {
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Classic => "classic",
+            Variant::Ultimate => "ultimate",
+            Variant::Cube => "cube",
+            Variant::Gravity => "gravity",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name {
+            "classic" => Some(Variant::Classic),
+            "ultimate" => Some(Variant::Ultimate),
+            "cube" => Some(Variant::Cube),
+            "gravity" | "connect4" => Some(Variant::Gravity),
+            _ => None,
+        }
+    }
+
+    /// The size to play when none is given. Qubic, the 4x4x4 game, is the
+    /// cube worth playing, and Connect Four is seven columns of six.
+    pub fn default_size(self) -> usize {
+        match self {
+            Variant::Cube => 4,
+            Variant::Gravity => 7,
+            Variant::Classic | Variant::Ultimate => 3,
+        }
+    }
+
+    /// How many in a row win when that isn't given either.
+    pub fn default_k(self, size: usize) -> usize {
+        match self {
+            Variant::Gravity => size.min(4),
+            _ => size.min(5),
+        }
+    }
+}
+
+/// An N by N board where K marks in a row win. Keeps track of whose
+/// turn it is so callers only have to say where to play.
+#[derive(Clone, Debug)]
+pub struct Board {
+    /// Squares across, which is also squares down on all but gravity boards.
+    size: usize,
+    rows: usize,
+    k: usize,
+    variant: Variant,
+    rules: Rules,
+    /// Layer by layer, then row by row. What the board shows; the engines
+    /// mostly look at `marks` instead.
+    cells: Vec<Option<Piece>>,
+    /// How many take turns, two unless the board says otherwise.
+    players: usize,
+    /// The squares holding each player's mark, in turn order.
+    marks: Vec<Bitboard>,
+    /// The squares holding anything at all.
+    filled: Bitboard,
+    shape: Arc<Shape>,
+    /// Ultimate only: the result of each small board, in reading order.
+    small: Vec<Option<GameResult>>,
+    to_move: Player,
+    history: Vec<Move>,
+    winner: Option<Player>,
+    hash: u64,
+}
+
+/// What every position on one kind of board has in common, worked out
+/// once and shared between clones.
+struct Shape {
+    /// Every run of K squares that could win.
+    lines: Vec<Vec<usize>>,
+    /// The same runs as sets of squares, to test against `Board::marks`.
+    masks: Vec<Bitboard>,
+    /// For each square, which of `lines` go through it.
+    through: Vec<Vec<usize>>,
+    /// Zobrist keys: `PIECE_CODES` per square, then one for each small
+    /// board an ultimate player can be sent to.
+    keys: Vec<u64>,
+}
+
+/// Fixed, so boards of the same shape always hash positions the same way.
+const ZOBRIST_SEED: u64 = 0x786f_786f;
+
+impl Shape {
+    fn new(squares: usize, lines: Vec<Vec<usize>>) -> Shape {
+        let masks = lines.iter().map(|line| Bitboard::with(squares, line)).collect();
+        let mut through = vec![Vec::new(); squares];
+        for (i, line) in lines.iter().enumerate() {
+            for &square in line {
+                through[square].push(i);
+            }
+        }
+        let mut rng = Rng::new(ZOBRIST_SEED);
+        let keys = (0..squares * PIECE_CODES + 9).map(|_| rng.next_u64()).collect();
+        Shape {
+            lines,
+            masks,
+            through,
+            keys,
+        }
+    }
+}
+
+impl fmt::Debug for Shape {
+    // the lines and keys run to thousands of entries on big boards
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Shape {{ {} squares, {} lines }}", self.through.len(), self.lines.len())
+    }
+}
+
+/// Steps between neighbouring squares of a line, as (layer, row, col).
+/// Flat boards only use the first four: right, down, down-right and
+/// down-left. Cubes add the nine that go down through the layers.
+const DIRECTIONS: [(isize, isize, isize); 13] = [
+    (0, 0, 1),
+    (0, 1, 0),
+    (0, 1, 1),
+    (0, 1, -1),
+    (1, 0, 0),
+    (1, 0, 1),
+    (1, 0, -1),
+    (1, 1, 0),
+    (1, -1, 0),
+    (1, 1, 1),
+    (1, 1, -1),
+    (1, -1, 1),
+    (1, -1, -1),
+];
+
+/// Every run of `k` squares in a straight line on a board `size` squares
+/// across and `rows` down with `layers` layers, as indexes into the
+/// cells. With `k` equal to the size that's the classic 8 lines of 3x3,
+/// or the 76 of a 4x4x4 cube.
+pub fn winning_lines(size: usize, rows: usize, k: usize, layers: usize) -> Vec<Vec<usize>> {
+    let (n, height) = (size as isize, rows as isize);
+    let depth = layers as isize;
+    let directions = if layers > 1 { &DIRECTIONS[..] } else { &DIRECTIONS[..4] };
+    let mut lines = Vec::new();
+    for &(dl, dr, dc) in directions {
+        for l in 0..depth {
+            for r in 0..height {
+                for c in 0..n {
+                    let steps = k as isize - 1;
+                    let (end_l, end_r, end_c) = (l + dl * steps, r + dr * steps, c + dc * steps);
+                    if end_l < 0 || end_l >= depth || end_r < 0 || end_r >= height || end_c < 0 || end_c >= n {
+                        continue;
+                    }
+                    let line = (0..k as isize)
+                        .map(|i| (((l + dl * i) * height + r + dr * i) * n + c + dc * i) as usize)
+                        .collect();
+                    lines.push(line);
+                }
+            }
+        }
+    }
+    lines
+}
+
+impl Board {
+    /// Panics unless `1 <= k <= size <= MAX_SIZE`.
+    pub fn new(size: usize, k: usize) -> Board {
+        Board::empty(Variant::Classic, size, size, k, 1)
+    }
+
+    /// The 9x9 board for ultimate tic-tac-toe.
+    pub fn ultimate() -> Board {
+        Board {
+            small: vec![None; 9],
+            ..Board::empty(Variant::Ultimate, 9, 9, 3, 1)
+        }
+    }
+
+    /// An N by N by N cube where K in a row wins. Panics like `new`.
+    pub fn cube(size: usize, k: usize) -> Board {
+        Board::empty(Variant::Cube, size, size, k, size)
+    }
+
+    /// Connect Four, `size` columns wide and one fewer high: 7 and 4 for
+    /// the usual game. Panics unless `2 <= size`, and like `new`.
+    pub fn gravity(size: usize, k: usize) -> Board {
+        assert!(size >= 2, "a gravity board needs at least 2 columns");
+        Board::empty(Variant::Gravity, size, size - 1, k, 1)
+    }
+
+    fn empty(variant: Variant, size: usize, rows: usize, k: usize, layers: usize) -> Board {
+        assert!((1..=MAX_SIZE).contains(&size), "board size must be 1..={}", MAX_SIZE);
+        assert!((1..=size).contains(&k), "win length must be 1..={}", size);
+        let squares = layers * rows * size;
+        let lines = match variant {
+            // wins are decided small board by small board, not by lines
+            Variant::Ultimate => Vec::new(),
+            _ => winning_lines(size, rows, k, layers),
+        };
+        Board {
+            size,
+            rows,
+            k,
+            variant,
+            rules: Rules::Standard,
+            cells: vec![None; squares],
+            players: 2,
+            marks: vec![Bitboard::empty(squares); 2],
+            filled: Bitboard::empty(squares),
+            shape: Arc::new(Shape::new(squares, lines)),
+            small: Vec::new(),
+            to_move: Player::X,
+            history: Vec::new(),
+            winner: None,
+            hash: 0,
+        }
+    }
+
+    /// Whether `with_variant`, `with_rules` and `with_players` can build
+    /// this board, or what's wrong with it. Settings from outside the
+    /// program (the command line, a network peer) go through here before
+    /// they reach the constructors, which panic instead.
+    pub fn check_settings(variant: Variant, size: usize, k: usize, rules: Rules, players: usize) -> Result<(), String> {
+        check_players(variant, rules, players)?;
+        if size == 0 || size > MAX_SIZE {
+            return Err(format!("the size must be between 1 and {}", MAX_SIZE));
+        }
+        if variant == Variant::Gravity && size < 2 {
+            return Err("a gravity board needs at least 2 columns".to_owned());
+        }
+        if k == 0 || k > size {
+            return Err(format!("k must be between 1 and the board size ({})", size));
+        }
+        rules.supports(variant, size, k)
+    }
+
+    pub fn with_variant(variant: Variant, size: usize, k: usize) -> Board {
+        match variant {
+            Variant::Classic => Board::new(size, k),
+            Variant::Ultimate => Board::ultimate(),
+            Variant::Cube => Board::cube(size, k),
+            Variant::Gravity => Board::gravity(size, k),
+        }
+    }
+
+    /// The same empty board played under `rules`. Panics unless
+    /// `rules.supports` the board.
+    pub fn with_rules(self, rules: Rules) -> Board {
+        if let Err(e) = rules.supports(self.variant, self.size, self.k).and(check_players(self.variant, rules, self.players)) {
+            panic!("{}", e);
+        }
+        Board { rules, ..self }
+    }
+
+    /// The same empty board with `players` taking turns. Panics unless
+    /// that's 2 to `MAX_PLAYERS`, and 2 for ultimate or any rules but the
+    /// standard ones.
+    pub fn with_players(self, players: usize) -> Board {
+        if let Err(e) = check_players(self.variant, self.rules, players) {
+            panic!("{}", e);
+        }
+        let squares = self.cells.len();
+        Board {
+            players,
+            marks: vec![Bitboard::empty(squares); players],
+            ..self
+        }
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn players(&self) -> usize {
+        self.players
+    }
+
+    /// Squares down: one fewer than across on a gravity board.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// How many squares there are, on every layer.
+    pub fn squares(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// How many layers deep the board is: the size for a cube, else 1.
+    pub fn layers(&self) -> usize {
+        match self.variant {
+            Variant::Cube => self.size,
+            _ => 1,
+        }
+    }
+
+    /// The square at those coordinates. `layer` is ignored on flat boards.
+    pub fn square(&self, layer: usize, row: usize, col: usize) -> Move {
+        match self.variant {
+            Variant::Cube => Move::layered(layer, row, col),
+            _ => Move::new(row, col),
+        }
+    }
+
+    /// The middle square, or the one just before it on even sizes.
+    pub fn centre(&self) -> Move {
+        self.square((self.layers() - 1) / 2, (self.rows - 1) / 2, (self.size - 1) / 2)
+    }
+
+    /// Every run of K squares in a row, as indexes for `cell`. Ultimate
+    /// has none; its wins come from the small boards.
+    pub fn lines(&self) -> &[Vec<usize>] {
+        &self.shape.lines
+    }
+
+    /// `lines` as sets of squares, in the same order.
+    pub fn line_masks(&self) -> &[Bitboard] {
+        &self.shape.masks
+    }
+
+    /// The squares holding `player`'s mark.
+    pub fn marks(&self, player: Player) -> &Bitboard {
+        &self.marks[player.index()]
+    }
+
+    /// Every rotation and reflection of the board, as permutations of the
+    /// square indexes (see `bitboard::symmetries`). Gravity only has the
+    /// mirror image; turned on its side, pieces would fall the wrong way.
+    pub fn symmetries(&self) -> Vec<Vec<usize>> {
+        match self.variant {
+            Variant::Gravity => {
+                let mirror = (0..self.cells.len()).map(|i| i - i % self.size + self.size - 1 - i % self.size);
+                vec![(0..self.cells.len()).collect(), mirror.collect()]
+            }
+            _ => bitboard::symmetries(self.size, self.layers()),
+        }
+    }
+
+    /// Where `mv` is among the cells, as used in `lines`.
+    pub fn index(&self, mv: Move) -> usize {
+        (mv.layer.unwrap_or(0) * self.rows + mv.row) * self.size + mv.col
+    }
+
+    /// The square at an index from `lines`.
+    pub fn square_at(&self, idx: usize) -> Move {
+        let (n, rows) = (self.size, self.rows);
+        self.square(idx / (n * rows), idx / n % rows, idx % n)
+    }
+
+    /// Gravity only: the row a piece dropped in `col` lands on, or `None`
+    /// if the column is full.
+    pub fn landing(&self, col: usize) -> Option<usize> {
+        (0..self.rows).rev().find(|&row| self.get(row, col).is_none())
+    }
+
+    pub fn to_move(&self) -> Player {
+        self.to_move
+    }
+
+    /// The piece on a square of a flat board, or of a cube's top layer.
+    pub fn get(&self, row: usize, col: usize) -> Option<Piece> {
+        self.cells[row * self.size + col]
+    }
+
+    /// The piece on `mv`, which must be on the board.
+    pub fn at(&self, mv: Move) -> Option<Piece> {
+        self.cells[self.index(mv)]
+    }
+
+    /// The piece on a square by its index, as used in `lines`.
+    pub fn cell(&self, idx: usize) -> Option<Piece> {
+        self.cells[idx]
+    }
+
+    /// Number of marks on the board.
+    pub fn moves_played(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn last_move(&self) -> Option<Move> {
+        self.history.last().copied()
+    }
+
+    /// Every move so far, oldest first.
+    pub fn moves(&self) -> &[Move] {
+        &self.history
+    }
+
+    /// Zobrist hash of the marks on the board, updated as moves are played and
+    /// undone. Equal positions hash equally however they were reached.
+    pub fn hash(&self) -> u64 {
+        match self.forced_board() {
+            // where the next move must go is part of the position too
+            Some(b) => self.hash ^ self.shape.keys[self.cells.len() * PIECE_CODES + b],
+            None => self.hash,
+        }
+    }
+
+    /// Moves the player to move may play, in reading order (and for each
+    /// square, every piece they could put there), or nothing once the
+    /// game is over.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if self.result().is_some() {
+            return Vec::new();
+        }
+        let pieces = self.rules.pieces(self);
+        let forced = self.forced_board();
+        self.filled
+            .iter_missing(self.cells.len())
+            .map(|i| self.square_at(i))
+            .filter(|&mv| match self.variant {
+                Variant::Ultimate => {
+                    let b = small_board_of(mv);
+                    self.small[b].is_none() && forced.is_none_or(|f| f == b)
+                }
+                // pieces rest on the bottom row or on another piece
+                Variant::Gravity => mv.row + 1 == self.rows || self.filled.contains(self.index(mv) + self.size),
+                Variant::Classic | Variant::Cube => true,
+            })
+            .filter(|&mv| self.rules.check_square(self, mv).is_ok())
+            .flat_map(|mv| pieces.iter().map(move |&piece| mv.with_piece(piece)))
+            .collect()
+    }
+
+    /// Ultimate only: the small board (0 to 8, reading order) the player to
+    /// move has to play in, or `None` if they may pick any open one.
+    pub fn forced_board(&self) -> Option<usize> {
+        if self.variant != Variant::Ultimate {
+            return None;
+        }
+        let last = self.last_move()?;
+        let next = (last.row % 3) * 3 + last.col % 3;
+        if self.small[next].is_none() {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// Ultimate only: how each small board has ended so far.
+    pub fn small_results(&self) -> &[Option<GameResult>] {
+        &self.small
+    }
+
+    fn allowed_in_ultimate(&self, mv: Move) -> Result<(), MoveError> {
+        let target = small_board_of(mv);
+        if self.small[target].is_some() {
+            return Err(MoveError::Forbidden(format!(
+                "the {} board is already decided",
+                SMALL_BOARD_NAMES[target]
+            )));
+        }
+        match self.forced_board() {
+            Some(forced) if forced != target => Err(MoveError::Forbidden(format!(
+                "you have to play in the {} board",
+                SMALL_BOARD_NAMES[forced]
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the player to move could play `mv`, without playing it.
+    pub fn check(&self, mv: Move) -> Result<(), MoveError> {
+        if self.result().is_some() {
+            return Err(MoveError::GameOver);
+        }
+        if mv.col >= self.size {
+            return Err(MoveError::ColumnOutOfRange(column_letter(mv.col).to_string()));
+        }
+        if mv.row >= self.rows {
+            return Err(MoveError::RowOutOfRange(mv.row + 1));
+        }
+        match (self.variant, mv.layer) {
+            (Variant::Cube, None) => return Err(MoveError::NoLayer),
+            (Variant::Cube, Some(l)) if l < self.size => {}
+            (_, None) => {}
+            (_, Some(l)) => return Err(MoveError::LayerOutOfRange(l + 1)),
+        }
+        if self.at(mv).is_some() {
+            return Err(MoveError::SquareTaken(mv));
+        }
+        if self.variant == Variant::Ultimate {
+            self.allowed_in_ultimate(mv)?;
+        }
+        if self.variant == Variant::Gravity {
+            match self.landing(mv.col) {
+                Some(row) if row != mv.row => {
+                    let lands = Move::new(row, mv.col);
+                    return Err(MoveError::Forbidden(format!("pieces dropped in {} land on {}", column_letter(mv.col), lands)));
+                }
+                _ => {}
+            }
+        }
+        self.rules.check_square(self, mv)?;
+        self.rules.check_piece(self, mv.piece)
+    }
+
+    /// Puts the current player's mark (or the piece `mv` names) on the
+    /// square and passes the turn.
+    pub fn play(&mut self, mv: Move) -> Result<(), MoveError> {
+        self.check(mv)?;
+        let idx = self.index(mv);
+        let piece = mv.piece.unwrap_or(Piece::Mark(self.to_move));
+        self.cells[idx] = Some(piece);
+        self.filled.insert(idx);
+        if let Piece::Mark(p) = piece {
+            self.marks[p.index()].insert(idx);
+        }
+        self.hash ^= self.shape.keys[idx * PIECE_CODES + piece.code()];
+        self.history.push(mv);
+        match self.variant {
+            Variant::Classic | Variant::Cube | Variant::Gravity => self.winner = self.rules.winner_after(self, mv),
+            Variant::Ultimate => {
+                let b = small_board_of(mv);
+                self.small[b] = self.small_board_result(b);
+                if self.small[b] == Some(GameResult::Win(self.to_move)) && self.meta_line(b).is_some() {
+                    self.winner = Some(self.to_move);
+                }
+            }
+        }
+        self.to_move = self.to_move.next(self.players);
+        Ok(())
+    }
+
+    /// Takes back the last move. Returns it, or `None` on an empty board.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.history.pop()?;
+        let idx = self.index(mv);
+        self.to_move = self.to_move.previous(self.players);
+        if let Some(piece) = self.cells[idx].take() {
+            self.filled.remove(idx);
+            if let Piece::Mark(p) = piece {
+                self.marks[p.index()].remove(idx);
+            }
+            self.hash ^= self.shape.keys[idx * PIECE_CODES + piece.code()];
+        }
+        if self.variant == Variant::Ultimate {
+            let b = small_board_of(mv);
+            self.small[b] = self.small_board_result(b);
+        }
+        // a win always ends the game, so it can only have come from this move
+        self.winner = None;
+        Some(mv)
+    }
+
+    /// Ultimate only: scans small board `b` for a win or a full board.
+    fn small_board_result(&self, b: usize) -> Option<GameResult> {
+        let (top, left) = ((b / 3) * 3, (b % 3) * 3);
+        let at = |r: usize, c: usize| self.get(top + r, left + c);
+        for line in &LINES_3X3 {
+            if let Some(Piece::Mark(p)) = at(line[0] / 3, line[0] % 3) {
+                if line.iter().all(|&i| at(i / 3, i % 3) == Some(Piece::Mark(p))) {
+                    return Some(GameResult::Win(p));
+                }
+            }
+        }
+        if (0..9).all(|i| at(i / 3, i % 3).is_some()) {
+            return Some(GameResult::Draw);
+        }
+        None
+    }
+
+    /// Ultimate only: the three small boards in a row through `b` that
+    /// one player has all won, if there are any.
+    fn meta_line(&self, b: usize) -> Option<[usize; 3]> {
+        let owner = match self.small[b] {
+            Some(GameResult::Win(p)) => p,
+            _ => return None,
+        };
+        LINES_3X3
+            .iter()
+            .find(|line| line.contains(&b) && line.iter().all(|&i| self.small[i] == Some(GameResult::Win(owner))))
+            .copied()
+    }
+
+    /// Whether the mark on `mv` completes K in a row. Tests only the lines
+    /// through `mv`, each against the marks in one go, so it's the cheap
+    /// check to make after every move; `run_through` finds the squares.
+    pub fn makes_line(&self, mv: Move) -> bool {
+        let idx = self.index(mv);
+        let marks = match self.cells[idx] {
+            Some(Piece::Mark(p)) => &self.marks[p.index()],
+            _ => return false,
+        };
+        self.shape.through[idx].iter().any(|&line| marks.is_superset(&self.shape.masks[line]))
+    }
+
+    /// The run of K or more of the same piece through `mv`, in order, if
+    /// there is one; on an overline the whole run. Only the lines through
+    /// the newest stone can have changed, so this is all the win check
+    /// needs on big boards.
+    pub fn run_through(&self, mv: Move) -> Option<Vec<Move>> {
+        let mark = self.at(mv);
+        mark?;
+        for &(dl, dr, dc) in self.directions() {
+            let back = self.run(mv, (dl, dr, dc), -1, mark) as isize;
+            let len = 1 + back + self.run(mv, (dl, dr, dc), 1, mark) as isize;
+            if len >= self.k as isize {
+                let l = mv.layer.unwrap_or(0) as isize - dl * back;
+                let (r, c) = (mv.row as isize - dr * back, mv.col as isize - dc * back);
+                let line = (0..len)
+                    .map(|i| self.square((l + dl * i) as usize, (r + dr * i) as usize, (c + dc * i) as usize))
+                    .collect();
+                return Some(line);
+            }
+        }
+        None
+    }
+
+    /// Steps between neighbouring squares of a line, as (layer, row, col).
+    pub(crate) fn directions(&self) -> &'static [(isize, isize, isize)] {
+        if self.layers() > 1 {
+            &DIRECTIONS
+        } else {
+            &DIRECTIONS[..4]
+        }
+    }
+
+    /// Counts squares holding `mark` stepping away from `mv` (forwards
+    /// along `step` if `sign` is 1, backwards if -1), not counting `mv`.
+    fn run(&self, mv: Move, step: (isize, isize, isize), sign: isize, mark: Option<Piece>) -> usize {
+        let (n, rows, depth) = (self.size as isize, self.rows as isize, self.layers() as isize);
+        let (dl, dr, dc) = (step.0 * sign, step.1 * sign, step.2 * sign);
+        let mut count = 0;
+        let (mut l, mut r, mut c) = (mv.layer.unwrap_or(0) as isize + dl, mv.row as isize + dr, mv.col as isize + dc);
+        while (0..depth).contains(&l)
+            && (0..rows).contains(&r)
+            && (0..n).contains(&c)
+            && self.at(self.square(l as usize, r as usize, c as usize)) == mark
+        {
+            count += 1;
+            l += dl;
+            r += dr;
+            c += dc;
+        }
+        count
+    }
+
+    pub fn winner(&self) -> Option<Player> {
+        self.winner
+    }
+
+    /// The squares of the line that ended the game, in order, once someone
+    /// has won. On an overline this is the whole run, not just K of it.
+    pub fn winning_line(&self) -> Option<Vec<Move>> {
+        self.winner?;
+        let mv = self.last_move()?;
+        if self.variant == Variant::Ultimate {
+            // every square of the three small boards that won it
+            let boards = self.meta_line(small_board_of(mv))?;
+            let line = boards
+                .iter()
+                .flat_map(|&b| (0..9).map(move |i| Move::new((b / 3) * 3 + i / 3, (b % 3) * 3 + i % 3)))
+                .collect();
+            return Some(line);
+        }
+        self.rules.completed_line(self, mv)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.history.len() == self.cells.len()
+    }
+
+    /// `None` while the game is still going.
+    pub fn result(&self) -> Option<GameResult> {
+        match self.winner() {
+            Some(p) => Some(GameResult::Win(p)),
+            None if self.is_full() => Some(GameResult::Draw),
+            // every small board decided without three in a row
+            None if self.variant == Variant::Ultimate && self.small.iter().all(|r| r.is_some()) => {
+                Some(GameResult::Draw)
+            }
+            // renju's X with only fouls left
+            None => self.rules.stalemate(self),
+        }
+    }
+
+    /// What it takes to win, to tell the players at the start, or `None`
+    /// for the classic game everyone knows.
+    pub fn goal(&self) -> Option<String> {
+        if let Some(rules) = self.rules.describe(self) {
+            return Some(rules);
+        }
+        match self.variant {
+            Variant::Cube => Some(format!("Get {} in a row in any direction, through the layers too.", self.k)),
+            Variant::Gravity => Some(format!("Drop pieces into the columns; get {} in a row to win.", self.k)),
+            Variant::Classic if self.k != self.size => Some(format!("Get {} in a row to win.", self.k)),
+            Variant::Classic | Variant::Ultimate => None,
+        }
+    }
+}
+
+/// Whether `players` can play a game of `variant` under `rules`. Every
+/// mark gets its own line on ordinary boards, but the other rules and
+/// ultimate's small boards are made for two.
+fn check_players(variant: Variant, rules: Rules, players: usize) -> Result<(), String> {
+    if !(2..=MAX_PLAYERS).contains(&players) {
+        return Err(format!("there must be between 2 and {} players", MAX_PLAYERS));
+    }
+    if players > 2 && variant == Variant::Ultimate {
+        return Err("ultimate is for two players".to_owned());
+    }
+    if players > 2 && rules != Rules::Standard {
+        return Err(format!("{} rules are for two players", rules.name()));
+    }
+    Ok(())
+}
+
+/// The eight lines of a 3x3 board, as squares in reading order.
+const LINES_3X3: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+const SMALL_BOARD_NAMES: [&str; 9] = [
+    "top-left",
+    "top",
+    "top-right",
+    "left",
+    "middle",
+    "right",
+    "bottom-left",
+    "bottom",
+    "bottom-right",
+];
+
+/// Characters `s` takes up on screen, leaving out ANSI escape codes.
+fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in s.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            c if in_escape && c.is_ascii_alphabetic() => in_escape = false,
+            _ if in_escape => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+/// Which of the nine small boards of an ultimate board `mv` is in.
+fn small_board_of(mv: Move) -> usize {
+    (mv.row / 3) * 3 + mv.col / 3
+}
+
+impl Default for Board {
+    fn default() -> Board {
+        Board::new(3, 3)
+    }
+}
+
+impl Board {
+    /// Draws the board as text, ending lines with `newline`. `style` can
+    /// wrap any square's contents in a (prefix, suffix) pair, such as the
+    /// escape codes the terminal UI uses for highlighting.
+    pub fn render(&self, newline: &str, style: &dyn Fn(Move) -> Option<(&'static str, &'static str)>) -> String {
+        self.render_labelled(newline, style, &|_| None)
+    }
+
+    /// Like `render`, with `label` putting text of its own on any square
+    /// in place of what's there, such as a hint's verdict on playing
+    /// there or a player's own symbol. It should take one column.
+    pub fn render_labelled(
+        &self,
+        newline: &str,
+        style: &dyn Fn(Move) -> Option<(&'static str, &'static str)>,
+        label: &dyn Fn(Move) -> Option<String>,
+    ) -> String {
+        match self.variant {
+            Variant::Cube => self.render_cube(newline, style, label),
+            Variant::Ultimate => self.grid(0, style, label).join(newline) + &self.ultimate_summary(newline),
+            Variant::Classic | Variant::Gravity => self.grid(0, style, label).join(newline),
+        }
+    }
+
+    /// The lines of text for one layer: rows between rules, then the
+    /// column letters.
+    fn grid(
+        &self,
+        layer: usize,
+        style: &dyn Fn(Move) -> Option<(&'static str, &'static str)>,
+        label: &dyn Fn(Move) -> Option<String>,
+    ) -> Vec<String> {
+        let mut lines = Vec::new();
+        // leave room for two-digit row labels on big boards
+        let margin = if self.rows >= 10 { 2 } else { 1 };
+        let ultimate = self.variant == Variant::Ultimate;
+        // ultimate marks the edges of the small boards with heavier lines
+        let heavy = |i: usize| ultimate && i.is_multiple_of(3) && i > 0;
+        let horiz = " ".repeat(margin) + &"-".repeat(4 * self.size + 1);
+        let heavy_horiz = " ".repeat(margin) + &"=".repeat(4 * self.size + 1);
+        for row in 0..self.rows {
+            lines.push(if heavy(row) { heavy_horiz.clone() } else { horiz.clone() });
+            let mut line = format!("{:>width$}", row + 1, width = margin);
+            for col in 0..self.size {
+                line += if heavy(col) { "#" } else { "|" };
+                let mv = self.square(layer, row, col);
+                let mark = match (label(mv), self.at(mv)) {
+                    (Some(text), _) => text,
+                    (None, Some(p)) => p.to_string(),
+                    (None, None) => " ".to_owned(),
+                };
+                match style(mv) {
+                    Some((pre, post)) => line += &format!("{} {} {}", pre, mark, post),
+                    None => line += &format!(" {} ", mark),
+                }
+            }
+            line += "|";
+            lines.push(line);
+        }
+        lines.push(horiz);
+        let mut letters = " ".repeat(margin + 1);
+        for col in 0..self.size {
+            letters += &format!(" {}  ", column_letter(col));
+        }
+        lines.push(letters);
+        lines
+    }
+
+    /// Lays the layers out side by side, as many to a row as fit in 80
+    /// columns, each headed with its number.
+    fn render_cube(
+        &self,
+        newline: &str,
+        style: &dyn Fn(Move) -> Option<(&'static str, &'static str)>,
+        label: &dyn Fn(Move) -> Option<String>,
+    ) -> String {
+        const GAP: usize = 2;
+        let width = if self.size >= 10 { 2 } else { 1 } + 4 * self.size + 1;
+        let per_row = ((80 + GAP) / (width + GAP)).max(1);
+        let panels: Vec<Vec<String>> = (0..self.size)
+            .map(|layer| {
+                let mut panel = vec![format!("layer {}", layer + 1)];
+                panel.extend(self.grid(layer, style, label));
+                panel
+            })
+            .collect();
+        let mut rows = Vec::new();
+        for chunk in panels.chunks(per_row) {
+            let lines: Vec<String> = (0..chunk[0].len())
+                .map(|i| {
+                    let mut line = String::new();
+                    for (j, panel) in chunk.iter().enumerate() {
+                        if j > 0 {
+                            // escape codes take no room on screen, so pad by what shows
+                            line += &" ".repeat(width.saturating_sub(visible_width(&chunk[j - 1][i])) + GAP);
+                        }
+                        line += &panel[i];
+                    }
+                    line.trim_end().to_owned()
+                })
+                .collect();
+            rows.push(lines.join(newline));
+        }
+        rows.join(&format!("{}{}", newline, newline))
+    }
+
+    fn ultimate_summary(&self, newline: &str) -> String {
+        let mut out = String::new();
+        let decided: Vec<String> = (0..9)
+            .filter_map(|b| match self.small[b] {
+                Some(GameResult::Win(p)) => Some(format!("{} took the {} board", p, SMALL_BOARD_NAMES[b])),
+                Some(GameResult::Draw) => Some(format!("the {} board is drawn", SMALL_BOARD_NAMES[b])),
+                None => None,
+            })
+            .collect();
+        if !decided.is_empty() {
+            out += newline;
+            out += &decided.join(", ");
+        }
+        if self.result().is_none() {
+            out += newline;
+            out += &match self.forced_board() {
+                Some(b) => format!("{} plays in the {} board.", self.to_move, SMALL_BOARD_NAMES[b]),
+                None => format!("{} may play in any open board.", self.to_move),
+            };
+        }
+        out
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render("\n", &|_| None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ultimate_after(moves: &[&str]) -> Board {
+        let mut board = Board::ultimate();
+        for mv in moves {
+            let mv = crate::notation::parse_move(mv, &board).unwrap();
+            board.play(mv).unwrap();
+        }
+        board
+    }
+
+    #[test]
+    fn ultimate_sends_you_to_the_matching_board() {
+        // e5 is the middle of the middle board, so O must answer there
+        let board = ultimate_after(&["e5"]);
+        assert_eq!(board.forced_board(), Some(4));
+        assert_eq!(board.legal_moves().len(), 8);
+        assert!(matches!(board.check(Move::new(0, 0)), Err(MoveError::Forbidden(_))));
+        // a1 is top-left of its board, so X goes to the top-left board
+        let board = ultimate_after(&["e5", "d4"]);
+        assert_eq!(board.forced_board(), Some(0));
+    }
+
+    #[test]
+    fn decided_boards_free_the_next_player() {
+        // O keeps answering in a corner that sends X back to the top-left
+        // board, and X completes the diagonal there
+        let board = ultimate_after(&["c3", "g7", "b2", "d4"]);
+        assert_eq!(board.forced_board(), Some(0));
+        let board = ultimate_after(&["c3", "g7", "b2", "d4", "a1"]);
+        assert_eq!(board.small_results()[0], Some(GameResult::Win(Player::X)));
+
+        // a1 points O at the board X just took, so O may play anywhere open
+        assert_eq!(board.forced_board(), None);
+        assert!(matches!(board.check(Move::new(2, 0)), Err(MoveError::Forbidden(_))));
+        assert!(board.check(Move::new(4, 4)).is_ok());
+    }
+
+    #[test]
+    fn lines_are_counted_not_listed() {
+        assert_eq!(winning_lines(3, 3, 3, 1).len(), 8);
+        assert_eq!(winning_lines(15, 15, 5, 1).len(), 2 * 11 * 15 + 2 * 11 * 11);
+        // Connect Four: 24 across, 21 down and 12 each way diagonally
+        assert_eq!(Board::gravity(7, 4).lines().len(), 69);
+        assert_eq!(Board::cube(3, 3).lines().len(), 49);
+        // Qubic
+        assert_eq!(Board::cube(4, 4).lines().len(), 76);
+        // (n + 2)^3 - n^3, halved, for any cube where the whole side wins
+        assert_eq!(Board::cube(6, 6).lines().len(), (8 * 8 * 8 - 6 * 6 * 6) / 2);
+    }
+
+    #[test]
+    fn cube_wins_through_the_layers() {
+        // X goes corner to corner of the cube, O stays on the top layer
+        let mut board = Board::cube(4, 4);
+        for i in 0..4 {
+            board.play(Move::layered(i, i, 3 - i)).unwrap();
+            if i < 3 {
+                board.play(Move::layered(0, 3, i)).unwrap();
+            }
+        }
+        assert_eq!(board.result(), Some(GameResult::Win(Player::X)));
+        let line: Vec<String> = board.winning_line().unwrap().iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(line, ["1d1", "2c2", "3b3", "4a4"]);
+        assert_eq!(board.check(Move::new(0, 0)), Err(MoveError::GameOver));
+
+        board.undo();
+        assert_eq!(board.result(), None);
+        assert_eq!(board.check(Move::new(0, 0)), Err(MoveError::NoLayer));
+        assert_eq!(board.legal_moves().len(), 64 - 6);
+    }
+
+    #[test]
+    fn gravity_pieces_drop_and_stack() {
+        let mut board = Board::gravity(7, 4);
+        assert_eq!((board.size(), board.rows()), (7, 6));
+        assert_eq!(board.legal_moves().len(), 7);
+        assert!(board.legal_moves().iter().all(|mv| mv.row == 5));
+        assert!(matches!(board.check(Move::new(0, 3)), Err(MoveError::Forbidden(_))));
+        // X stacks up the d column while O answers beside it
+        for _ in 0..3 {
+            board.play(Move::new(board.landing(3).unwrap(), 3)).unwrap();
+            board.play(Move::new(board.landing(4).unwrap(), 4)).unwrap();
+        }
+        assert_eq!(board.landing(3), Some(2));
+        board.play(Move::new(2, 3)).unwrap();
+        assert_eq!(board.result(), Some(GameResult::Win(Player::X)));
+        let line: Vec<String> = board.winning_line().unwrap().iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(line, ["d3", "d4", "d5", "d6"]);
+        // a full column is out of play
+        board.undo();
+        board.play(Move::new(2, 4)).unwrap();
+        for _ in 0..3 {
+            let row = board.landing(3).unwrap();
+            board.play(Move::new(row, 3)).unwrap();
+        }
+        assert_eq!(board.landing(3), None);
+        assert!(board.legal_moves().iter().all(|mv| mv.col != 3));
+    }
+
+    #[test]
+    fn bitboards_follow_the_cells() {
+        let mut rng = crate::rng::Rng::new(5);
+        for board in &mut [
+            Board::new(9, 4),
+            Board::cube(4, 4),
+            Board::gravity(7, 4),
+            Board::new(3, 3).with_rules(Rules::Wild),
+            Board::new(6, 4).with_players(3),
+        ] {
+            let (empty, opening) = (board.hash(), board.legal_moves());
+            for _ in 0..50 {
+                while board.result().is_none() {
+                    let moves = board.legal_moves();
+                    board.play(moves[rng.below(moves.len())]).unwrap();
+                    let last = board.last_move().unwrap();
+                    assert_eq!(board.makes_line(last), board.run_through(last).is_some());
+                }
+                for p in Player::all(board.players()) {
+                    let cells: Vec<usize> =
+                        (0..board.cells.len()).filter(|&i| board.cell(i) == Some(Piece::Mark(p))).collect();
+                    assert_eq!(board.marks(p).iter().collect::<Vec<_>>(), cells);
+                }
+                while board.undo().is_some() {}
+                assert_eq!(board.hash(), empty);
+                assert_eq!(board.legal_moves(), opening);
+            }
+        }
+    }
+
+    #[test]
+    fn three_players_take_turns() {
+        let mut board = Board::new(4, 3).with_players(3);
+        let y = Player::nth(2);
+        // X, O and Y each take a row, and Y gets there first
+        for mv in &["a1", "a2", "a3", "b1", "b2", "b3", "d4", "d3"] {
+            board.play(crate::notation::parse_move(mv, &board).unwrap()).unwrap();
+        }
+        assert_eq!(board.to_move(), y);
+        assert_eq!(board.get(2, 0), Some(Piece::Mark(y)));
+        board.play(crate::notation::parse_move("c3", &board).unwrap()).unwrap();
+        assert_eq!(board.result(), Some(GameResult::Win(y)));
+        board.undo();
+        assert_eq!(board.to_move(), y);
+        assert_eq!(y.next(3), Player::X);
+        assert_eq!(Player::X.previous(3), y);
+        assert!(Board::check_settings(Variant::Ultimate, 9, 3, Rules::Standard, 3).is_err());
+        assert!(Board::check_settings(Variant::Classic, 3, 3, Rules::Misere, 3).is_err());
+        assert!(Board::check_settings(Variant::Classic, 5, 4, Rules::Standard, MAX_PLAYERS + 1).is_err());
+    }
+
+    #[test]
+    fn three_small_boards_in_a_row_win() {
+        let mut board = Board::ultimate();
+        let mut rng = crate::rng::Rng::new(11);
+        for _ in 0..200 {
+            while board.result().is_none() {
+                let moves = board.legal_moves();
+                board.play(moves[rng.below(moves.len())]).unwrap();
+            }
+            if let Some(GameResult::Win(p)) = board.result() {
+                let line = board.winning_line().unwrap();
+                assert_eq!(line.len(), 27);
+                assert!(line.iter().any(|&mv| Some(mv) == board.last_move()));
+                let won = board.small_results().iter().filter(|r| **r == Some(GameResult::Win(p))).count();
+                assert!(won >= 3);
+            }
+            // and undo unwinds everything, small boards included
+            while board.undo().is_some() {}
+            assert!(board.small_results().iter().all(|r| r.is_none()));
+            assert_eq!(board.hash(), Board::ultimate().hash());
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Chess clocks: every player starts with the same time and gets a little
+//! back with each move, only the clock of the player to move runs, and
+//! whoever runs out first loses.
+//!
+//! Time comes from a `TimeSource`, so tests can move it on by hand
+//! instead of sleeping.
+//!
+//! At the full-screen board the clock counts down on screen while the
+//! player chooses, and they lose the moment it runs out. Typing moves a
+//! line at a time, the clocks are shown before each move and a player
+//! who has run out is only caught when they next act, as nothing can
+//! interrupt a line half typed.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::board::{Board, Player};
+
+/// The most moves ahead a computer player saves time for. On big boards
+/// the game rarely lasts as long as the empty squares suggest.
+const PLANNED_MOVES: usize = 20;
+
+/// Where the clocks get the time from. Games can move between threads,
+/// so their clocks have to be able to go with them.
+pub trait TimeSource: Send + Sync {
+    /// Time since some fixed point. Never goes backwards.
+    fn now(&self) -> Duration;
+}
+
+/// The real time, counted from when it was made.
+pub struct WallClock(Instant);
+
+impl WallClock {
+    pub fn new() -> WallClock {
+        WallClock(Instant::now())
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> WallClock {
+        WallClock::new()
+    }
+}
+
+impl TimeSource for WallClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// Time that only passes when it's told to. Clones share the same time,
+/// so a test can keep one and hand another to the clocks.
+#[derive(Clone, Default)]
+pub struct ManualTime(Arc<AtomicU64>);
+
+impl ManualTime {
+    pub fn new() -> ManualTime {
+        ManualTime::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.0.fetch_add(by.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::SeqCst))
+    }
+}
+
+/// How much time everyone starts with, and how much each move earns back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    /// Seconds, then optionally `+` and the increment in seconds: `60`,
+    /// `300+5` or `10+0.5`.
+    fn from_str(s: &str) -> Result<TimeControl, String> {
+        let seconds = |t: &str| {
+            t.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|t| t.is_finite() && *t >= 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| format!("{} isn't a number of seconds", t))
+        };
+        let (base, increment) = match s.split_once('+') {
+            Some((base, increment)) => (seconds(base)?, seconds(increment)?),
+            None => (seconds(s)?, Duration::ZERO),
+        };
+        if base.is_zero() {
+            return Err("a clock needs some time on it".to_owned());
+        }
+        Ok(TimeControl { base, increment })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}+{}", self.base.as_secs_f64(), self.increment.as_secs_f64())
+    }
+}
+
+/// One clock for each player, at most one of them running.
+#[derive(Clone)]
+pub struct Clock {
+    control: TimeControl,
+    /// Left on each clock when it was last stopped.
+    remaining: Vec<Duration>,
+    /// Whose clock is running, and when it was started.
+    running: Option<(Player, Duration)>,
+    source: Arc<dyn TimeSource>,
+}
+
+impl Clock {
+    /// Clocks for `players`, all stopped with the base time on them.
+    pub fn new(control: TimeControl, players: usize, source: Arc<dyn TimeSource>) -> Clock {
+        Clock {
+            control,
+            remaining: vec![control.base; players],
+            running: None,
+            source,
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    /// Whose clock is running, if anyone's.
+    pub fn running(&self) -> Option<Player> {
+        self.running.map(|(p, _)| p)
+    }
+
+    /// Starts `player`'s clock, stopping anyone else's without the
+    /// increment. Does nothing if it's already running.
+    pub fn start(&mut self, player: Player) {
+        if self.running() == Some(player) {
+            return;
+        }
+        if let Some((other, _)) = self.running {
+            self.remaining[other.index()] = self.remaining(other);
+        }
+        self.running = Some((player, self.source.now()));
+    }
+
+    /// Stops the running clock after a move and adds the increment.
+    /// Returns `false`, leaving no time on it, if it had already run out.
+    pub fn stop(&mut self) -> bool {
+        let player = match self.running() {
+            Some(p) => p,
+            None => return true,
+        };
+        let left = self.remaining(player);
+        self.running = None;
+        self.remaining[player.index()] = left;
+        if left.is_zero() {
+            return false;
+        }
+        self.remaining[player.index()] += self.control.increment;
+        true
+    }
+
+    /// Time left on `player`'s clock, counting down while it runs.
+    pub fn remaining(&self, player: Player) -> Duration {
+        let left = self.remaining[player.index()];
+        match self.running {
+            Some((p, started)) if p == player => left.saturating_sub(self.source.now().saturating_sub(started)),
+            _ => left,
+        }
+    }
+
+    /// The player whose clock is running and has run out.
+    pub fn flagged(&self) -> Option<Player> {
+        self.running().filter(|&p| self.remaining(p).is_zero())
+    }
+
+    /// How long the player to move on `board` should think, if it's the
+    /// computer: an even share of what's left over the moves it may
+    /// still have to make, plus most of the increment, and never more
+    /// than half of what's left.
+    pub fn budget(&self, board: &Board) -> Duration {
+        let player = board.to_move();
+        let left = self.remaining(player);
+        let empty = board.squares() - board.moves_played();
+        let moves = (empty / board.players()).clamp(1, PLANNED_MOVES) as u32;
+        let share = left / moves + self.control.increment * 3 / 4;
+        share.min(left / 2)
+    }
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Clock({})", self)
+    }
+}
+
+impl fmt::Display for Clock {
+    /// Every player's time left, `X 0:58.2  O 1:00.0`, with the one
+    /// running marked.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let clocks: Vec<String> = Player::all(self.remaining.len())
+            .map(|p| {
+                let marker = if self.running() == Some(p) { "*" } else { "" };
+                format!("{} {}{}", p, minutes(self.remaining(p)), marker)
+            })
+            .collect();
+        write!(f, "{}", clocks.join("  "))
+    }
+}
+
+/// `1:05.3`: minutes, seconds and tenths, to the nearest tenth.
+fn minutes(time: Duration) -> String {
+    let tenths = (time.as_millis() + 50) / 100;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: f64) -> Duration {
+        Duration::from_secs_f64(s)
+    }
+
+    fn clock(control: &str) -> (Clock, ManualTime) {
+        let time = ManualTime::new();
+        (Clock::new(control.parse().unwrap(), 2, Arc::new(time.clone())), time)
+    }
+
+    #[test]
+    fn reads_time_controls() {
+        assert_eq!("300+5".parse(), Ok(TimeControl { base: secs(300.0), increment: secs(5.0) }));
+        assert_eq!("10+0.5".parse::<TimeControl>().unwrap().increment, secs(0.5));
+        assert_eq!("60".parse::<TimeControl>().unwrap().increment, Duration::ZERO);
+        assert!("0+5".parse::<TimeControl>().is_err());
+        assert!("ten".parse::<TimeControl>().is_err());
+        assert!("60+-1".parse::<TimeControl>().is_err());
+    }
+
+    #[test]
+    fn only_the_player_to_move_loses_time() {
+        let (mut clock, time) = clock("60+2");
+        clock.start(Player::X);
+        time.advance(secs(10.0));
+        assert_eq!(clock.remaining(Player::X), secs(50.0));
+        assert_eq!(clock.to_string(), "X 0:50.0*  O 1:00.0");
+        assert!(clock.stop());
+        assert_eq!(clock.remaining(Player::X), secs(52.0));
+        // nobody's clock runs between moves
+        time.advance(secs(5.0));
+        clock.start(Player::O);
+        clock.start(Player::O);
+        time.advance(secs(1.5));
+        assert!(clock.stop());
+        assert_eq!((clock.remaining(Player::X), clock.remaining(Player::O)), (secs(52.0), secs(60.5)));
+    }
+
+    #[test]
+    fn running_out_loses() {
+        let (mut clock, time) = clock("5+1");
+        clock.start(Player::X);
+        time.advance(secs(4.9));
+        assert_eq!(clock.flagged(), None);
+        time.advance(secs(0.2));
+        assert_eq!(clock.flagged(), Some(Player::X));
+        assert!(!clock.stop());
+        // no increment for a move made too late
+        assert_eq!(clock.remaining(Player::X), Duration::ZERO);
+        assert_eq!(clock.flagged(), None);
+    }
+
+    #[test]
+    fn computers_share_out_their_time() {
+        let (mut clock, time) = clock("10+1");
+        let board = Board::new(3, 3);
+        // four moves each still to come on 3x3
+        assert_eq!(clock.budget(&board), secs(10.0 / 4.0 + 0.75));
+        clock.start(Player::X);
+        time.advance(secs(9.0));
+        // but never so much it could flag
+        assert_eq!(clock.budget(&board), secs(0.5));
+        assert!(clock.budget(&Board::new(15, 5)) <= secs(1.0 / 20.0 + 0.75));
+    }
+}
@@ -0,0 +1,471 @@
+//! The engine on its own, driven over stdin and stdout by scripts, other
+//! front-ends and tournaments, in the spirit of chess's UCI.
+//!
+//! One command per line; every command gets exactly one final reply,
+//! possibly after some `id` or `info` lines:
+//!
+//! ```text
+//! engine                     who's there: id lines, then ok
+//! newgame [size=N] [k=K] [variant=V] [rules=R] [players=P]
+//!                            an empty board, with the same defaults as xo
+//! position [moves <m>...]    the moves since the empty board, e.g.
+//!                            position moves b2 a1
+//! go [movetime MS] [depth D] search the position: info depth D nodes N
+//!                            eval E, then bestmove <move> score <s>
+//! choose <choice>...         in a swap2 opening, pick one of x, o and
+//!                            two for the position: choice <choice>
+//! isready                    readyok once everything before is done
+//! quit
+//! ```
+//!
+//! Moves are written as in the game (`b2`, `2b3`, `b2=O`); on a gravity
+//! board a column on its own will do. The score is +1 when the side to
+//! move can force a win, -1 when it can't avoid a loss and +0 otherwise;
+//! `eval` is the search's own number behind it. Both are written with
+//! their sign, +0 included. Anything that goes wrong is answered with
+//! `error <why>` and leaves the position as it was.
+//!
+//! `External` is the other end: a `Strategy` that runs a program speaking
+//! this protocol, or connects to one listening on the network, so outside
+//! bots can play in tournaments and take seats in games.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::board::{Board, Move, Variant};
+use crate::minimax::{Minimax, WIN};
+use crate::notation::parse_move;
+use crate::opening::Choice;
+use crate::rules::Rules;
+use crate::strategy::Strategy;
+
+/// The position the engine has been given.
+pub struct Engine {
+    /// The empty board of the current game.
+    start: Board,
+    board: Board,
+}
+
+impl Default for Engine {
+    fn default() -> Engine {
+        Engine::new()
+    }
+}
+
+impl Engine {
+    /// Ready to play classic 3x3 until told otherwise.
+    pub fn new() -> Engine {
+        let start = Board::new(3, 3);
+        Engine {
+            board: start.clone(),
+            start,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Carries out one command and returns the lines to send back, or
+    /// `None` for `quit`.
+    pub fn handle(&mut self, line: &str) -> Option<Vec<String>> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        let reply = match command {
+            "" => return Some(Vec::new()),
+            "engine" => Ok(vec![
+                format!("id name xo {}", env!("CARGO_PKG_VERSION")),
+                "ok".to_owned(),
+            ]),
+            "newgame" => self.newgame(&args).map(|()| vec!["ok".to_owned()]),
+            "position" => self.position(&args).map(|()| vec!["ok".to_owned()]),
+            "go" => self.go(&args),
+            "choose" => self.choose(&args),
+            "isready" => Ok(vec!["readyok".to_owned()]),
+            "quit" => return None,
+            other => Err(format!("unknown command {}", other)),
+        };
+        Some(reply.unwrap_or_else(|e| vec![format!("error {}", e)]))
+    }
+
+    fn newgame(&mut self, args: &[&str]) -> Result<(), String> {
+        let (mut variant, mut rules, mut size, mut k) = (Variant::Classic, Rules::Standard, None, None);
+        let mut players = 2;
+        for arg in args {
+            let (key, value) = arg.split_once('=').ok_or_else(|| format!("{} should be key=value", arg))?;
+            let number = || value.parse().map_err(|_| format!("{} needs a number", key));
+            match key {
+                "size" => size = Some(number()?),
+                "k" => k = Some(number()?),
+                "players" => players = number()?,
+                "variant" => variant = Variant::from_name(value).ok_or_else(|| format!("unknown variant {}", value))?,
+                "rules" => rules = Rules::from_name(value).ok_or_else(|| format!("unknown rules {}", value))?,
+                _ => return Err(format!("unknown setting {}", key)),
+            }
+        }
+        let size = size.unwrap_or_else(|| variant.default_size());
+        let k = k.unwrap_or_else(|| variant.default_k(size));
+        Board::check_settings(variant, size, k, rules, players)?;
+        self.start = Board::with_variant(variant, size, k).with_rules(rules).with_players(players);
+        self.board = self.start.clone();
+        Ok(())
+    }
+
+    fn position(&mut self, args: &[&str]) -> Result<(), String> {
+        let moves = match args.split_first() {
+            None => &[][..],
+            Some((&"moves", moves)) => moves,
+            Some((other, _)) => return Err(format!("expected moves, got {}", other)),
+        };
+        let mut board = self.start.clone();
+        for mv in moves {
+            let parsed = parse_move(mv, &board).map_err(|e| format!("can't play {}: {}", mv, e))?;
+            board.play(parsed).map_err(|e| format!("can't play {}: {}", mv, e))?;
+        }
+        self.board = board;
+        Ok(())
+    }
+
+    fn go(&mut self, args: &[&str]) -> Result<Vec<String>, String> {
+        if let Some(result) = self.board.result() {
+            return Err(format!("the game is over: {}", result));
+        }
+        let mut engine = Minimax::for_board(&self.board);
+        for pair in args.chunks(2) {
+            let value = pair.get(1).and_then(|v| v.parse::<u64>().ok());
+            engine = match (pair[0], value) {
+                ("movetime", Some(ms)) => Minimax::timed(Duration::from_millis(ms)),
+                ("depth", Some(d)) => Minimax::with_depth(d as u32),
+                (option, _) => return Err(format!("go {} needs a number", option)),
+            };
+        }
+        let result = engine.search(&self.board);
+        let best = result.best.ok_or("no move found")?;
+        let score = if result.score > WIN / 2 {
+            1
+        } else if result.score < -WIN / 2 {
+            -1
+        } else {
+            0
+        };
+        Ok(vec![
+            format!("info depth {} nodes {} eval {:+}", result.depth, result.nodes, result.score),
+            format!("bestmove {} score {:+}", best, score),
+        ])
+    }
+
+    /// Takes the side a search of the position likes better, as long as
+    /// it's one of the choices.
+    fn choose(&mut self, args: &[&str]) -> Result<Vec<String>, String> {
+        let choices = args
+            .iter()
+            .map(|a| Choice::from_name(a).ok_or_else(|| format!("unknown choice {}", a)))
+            .collect::<Result<Vec<Choice>, String>>()?;
+        let choice = Minimax::for_board(&self.board).choose_swap(&self.board, &choices).ok_or("no choice made")?;
+        if !choices.contains(&choice) {
+            return Err(format!("can't choose between {}", args.join(" ")));
+        }
+        Ok(vec![format!("choice {}", choice.name())])
+    }
+}
+
+/// Reads commands from `input` until `quit` or the end, answering each on
+/// `output`.
+pub fn serve(input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut engine = Engine::new();
+    for line in input.lines() {
+        match engine.handle(&line?) {
+            Some(reply) => {
+                for line in reply {
+                    writeln!(output, "{}", line)?;
+                }
+                output.flush()?;
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Serves everyone who connects to `listener`, each in a thread of their
+/// own with an engine of their own, as `xo engine --port` does.
+pub fn listen(listener: &TcpListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || {
+            if let Ok(input) = stream.try_clone() {
+                let _ = serve(BufReader::new(input), stream);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// A program that speaks the engine protocol, playing as a `Strategy`.
+/// It's started (or connected to) on its first move and sent the whole
+/// game every turn, so it needn't remember anything between moves.
+pub struct External {
+    /// The command that starts it, or the address it listens on.
+    target: String,
+    remote: bool,
+    process: Option<Process>,
+    last_score: Option<String>,
+    /// Sent with `go` when the game is against the clock.
+    movetime: Option<Duration>,
+}
+
+struct Process {
+    /// `None` over the network.
+    child: Option<Child>,
+    input: Box<dyn Write>,
+    output: Box<dyn BufRead>,
+}
+
+impl External {
+    /// `command` is the program and its arguments, split on spaces.
+    pub fn new(command: &str) -> External {
+        External {
+            target: command.to_owned(),
+            remote: false,
+            process: None,
+            last_score: None,
+            movetime: None,
+        }
+    }
+
+    /// An engine listening at `addr` (`HOST:PORT`), such as `xo engine --port`.
+    pub fn connect(addr: &str) -> External {
+        External {
+            target: addr.to_owned(),
+            remote: true,
+            process: None,
+            last_score: None,
+            movetime: None,
+        }
+    }
+
+    fn start(&mut self) -> io::Result<&mut Process> {
+        if self.process.is_none() {
+            self.process = Some(if self.remote {
+                let stream = TcpStream::connect(self.target.as_str())?;
+                let output = BufReader::new(stream.try_clone()?);
+                Process {
+                    child: None,
+                    input: Box::new(stream),
+                    output: Box::new(output),
+                }
+            } else {
+                let mut words = self.target.split_whitespace();
+                let program = words.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no command"))?;
+                let mut child = Command::new(program)
+                    .args(words)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()?;
+                let input = child.stdin.take().expect("stdin is piped");
+                let output = BufReader::new(child.stdout.take().expect("stdout is piped"));
+                Process {
+                    child: Some(child),
+                    input: Box::new(input),
+                    output: Box::new(output),
+                }
+            });
+        }
+        Ok(self.process.as_mut().expect("just started"))
+    }
+
+    /// Sends the game so far, ready for a question about it.
+    fn send(&mut self, board: &Board) -> Result<&mut Process, String> {
+        let process = self.start().map_err(|e| e.to_string())?;
+        let moves: Vec<String> = board.moves().iter().map(|mv| mv.to_string()).collect();
+        let mut setup = format!(
+            "newgame size={} k={} variant={} rules={}",
+            board.size(),
+            board.k(),
+            board.variant().name(),
+            board.rules().name()
+        );
+        // left out for two, so engines from before it was a setting still work
+        if board.players() != 2 {
+            setup += &format!(" players={}", board.players());
+        }
+        process.command(&setup)?;
+        process.command(&format!("position moves {}", moves.join(" ")))?;
+        Ok(process)
+    }
+
+    /// Sends the game so far and asks for a move.
+    fn ask(&mut self, board: &Board) -> Result<Move, String> {
+        let go = match self.movetime {
+            Some(time) => format!("go movetime {}", time.as_millis()),
+            None => "go".to_owned(),
+        };
+        let reply = self.send(board)?.command(&go)?;
+        let mut words = reply.split_whitespace();
+        let mv = match (words.next(), words.next()) {
+            (Some("bestmove"), Some(mv)) => parse_move(mv, board).map_err(|e| format!("bad move {}: {}", mv, e))?,
+            _ => return Err(format!("expected bestmove, got {}", reply)),
+        };
+        self.last_score = match (words.next(), words.next()) {
+            (Some("score"), Some(score)) => Some(score.to_owned()),
+            _ => None,
+        };
+        Ok(mv)
+    }
+}
+
+impl Process {
+    /// Sends one command and returns the final reply, skipping `id` and
+    /// `info` lines.
+    fn command(&mut self, line: &str) -> Result<String, String> {
+        writeln!(self.input, "{}", line).and_then(|()| self.input.flush()).map_err(|e| e.to_string())?;
+        loop {
+            let mut reply = String::new();
+            if self.output.read_line(&mut reply).map_err(|e| e.to_string())? == 0 {
+                return Err("the engine exited".to_owned());
+            }
+            let reply = reply.trim();
+            if reply.starts_with("error") {
+                return Err(format!("{} (after {})", reply, line));
+            }
+            if !reply.is_empty() && !reply.starts_with("info") && !reply.starts_with("id ") {
+                return Ok(reply.to_owned());
+            }
+        }
+    }
+}
+
+impl Drop for External {
+    fn drop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            let _ = writeln!(process.input, "quit");
+            let _ = process.input.flush();
+            drop(process.input);
+            if let Some(mut child) = process.child {
+                let _ = child.wait();
+            }
+        }
+    }
+}
+
+impl Strategy for External {
+    fn name(&self) -> String {
+        self.target.clone()
+    }
+
+    /// Gives up if the program can't be run, stops answering or answers
+    /// nonsense, saying why on stderr.
+    fn choose_move(&mut self, board: &Board) -> Option<Move> {
+        match self.ask(board) {
+            Ok(mv) => Some(mv),
+            Err(e) => {
+                eprintln!("xo: {}: {}", self.target, e);
+                None
+            }
+        }
+    }
+
+    /// Gives up, like `choose_move`, if the program can't be asked or
+    /// picks something that isn't on offer.
+    fn choose_swap(&mut self, board: &Board, choices: &[Choice]) -> Option<Choice> {
+        let names: Vec<&str> = choices.iter().map(|c| c.name()).collect();
+        let asked = self.send(board).and_then(|process| process.command(&format!("choose {}", names.join(" "))));
+        let choice = asked.and_then(|reply| match reply.split_whitespace().collect::<Vec<_>>()[..] {
+            ["choice", name] => Choice::from_name(name)
+                .filter(|c| choices.contains(c))
+                .ok_or_else(|| format!("{} isn't one of the choices", name)),
+            _ => Err(format!("expected choice, got {}", reply)),
+        });
+        match choice {
+            Ok(choice) => Some(choice),
+            Err(e) => {
+                eprintln!("xo: {}: {}", self.target, e);
+                None
+            }
+        }
+    }
+
+    fn comment(&self) -> Option<String> {
+        self.last_score.as_ref().map(|s| format!("score {}", s))
+    }
+
+    fn think_for(&mut self, time: Duration) {
+        self.movetime = Some(time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(engine: &mut Engine, line: &str) -> Vec<String> {
+        engine.handle(line).expect("not quit")
+    }
+
+    #[test]
+    fn plays_the_position_it_is_given() {
+        let mut engine = Engine::new();
+        assert_eq!(reply(&mut engine, "newgame size=3 k=3"), ["ok"]);
+        assert_eq!(reply(&mut engine, "position moves b2 a1 c1 c3"), ["ok"]);
+        // X has b2 and c1, so a3 wins at once
+        let go = reply(&mut engine, "go");
+        assert!(go[0].starts_with("info depth"));
+        assert_eq!(go[1], "bestmove a3 score +1");
+        assert_eq!(reply(&mut engine, "position"), ["ok"]);
+        assert_eq!(reply(&mut engine, "go depth 2").len(), 2);
+        assert_eq!(reply(&mut engine, "isready"), ["readyok"]);
+        assert_eq!(engine.handle("quit"), None);
+    }
+
+    #[test]
+    fn other_games_and_time_limits() {
+        let mut engine = Engine::new();
+        assert_eq!(reply(&mut engine, "newgame variant=gravity"), ["ok"]);
+        assert_eq!((engine.board().size(), engine.board().k()), (7, 4));
+        reply(&mut engine, "position moves d a d b d");
+        // O has to drop on top of X's three
+        assert!(reply(&mut engine, "go movetime 50")[1].starts_with("bestmove d3 score"));
+        assert_eq!(reply(&mut engine, "newgame size=5 k=3 players=3"), ["ok"]);
+        reply(&mut engine, "position moves a1 e5 c5 b1");
+        // Y has to stop X's c1, or X wins before Y moves again
+        assert_eq!(reply(&mut engine, "go depth 3")[1], "bestmove c1 score +0");
+        // O's stone is off in the corner, out of the way of X's two
+        reply(&mut engine, "newgame size=15 k=5 rules=renju");
+        reply(&mut engine, "position moves h8 a1 i8");
+        assert_eq!(reply(&mut engine, "choose x o two"), ["choice x"]);
+        assert!(reply(&mut engine, "choose x maybe")[0].starts_with("error unknown choice"));
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_do() {
+        let mut engine = Engine::new();
+        let error = |engine: &mut Engine, line| reply(engine, line)[0].starts_with("error");
+        assert!(error(&mut engine, "castle"));
+        assert!(error(&mut engine, "newgame size=0"));
+        assert!(error(&mut engine, "newgame rules=numerical size=4"));
+        assert!(error(&mut engine, "newgame players=3 variant=ultimate"));
+        assert!(error(&mut engine, "newgame k"));
+        assert!(error(&mut engine, "position moves b2 b2"));
+        assert!(error(&mut engine, "go movetime soon"));
+        reply(&mut engine, "position moves a1 a2 b1 b2 c1");
+        assert!(error(&mut engine, "go"));
+        // the failed commands changed nothing
+        assert_eq!(engine.board().moves().len(), 5);
+    }
+
+    #[test]
+    fn serves_a_whole_session() {
+        let input = "engine\nnewgame\nposition moves b2\nisready\nquit\ngo\n";
+        let mut output = Vec::new();
+        serve(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].starts_with("id name xo"));
+        // nothing after quit
+        assert_eq!(lines[1..], ["ok", "ok", "ok", "readyok"]);
+    }
+}
@@ -0,0 +1,515 @@
+use crate::board::{Board, GameResult, Move, MoveError, Player};
+use crate::clock::Clock;
+use crate::hint;
+use crate::opening::{Opening, Swap2};
+use crate::record::GameRecord;
+use crate::strategy::{Action, Strategy};
+use crate::view::View;
+
+/// Where a game stands. It starts with X to move, goes back and forth
+/// between the players with every move, undo and redo, and stays over
+/// once someone wins, the board is drawn, or a player resigns or runs
+/// out of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    ToMove(Player),
+    Over(GameResult),
+}
+
+/// How a player lost that the board doesn't show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Forfeit {
+    Resigned,
+    Flagged,
+}
+
+/// A game being played: the board plus who is playing it, the moves
+/// that have been taken back, so they can be replayed with `redo`, the
+/// clock if it's played against one, and the opening if it starts with
+/// one.
+#[derive(Clone, Debug)]
+pub struct Game {
+    board: Board,
+    /// One per player, in turn order; under swap2, one per seat, the
+    /// opener first.
+    names: Vec<String>,
+    redo: Vec<Move>,
+    forfeit: Option<(Player, Forfeit)>,
+    clock: Option<Clock>,
+    swap2: Option<Swap2>,
+}
+
+impl Game {
+    /// Panics unless there's a name for every player the board has.
+    pub fn new(board: Board, names: Vec<String>) -> Game {
+        assert_eq!(names.len(), board.players(), "one name per player");
+        Game {
+            board,
+            names,
+            redo: Vec::new(),
+            forfeit: None,
+            clock: None,
+            swap2: None,
+        }
+    }
+
+    /// The same game played against `clock`, which `run` starts and
+    /// stops. There's no taking moves back against the clock. Panics
+    /// unless there are two players, as running out of time leaves more
+    /// than that without a winner, or if there's a swap2 opening, as the
+    /// clocks go by side and the opener plays both sides' stones.
+    pub fn with_clock(self, clock: Clock) -> Game {
+        assert_eq!(self.board.players(), 2, "clocks are for two players");
+        assert!(self.swap2.is_none(), "swap2 isn't played against the clock");
+        Game {
+            clock: Some(clock),
+            ..self
+        }
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
+    /// The same game started with `opening`, which `run` plays through.
+    /// Nothing can be taken back in it. Panics if there's more to it than
+    /// X going first and more than two players, or a clock.
+    pub fn with_opening(self, opening: Opening) -> Game {
+        assert!(opening == Opening::Free || self.board.players() == 2, "openings are for two players");
+        assert!(opening == Opening::Free || self.clock.is_none(), "swap2 isn't played against the clock");
+        Game {
+            swap2: opening.start(),
+            ..self
+        }
+    }
+
+    /// Which of the names given to `new` is playing `player`'s side.
+    /// That's the player's own index but under swap2.
+    fn seat_of(&self, player: Player) -> usize {
+        match &self.swap2 {
+            Some(swap2) => swap2.seat_of(player),
+            None => player.index(),
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn name(&self, player: Player) -> &str {
+        &self.names[self.seat_of(player)]
+    }
+
+    pub fn state(&self) -> State {
+        match self.result() {
+            Some(result) => State::Over(result),
+            None => State::ToMove(self.board.to_move()),
+        }
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        match self.forfeit {
+            Some((p, _)) => Some(GameResult::Win(p.other())),
+            None => self.board.result(),
+        }
+    }
+
+    /// "Ann wins!" or "It's a draw.", once the game is over.
+    pub fn result_text(&self) -> Option<String> {
+        match self.result()? {
+            GameResult::Win(p) if self.forfeit.is_some() => {
+                let how = match self.forfeit {
+                    Some((_, Forfeit::Flagged)) => "ran out of time",
+                    _ => "resigns",
+                };
+                Some(format!("{} {}. {} wins!", self.name(p.other()), how, self.name(p)))
+            }
+            GameResult::Win(p) => Some(format!("{} wins!", self.name(p))),
+            GameResult::Draw => Some("It's a draw.".to_owned()),
+        }
+    }
+
+    /// Ends the game in the other player's favour. Does nothing if it's
+    /// already over, or if there's more than one other player to give it to.
+    pub fn resign(&mut self, player: Player) {
+        if self.result().is_none() && self.board.players() == 2 {
+            self.forfeit = Some((player, Forfeit::Resigned));
+        }
+    }
+
+    /// Plays a new move, which throws away anything that could be redone.
+    pub fn play(&mut self, mv: Move) -> Result<(), MoveError> {
+        if self.forfeit.is_some() {
+            return Err(MoveError::GameOver);
+        }
+        self.board.play(mv)?;
+        self.redo.clear();
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> Option<Move> {
+        if self.forfeit.is_some() {
+            return None;
+        }
+        let mv = self.board.undo()?;
+        self.redo.push(mv);
+        Some(mv)
+    }
+
+    pub fn redo(&mut self) -> Option<Move> {
+        if self.forfeit.is_some() {
+            return None;
+        }
+        let mv = self.redo.pop()?;
+        self.board.play(mv).expect("undone moves can be replayed");
+        Some(mv)
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn record(&self) -> GameRecord {
+        GameRecord {
+            names: Player::all(self.board.players()).map(|p| self.name(p).to_owned()).collect(),
+            variant: self.board.variant(),
+            rules: self.board.rules(),
+            size: self.board.size(),
+            k: self.board.k(),
+            result: self.result(),
+            moves: self.board.moves().to_vec(),
+        }
+    }
+
+    /// Writes the record to `path`, and says how that went.
+    pub fn save(&self, path: &str) -> String {
+        match self.record().save(path) {
+            Ok(()) => format!("Saved the game to {}.", path),
+            Err(e) => format!("Can't write {}: {}.", path, e),
+        }
+    }
+
+    /// Asks `players` (X's first) for moves in turn until the game ends or
+    /// someone quits. Undo and redo skip over the computer's moves, so
+    /// it's the person's turn again afterwards.
+    ///
+    /// Against the clock, the player to move's clock runs until they've
+    /// moved, the computer is told how long to think, a person is handed
+    /// the clock to watch, and anyone found to be out of time when they
+    /// act loses.
+    ///
+    /// Under swap2, `players` and `is_computer` go by seat instead, the
+    /// opener first, and whoever's turn it is to choose is asked to.
+    pub fn run(&mut self, players: &mut [Box<dyn Strategy>], is_computer: &dyn Fn(Player) -> bool, view: &mut dyn View) {
+        while let State::ToMove(side) = self.state() {
+            view.show(self.board());
+            if let Some(swap2) = &mut self.swap2 {
+                if let Some((seat, choices)) = swap2.pending(&self.board) {
+                    let choice = match players[seat].choose_swap(&self.board, choices) {
+                        Some(choice) => choice,
+                        None => break,
+                    };
+                    match swap2.choose(&self.board, choice) {
+                        Ok(()) => view.message(&format!("{} chooses to {}.", self.names[seat], choice)),
+                        Err(e) => view.message(&format!("{} can't choose that: {}.", self.names[seat], e)),
+                    }
+                    continue;
+                }
+            }
+            // who puts the stone down, which in a swap2 opening needn't be who ends up playing it
+            let seat = match &self.swap2 {
+                Some(swap2) => swap2.seat(&self.board),
+                None => side.index(),
+            };
+            let computer = is_computer(Player::nth(seat));
+            let player = &mut players[seat];
+            if let Some(clock) = &mut self.clock {
+                clock.start(side);
+                view.clock(clock);
+                if computer {
+                    player.think_for(clock.budget(&self.board));
+                } else {
+                    player.use_clock(clock);
+                }
+            }
+            let action = player.choose_action(self.board());
+            if let Some(clock) = &mut self.clock {
+                if clock.flagged() == Some(side) {
+                    clock.stop();
+                    self.forfeit = Some((side, Forfeit::Flagged));
+                    break;
+                }
+            }
+            match action {
+                Action::Undo | Action::Redo if self.clock.is_some() => {
+                    view.message("There's no taking moves back against the clock.")
+                }
+                Action::Undo | Action::Redo if self.swap2.is_some() => {
+                    view.message("There's no taking moves back after a swap2 opening.")
+                }
+                Action::Move(mv) => match self.play(mv) {
+                    Ok(()) => {
+                        if let Some(clock) = &mut self.clock {
+                            clock.stop();
+                        }
+                        if computer {
+                            let name = &self.names[seat];
+                            match player.comment() {
+                                Some(c) => view.message(&format!("{} ({}) plays {}: {}.", name, side, mv, c)),
+                                None => view.message(&format!("{} ({}) plays {}.", name, side, mv)),
+                            }
+                        }
+                    }
+                    Err(e) => view.message(&format!("{} can't play {}: {}.", self.names[seat], mv, e)),
+                },
+                // take back the computer's reply too, so it's your turn again
+                Action::Undo => match self.undo() {
+                    Some(_) => {
+                        while is_computer(self.board().to_move()) && self.undo().is_some() {}
+                    }
+                    None => view.message("There is nothing to undo."),
+                },
+                Action::Redo if self.can_redo() => {
+                    self.redo();
+                    while is_computer(self.board().to_move()) && self.redo().is_some() {}
+                }
+                Action::Redo => view.message("There is nothing to redo."),
+                Action::Hint => view.hint(self.board(), &hint::analyse(self.board())),
+                Action::Save(path) => view.message(&self.save(&path)),
+                Action::Chat(_) => view.message("There's nobody to chat with in a local game."),
+                Action::Resign if self.board.players() > 2 => {
+                    view.message("There's nobody to resign to with more than two players; quit instead.")
+                }
+                Action::Resign => self.resign(side),
+                Action::Quit => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{ManualTime, TimeSource};
+    use crate::hint::Analysis;
+    use crate::minimax::Minimax;
+    use crate::opening::Choice;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// Keeps the messages and shows nothing.
+    struct Quiet(Vec<String>);
+
+    impl View for Quiet {
+        fn show(&mut self, _: &Board) {}
+
+        fn message(&mut self, text: &str) {
+            self.0.push(text.to_owned());
+        }
+
+        fn hint(&mut self, _: &Board, _: &Analysis) {}
+
+        fn finish(&mut self, _: &Game) {}
+    }
+
+    #[test]
+    fn state_follows_the_turns_to_the_end() {
+        let mut game = Game::new(Board::new(3, 3), vec!["a".to_owned(), "b".to_owned()]);
+        assert_eq!(game.state(), State::ToMove(Player::X));
+        game.play(Move::new(1, 1)).unwrap();
+        assert_eq!(game.state(), State::ToMove(Player::O));
+        game.undo();
+        assert_eq!(game.state(), State::ToMove(Player::X));
+
+        let mut players: Vec<Box<dyn Strategy>> = vec![Box::new(Minimax::perfect()), Box::new(Minimax::perfect())];
+        let mut view = Quiet(Vec::new());
+        game.run(&mut players, &|_| true, &mut view);
+        assert_eq!(game.state(), State::Over(GameResult::Draw));
+        assert_eq!(view.0.len(), 9);
+        assert!(view.0[0].starts_with("a (X) plays"));
+    }
+
+    /// Takes as long as it's told over every move, then plays the first
+    /// square open, remembering how long it was told it had.
+    struct Ponder {
+        time: ManualTime,
+        thinking: Duration,
+        told: Rc<Cell<Duration>>,
+    }
+
+    impl Strategy for Ponder {
+        fn name(&self) -> String {
+            "Ponder".to_owned()
+        }
+
+        fn choose_move(&mut self, board: &Board) -> Option<Move> {
+            self.time.advance(self.thinking);
+            board.legal_moves().first().copied()
+        }
+
+        fn think_for(&mut self, time: Duration) {
+            self.told.set(time);
+        }
+    }
+
+    #[test]
+    fn slow_players_lose_on_time() {
+        let time = ManualTime::new();
+        let clock = Clock::new("10+1".parse().unwrap(), 2, std::sync::Arc::new(time.clone()));
+        let mut game = Game::new(Board::new(3, 3), vec!["a".to_owned(), "b".to_owned()]).with_clock(clock);
+        let told = Rc::new(Cell::new(Duration::ZERO));
+        let ponder = |secs| Ponder {
+            time: time.clone(),
+            thinking: Duration::from_secs(secs),
+            told: told.clone(),
+        };
+        let mut players: Vec<Box<dyn Strategy>> = vec![Box::new(ponder(1)), Box::new(ponder(5))];
+        let mut view = Quiet(Vec::new());
+        game.run(&mut players, &|_| true, &mut view);
+        // O has 6 seconds left after one move, 2 after two, and then flags
+        assert_eq!(game.result(), Some(GameResult::Win(Player::X)));
+        assert_eq!(game.board().moves_played(), 5);
+        assert_eq!(game.result_text().unwrap(), "b ran out of time. a wins!");
+        let clock = game.clock().unwrap();
+        assert_eq!(clock.remaining(Player::X), Duration::from_secs(10));
+        assert_eq!(clock.remaining(Player::O), Duration::ZERO);
+        // told to take no more than half its last 2 seconds
+        assert_eq!(told.get(), Duration::from_secs(1));
+        assert!(view.0.contains(&"X 0:10.0  O 0:02.0*".to_owned()));
+    }
+
+    /// Watches its clock the way the full-screen board does, a second at
+    /// a time, and gives up when it runs out.
+    struct Dawdle {
+        time: ManualTime,
+        clock: Option<Clock>,
+    }
+
+    impl Strategy for Dawdle {
+        fn name(&self) -> String {
+            "Dawdle".to_owned()
+        }
+
+        fn choose_move(&mut self, _: &Board) -> Option<Move> {
+            let clock = self.clock.as_ref().expect("people are handed the clock");
+            while clock.flagged().is_none() {
+                self.time.advance(Duration::from_secs(1));
+            }
+            None
+        }
+
+        fn use_clock(&mut self, clock: &Clock) {
+            self.clock = Some(clock.clone());
+        }
+    }
+
+    #[test]
+    fn people_flag_while_they_think() {
+        let time = ManualTime::new();
+        let clock = Clock::new("10".parse().unwrap(), 2, std::sync::Arc::new(time.clone()));
+        let mut game = Game::new(Board::new(3, 3), vec!["a".to_owned(), "b".to_owned()]).with_clock(clock);
+        let dawdle = Dawdle {
+            time: time.clone(),
+            clock: None,
+        };
+        let mut players: Vec<Box<dyn Strategy>> = vec![Box::new(dawdle), Box::new(Minimax::perfect())];
+        game.run(&mut players, &|p| p == Player::O, &mut Quiet(Vec::new()));
+        // giving up as the flag falls is losing on time, not quitting
+        assert_eq!(game.result_text().unwrap(), "a ran out of time. b wins!");
+        assert_eq!(time.now(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn three_players_go_round() {
+        let names = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let mut game = Game::new(Board::new(4, 3).with_players(3), names);
+        // nobody to hand the game to
+        game.resign(Player::X);
+        assert_eq!(game.result(), None);
+        let mut players: Vec<Box<dyn Strategy>> = (0..3).map(|_| Box::new(Minimax::with_depth(2)) as Box<dyn Strategy>).collect();
+        let mut view = Quiet(Vec::new());
+        game.run(&mut players, &|_| true, &mut view);
+        assert!(game.result().is_some());
+        assert!(view.0[2].starts_with("c (Y) plays"));
+        assert!(view.0[3].starts_with("a (X) plays"));
+        assert_eq!(game.record().names.len(), 3);
+    }
+
+    /// Plays like a shallow search, but makes the swap2 choice it's told
+    /// to where it can, and takes O where it can't.
+    struct Picky(Choice, Minimax);
+
+    impl Strategy for Picky {
+        fn name(&self) -> String {
+            "Picky".to_owned()
+        }
+
+        fn choose_move(&mut self, board: &Board) -> Option<Move> {
+            self.1.choose_move(board)
+        }
+
+        fn choose_swap(&mut self, _: &Board, choices: &[Choice]) -> Option<Choice> {
+            Some(if choices.contains(&self.0) { self.0 } else { Choice::PlayO })
+        }
+    }
+
+    #[test]
+    fn swap2_settles_who_plays_which_side() {
+        let names = vec!["a".to_owned(), "b".to_owned()];
+        let mut game = Game::new(Board::new(9, 5), names).with_opening(Opening::Swap2);
+        let mut players: Vec<Box<dyn Strategy>> = vec![
+            Box::new(Picky(Choice::PlayO, Minimax::with_depth(1))),
+            Box::new(Picky(Choice::PlaceTwo, Minimax::with_depth(1))),
+        ];
+        let mut view = Quiet(Vec::new());
+        game.run(&mut players, &|_| true, &mut view);
+        let opening: Vec<&str> = view.0[..8].iter().map(|m| &m[..m.find(" plays").unwrap_or(m.len())]).collect();
+        assert_eq!(
+            opening,
+            [
+                "a (X)",
+                "a (O)",
+                "a (X)",
+                "b chooses to put down two more stones.",
+                "b (O)",
+                "b (X)",
+                "a chooses to play O.",
+                "a (O)"
+            ]
+        );
+        assert!(game.result().is_some());
+        assert_eq!((game.name(Player::X), game.name(Player::O)), ("b", "a"));
+        assert_eq!(game.record().names, ["b", "a"]);
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_history() {
+        let mut game = Game::new(Board::new(3, 3), vec!["a".to_owned(), "b".to_owned()]);
+        for &(r, c) in &[(1, 1), (0, 0), (2, 2)] {
+            game.play(Move::new(r, c)).unwrap();
+        }
+        let hash = game.board().hash();
+        assert_eq!(game.undo(), Some(Move::new(2, 2)));
+        assert_eq!(game.undo(), Some(Move::new(0, 0)));
+        assert_eq!(game.board().to_move(), Player::O);
+        assert_eq!(game.redo(), Some(Move::new(0, 0)));
+        assert_eq!(game.redo(), Some(Move::new(2, 2)));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.board().hash(), hash);
+
+        game.undo();
+        game.play(Move::new(0, 2)).unwrap();
+        assert!(!game.can_redo());
+    }
+
+    #[test]
+    fn resigning_ends_the_game() {
+        let mut game = Game::new(Board::new(3, 3), vec!["a".to_owned(), "b".to_owned()]);
+        game.play(Move::new(1, 1)).unwrap();
+        game.resign(Player::O);
+        assert_eq!(game.result(), Some(GameResult::Win(Player::X)));
+        assert_eq!(game.play(Move::new(0, 0)), Err(MoveError::GameOver));
+        assert_eq!(game.record().result, Some(GameResult::Win(Player::X)));
+    }
+}
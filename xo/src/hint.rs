@@ -0,0 +1,205 @@
+//! What the engine makes of every move open to the player, for anyone
+//! who types `hint` and wants to learn rather than just be told a move.
+
+use std::fmt;
+
+use crate::board::{Board, Move};
+use crate::minimax::{candidate_moves, Minimax, WIN};
+
+/// How far ahead a hint looks where the computer plays against the clock:
+/// it has the whole thinking time for one move, a hint has it for all.
+const TIMED_DEPTH: u32 = 6;
+
+/// What one move leads to, for the player making it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// A forced win, this many moves from now counting both sides.
+    Win(usize),
+    /// Only known for certain when the search reached the end.
+    Draw,
+    /// A forced loss, holding out as long as possible.
+    Loss(usize),
+    /// The heuristic's opinion, when the search stopped short.
+    Score(i32),
+}
+
+impl Verdict {
+    /// Higher is better.
+    fn rank(self) -> i64 {
+        match self {
+            Verdict::Win(n) => 2 * WIN as i64 - n as i64,
+            Verdict::Draw => 0,
+            Verdict::Score(s) => s as i64,
+            Verdict::Loss(n) => -2 * WIN as i64 + n as i64,
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::Win(n) => write!(f, "wins in {}", n),
+            Verdict::Draw => write!(f, "draws"),
+            Verdict::Loss(n) => write!(f, "loses in {}", n),
+            Verdict::Score(s) => write!(f, "scores {:+}", s),
+        }
+    }
+}
+
+/// Every move worth considering, best first.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub moves: Vec<(Move, Verdict)>,
+    /// How far ahead the search looked, or `None` if it went to the end
+    /// of the game and every verdict is exact.
+    pub depth: Option<u32>,
+}
+
+/// Searches every legal move (on big boards, every move near the marks
+/// already played) as deep as the computer would.
+pub fn analyse(board: &Board) -> Analysis {
+    let moves = match depth(board) {
+        None => board.legal_moves(),
+        Some(_) => candidate_moves(board, None),
+    };
+    rate(board, moves)
+}
+
+/// Like `analyse`, for just the moves given.
+pub fn rate(board: &Board, moves: Vec<Move>) -> Analysis {
+    let depth = depth(board);
+    // one engine for every reply, so positions they share are searched once
+    let mut engine = match depth {
+        None => Minimax::perfect(),
+        Some(d) => Minimax::with_depth(d.saturating_sub(1)),
+    };
+    let (now, me) = (board.moves_played(), board.to_move());
+    let mut after = board.clone();
+    let mut scored: Vec<(Move, Verdict)> = moves
+        .into_iter()
+        .map(|mv| {
+            after.play(mv).expect("legal moves can be played");
+            let score = engine.search_for(&after, me).score;
+            after.undo();
+            // won scores are WIN less the moves played by the end
+            let plies = (WIN - score.abs()) as usize - now;
+            let verdict = if score > WIN / 2 {
+                Verdict::Win(plies)
+            } else if score < -WIN / 2 {
+                Verdict::Loss(plies)
+            } else if depth.is_none() {
+                Verdict::Draw
+            } else {
+                Verdict::Score(score)
+            };
+            (mv, verdict)
+        })
+        .collect();
+    scored.sort_by_key(|&(_, v)| -v.rank());
+    Analysis { moves: scored, depth }
+}
+
+/// How far ahead to look, or `None` for the end of the game.
+fn depth(board: &Board) -> Option<u32> {
+    let engine = Minimax::for_board(board);
+    match engine.time() {
+        Some(_) => Some(TIMED_DEPTH),
+        None => engine.depth(),
+    }
+}
+
+impl Analysis {
+    /// Every move as good as the best one.
+    pub fn best(&self) -> Vec<Move> {
+        let top = match self.moves.first() {
+            Some(&(_, v)) => v,
+            None => return Vec::new(),
+        };
+        self.moves.iter().filter(|&&(_, v)| v == top).map(|&(mv, _)| mv).collect()
+    }
+
+    /// A letter to show on each square: W, D or L when the search saw the
+    /// result, otherwise the move's place in the ranking, 1 to 9.
+    pub fn label(&self, mv: Move) -> Option<char> {
+        let rank = self.moves.iter().position(|&(m, _)| m.with_piece(None) == mv)?;
+        match self.moves[rank].1 {
+            Verdict::Win(_) => Some('W'),
+            Verdict::Draw => Some('D'),
+            Verdict::Loss(_) => Some('L'),
+            Verdict::Score(_) if rank < 9 => std::char::from_digit(rank as u32 + 1, 10),
+            Verdict::Score(_) => None,
+        }
+    }
+
+    /// One line: the best moves and what they lead to.
+    pub fn summary(&self) -> String {
+        let mut best: Vec<String> = self.best().iter().map(|mv| mv.to_string()).collect();
+        let last = best.pop().unwrap_or_default();
+        let moves = if best.is_empty() { last } else { format!("{} or {}", best.join(", "), last) };
+        match self.moves.first() {
+            Some(&(_, verdict)) => format!("Hint: {} {} ({}).", moves, verdict, self.reach()),
+            None => "Hint: there are no moves left.".to_owned(),
+        }
+    }
+
+    fn reach(&self) -> String {
+        match self.depth {
+            None => "perfect play".to_owned(),
+            Some(d) => format!("looking {} moves ahead", d),
+        }
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Every move, best first ({}):", self.reach())?;
+        for (mv, verdict) in &self.moves {
+            writeln!(f, "  {:<6}{}", mv.to_string(), verdict)?;
+        }
+        write!(f, "{}", self.summary())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+
+    fn after(board: Board, moves: &[&str]) -> Board {
+        let mut board = board;
+        for mv in moves {
+            let mv = crate::notation::parse_move(mv, &board).unwrap();
+            board.play(mv).unwrap();
+        }
+        board
+    }
+
+    #[test]
+    fn exact_on_3x3() {
+        // X has a1 and b1 against O's a2 and b2: c1 wins at once, c2
+        // blocks O, and anything else lets O win on c2 next move
+        let hints = analyse(&after(Board::new(3, 3), &["a1", "a2", "b1", "b2"]));
+        assert_eq!(hints.depth, None);
+        assert_eq!(hints.moves[0], (Move::new(0, 2), Verdict::Win(1)));
+        assert_eq!(hints.best(), [Move::new(0, 2)]);
+        assert_eq!(hints.moves[1], (Move::new(1, 2), Verdict::Draw));
+        assert!(hints.moves[2..].iter().all(|&(_, v)| v == Verdict::Loss(2)));
+        assert_eq!(hints.label(Move::new(1, 2)), Some('D'));
+        assert_eq!(hints.label(Move::new(2, 2)), Some('L'));
+        assert_eq!(hints.label(Move::new(0, 0)), None);
+
+        let hints = analyse(&Board::new(3, 3));
+        assert!(hints.moves.iter().all(|&(_, v)| v == Verdict::Draw));
+        let hints = analyse(&after(Board::new(3, 3).with_rules(Rules::Wild), &[]));
+        assert!(matches!(hints.moves[0].1, Verdict::Win(_)));
+    }
+
+    #[test]
+    fn heuristic_on_big_boards() {
+        let hints = analyse(&after(Board::new(9, 5), &["e5", "a1"]));
+        assert_eq!(hints.depth, Some(3));
+        assert!(hints.moves.iter().all(|&(_, v)| matches!(v, Verdict::Score(_))));
+        assert_eq!(hints.label(hints.moves[0].0), Some('1'));
+        assert!(hints.summary().contains("looking 3 moves ahead"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Just enough HTTP/1.1 to serve the game API and its page: one request
+//! per connection, bodies only as long as `Content-Length` says, and
+//! form-encoded parameters rather than JSON going in.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+/// Longest request line or header, and most headers, taken from a client.
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+
+/// Longest body taken. Parameters for a game are a few dozen bytes.
+const MAX_BODY: usize = 64 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// Without the query string.
+    pub path: String,
+    pub query: String,
+    /// Names in lower case.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Reads one request, or says what was wrong with it.
+    pub fn read(input: &mut dyn BufRead) -> io::Result<Request> {
+        let line = read_line(input)?;
+        let mut parts = line.split(' ');
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => (method, target),
+            _ => return Err(invalid("bad request line")),
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(input)?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(invalid("too many headers"));
+            }
+            let (name, value) = line.split_once(':').ok_or_else(|| invalid("bad header"))?;
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+        }
+        let mut request = Request {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            query: query.to_owned(),
+            headers,
+            body: Vec::new(),
+        };
+        let len = match request.header("content-length") {
+            Some(len) => len.parse().map_err(|_| invalid("bad content length"))?,
+            None => 0,
+        };
+        if len > MAX_BODY {
+            return Err(invalid("the body is too long"));
+        }
+        request.body = vec![0; len];
+        input.read_exact(&mut request.body)?;
+        Ok(request)
+    }
+
+    /// The first header called `name`, which has to be in lower case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Whether a header lists `token` among its comma-separated values,
+    /// ignoring case, as `Connection: keep-alive, Upgrade` does `upgrade`.
+    pub fn header_has(&self, name: &str, token: &str) -> bool {
+        self.header(name).is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    }
+
+    /// Parameters from the query string and a form-encoded body; the
+    /// body's win when a name is in both.
+    pub fn params(&self) -> HashMap<String, String> {
+        let mut params = parse_form(&self.query);
+        params.extend(parse_form(&String::from_utf8_lossy(&self.body)));
+        params
+    }
+}
+
+fn read_line(input: &mut dyn BufRead) -> io::Result<String> {
+    let mut line = Vec::new();
+    input.take(MAX_LINE as u64 + 1).read_until(b'\n', &mut line)?;
+    if line.last() != Some(&b'\n') {
+        return Err(if line.len() > MAX_LINE { invalid("the line is too long") } else { eof() });
+    }
+    let line = String::from_utf8(line).map_err(|_| invalid("the request isn't UTF-8"))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+fn invalid(text: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, text.to_owned())
+}
+
+fn eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "the connection closed mid-request")
+}
+
+/// `name=Ann+Lee&size=3` as a map, with `+` and `%XX` decoded.
+pub fn parse_form(form: &str) -> HashMap<String, String> {
+    form.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect()
+}
+
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'+', _) => out.push(b' '),
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 2;
+            }
+            (b, _) => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// `s` as a JSON string, quotes and all.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// JSON values, already written out, as a JSON array.
+pub fn json_list(items: impl Iterator<Item = String>) -> String {
+    format!("[{}]", items.collect::<Vec<_>>().join(","))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, body: String) -> Response {
+        Response { status, content_type: "application/json", body: body.into_bytes() }
+    }
+
+    /// `{"error": text}`, for a request that couldn't be done.
+    pub fn error(status: u16, text: &str) -> Response {
+        Response::json(status, format!("{{\"error\":{}}}", json_string(text)))
+    }
+
+    pub fn html(body: &str) -> Response {
+        Response { status: 200, content_type: "text/html; charset=utf-8", body: body.as_bytes().to_vec() }
+    }
+
+    /// Sends it, and says the connection closes afterwards.
+    pub fn write_to(&self, output: &mut dyn Write) -> io::Result<()> {
+        write!(
+            output,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        )?;
+        output.write_all(&self.body)?;
+        output.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_requests() {
+        let raw = "POST /games/3/moves?move=a1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 21\r\n\r\nmove=b%322&name=Ann+B";
+        let request = Request::read(&mut raw.as_bytes()).unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/games/3/moves"));
+        assert_eq!(request.header("host"), Some("localhost"));
+        let params = request.params();
+        assert_eq!((params["move"].as_str(), params["name"].as_str()), ("b22", "Ann B"));
+        assert!(Request::read(&mut "GET /\r\n\r\n".as_bytes()).is_err());
+        let short = "POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nab";
+        assert_eq!(Request::read(&mut short.as_bytes()).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn escapes_json() {
+        assert_eq!(json_string("Ann \"The\" \\ \u{1}"), r#""Ann \"The\" \\ \u0001""#);
+    }
+}
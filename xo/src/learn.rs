@@ -0,0 +1,336 @@
+//! A player that learns classic 3x3 by playing itself, the way Donald
+//! Michie's MENACE did with matchboxes and beads in 1961.
+//!
+//! Every position has a box holding beads for each empty square, more of
+//! them early in the game. To move, a bead is drawn at random and played.
+//! Once the game is over, every box that was drawn from gets beads back
+//! for the moves that were made: three more for a win, one more for a
+//! draw, one taken away for a loss. Good moves get drawn more and more
+//! often, bad ones less, though never not at all.
+//!
+//! Positions share a box with their rotations and reflections, filed
+//! under the same key as the solved table's (see `solved`), so what it
+//! learns in one corner holds for all four.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use crate::baseline::RandomPlayer;
+use crate::board::{Board, GameResult, Move, Player};
+use crate::rng::Rng;
+use crate::solved::{self, Database, Oracle};
+use crate::strategy::Strategy;
+
+/// Start of a saved table, so any other file is refused.
+const MAGIC: &[u8; 4] = b"XOM\x01";
+
+/// Bytes per box: key (2), then beads for each of the nine squares (2 each).
+const BOX_BYTES: usize = 2 + 9 * 2;
+
+/// Beads per empty square in a new box for each player's first move,
+/// then one fewer for each move after, but never none.
+const FIRST_BEADS: u16 = 4;
+
+/// Beads given back for each move made in a game that was won, or drawn,
+/// and taken away after a loss.
+const WIN_BEADS: u16 = 3;
+const DRAW_BEADS: u16 = 1;
+const LOSS_BEADS: u16 = 1;
+
+/// Games against each opponent every time progress is measured, half
+/// of them going first.
+const CHECKPOINT_GAMES: u32 = 200;
+
+/// Bead boxes for every position met so far, and the hand that draws
+/// from them.
+#[derive(Clone, Debug)]
+pub struct Menace {
+    /// Beads for each square, in the orientation the key reads the
+    /// position in; none on squares that are taken.
+    boxes: HashMap<u16, [u16; 9]>,
+    symmetries: Vec<Vec<usize>>,
+    rng: Rng,
+}
+
+/// How one stretch of training ended up: wins, draws and losses, in that
+/// order, against a random player and against perfect play.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub episodes: u32,
+    pub random: [u32; 3],
+    pub perfect: [u32; 3],
+}
+
+/// The checkpoints of a training run, in order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Curve {
+    pub points: Vec<Checkpoint>,
+}
+
+impl Menace {
+    /// No boxes yet: every position gets one when first seen.
+    pub fn new(rng: Rng) -> Menace {
+        Menace { boxes: HashMap::new(), symmetries: Board::new(3, 3).symmetries(), rng }
+    }
+
+    /// Whether `board` is a game it can play: classic 3x3, as for the
+    /// solved table.
+    pub fn covers(board: &Board) -> bool {
+        Database::covers(board)
+    }
+
+    /// Boxes opened so far.
+    pub fn len(&self) -> usize {
+        self.boxes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boxes.is_empty()
+    }
+
+    /// The box for `board`, opened if it's new, and the symmetry that
+    /// turns the board the way the box reads it.
+    fn open(&mut self, board: &Board) -> (u16, usize) {
+        let (key, symmetry) = solved::canonical(&self.symmetries, board);
+        let perm = &self.symmetries[symmetry];
+        let beads = FIRST_BEADS.saturating_sub(board.moves_played() as u16 / 2).max(1);
+        self.boxes.entry(key).or_insert_with(|| {
+            let mut new = [0; 9];
+            for i in (0..9).filter(|&i| board.cell(i).is_none()) {
+                new[perm[i]] = beads;
+            }
+            new
+        });
+        (key, symmetry)
+    }
+
+    /// Draws a bead for `board`: the box it came from, the square as the
+    /// box reads it, and the move on the board itself.
+    fn draw(&mut self, board: &Board) -> (u16, usize, Move) {
+        let (key, symmetry) = self.open(board);
+        let beads = self.boxes[&key];
+        let total: u32 = beads.iter().map(|&b| b as u32).sum();
+        let mut bead = self.rng.below(total as usize) as u32;
+        let picked = (0..9)
+            .find(|&i| {
+                let found = bead < beads[i] as u32;
+                bead = bead.saturating_sub(beads[i] as u32);
+                found
+            })
+            .expect("the bead is in one of the piles");
+        let perm = &self.symmetries[symmetry];
+        let square = (0..9).find(|&i| perm[i] == picked).expect("symmetries are permutations");
+        (key, picked, board.square_at(square))
+    }
+
+    /// Plays one game against itself and learns from it.
+    pub fn episode(&mut self) -> GameResult {
+        let mut board = Board::new(3, 3);
+        let mut drawn: [Vec<(u16, usize)>; 2] = [Vec::new(), Vec::new()];
+        while board.result().is_none() {
+            let side = board.to_move();
+            let (key, picked, mv) = self.draw(&board);
+            drawn[side.index()].push((key, picked));
+            board.play(mv).expect("boxes only hold beads for empty squares");
+        }
+        let result = board.result().expect("the loop only ends when the game does");
+        for side in Player::all(2) {
+            for &(key, picked) in &drawn[side.index()] {
+                let pile = &mut self.boxes.get_mut(&key).expect("drawn from, so it's open")[picked];
+                *pile = match result {
+                    GameResult::Win(p) if p == side => pile.saturating_add(WIN_BEADS),
+                    GameResult::Win(_) => pile.saturating_sub(LOSS_BEADS).max(1),
+                    GameResult::Draw => pile.saturating_add(DRAW_BEADS),
+                };
+            }
+        }
+        result
+    }
+
+    /// Plays `episodes` games against itself, measuring how it does
+    /// against a random player and against perfect play before it starts
+    /// and `checkpoints` times along the way.
+    pub fn train(&mut self, episodes: u32, checkpoints: u32) -> Curve {
+        let mut perfect = Oracle::new(Database::generate());
+        let every = (episodes / checkpoints.max(1)).max(1);
+        let mut points = Vec::new();
+        for done in 0..=episodes {
+            if done % every == 0 || done == episodes {
+                let mut random = RandomPlayer::new(Rng::new(self.rng.next_u64()));
+                points.push(Checkpoint {
+                    episodes: done,
+                    random: self.face(&mut random, CHECKPOINT_GAMES),
+                    perfect: self.face(&mut perfect, CHECKPOINT_GAMES),
+                });
+            }
+            if done < episodes {
+                self.episode();
+            }
+        }
+        Curve { points }
+    }
+
+    /// Wins, draws and losses in `games` against `opponent`, taking turns
+    /// to go first. Nothing is learned from them.
+    pub fn face(&mut self, opponent: &mut dyn Strategy, games: u32) -> [u32; 3] {
+        let mut score = [0; 3];
+        for game in 0..games {
+            let me = Player::nth(game as usize % 2);
+            let mut board = Board::new(3, 3);
+            while board.result().is_none() {
+                let mv = if board.to_move() == me { Some(self.draw(&board).2) } else { opponent.choose_move(&board) };
+                match mv.map(|mv| board.play(mv)) {
+                    Some(Ok(())) => {}
+                    // an opponent that can't move loses
+                    _ => break,
+                }
+            }
+            let slot = match board.result() {
+                Some(GameResult::Win(p)) if p == me => 0,
+                Some(GameResult::Draw) => 1,
+                Some(_) => 2,
+                None => 0,
+            };
+            score[slot] += 1;
+        }
+        score
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut keys: Vec<&u16> = self.boxes.keys().collect();
+        keys.sort();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(keys.len() as u16).to_le_bytes());
+        for key in keys {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            for beads in &self.boxes[key] {
+                bytes.extend_from_slice(&beads.to_le_bytes());
+            }
+        }
+        fs::write(path, bytes)
+    }
+
+    /// Reads a table `save` wrote, to play or train on with `rng`.
+    pub fn load(path: &str, rng: Rng) -> Result<Menace, String> {
+        let bytes = fs::read(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+        let bad = || format!("{} isn't a MENACE table", path);
+        let body = bytes.strip_prefix(&MAGIC[..]).ok_or_else(bad)?;
+        if body.len() < 2 {
+            return Err(bad());
+        }
+        let count = u16::from_le_bytes([body[0], body[1]]) as usize;
+        let body = &body[2..];
+        if body.len() != count * BOX_BYTES {
+            return Err(bad());
+        }
+        let mut menace = Menace::new(rng);
+        for entry in body.chunks(BOX_BYTES) {
+            let key = u16::from_le_bytes([entry[0], entry[1]]);
+            let mut beads = [0; 9];
+            for (i, pile) in beads.iter_mut().enumerate() {
+                *pile = u16::from_le_bytes([entry[2 + 2 * i], entry[3 + 2 * i]]);
+            }
+            if beads.iter().all(|&b| b == 0) {
+                return Err(bad());
+            }
+            menace.boxes.insert(key, beads);
+        }
+        Ok(menace)
+    }
+}
+
+impl Strategy for Menace {
+    fn name(&self) -> String {
+        "MENACE".to_owned()
+    }
+
+    /// Draws a bead like in training, but learns nothing from the game.
+    fn choose_move(&mut self, board: &Board) -> Option<Move> {
+        if !Menace::covers(board) || board.result().is_some() {
+            return None;
+        }
+        Some(self.draw(board).2)
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    /// Percentages of wins, draws and losses against each opponent.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |score: [u32; 3]| {
+            let games = score.iter().sum::<u32>().max(1) as f64;
+            let [w, d, l] = score.map(|n| 100.0 * n as f64 / games);
+            format!("{:>5.1} {:>5.1} {:>5.1}", w, d, l)
+        };
+        write!(f, "{:>9}   {}   {}", self.episodes, percent(self.random), percent(self.perfect))
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let heading = format!("{:>9}   {:^17}   {:^17}", "", "vs random", "vs perfect");
+        writeln!(f, "{}", heading.trim_end())?;
+        writeln!(f, "{:>9}   {:>5} {:>5} {:>5}   {:>5} {:>5} {:>5}", "episodes", "W%", "D%", "L%", "W%", "D%", "L%")?;
+        for point in &self.points {
+            writeln!(f, "{}", point)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn learns_to_hold_perfect_play_to_a_draw() {
+        let mut menace = Menace::new(Rng::new(1));
+        let curve = menace.train(4000, 4);
+        assert_eq!(curve.points.len(), 5);
+        let (first, last) = (curve.points[0], curve.points[4]);
+        assert_eq!(last.episodes, 4000);
+        // perfect play never loses, so the only question is how often it draws
+        assert_eq!((first.perfect[0], last.perfect[0]), (0, 0));
+        assert!(last.perfect[1] > first.perfect[1] * 2, "{}", curve);
+        assert!(last.random[2] < first.random[2], "{}", curve);
+        // the same seed learns the same table
+        let mut again = Menace::new(Rng::new(1));
+        again.train(4000, 4);
+        assert_eq!(again.boxes, menace.boxes);
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let mut menace = Menace::new(Rng::new(2));
+        for _ in 0..200 {
+            menace.episode();
+        }
+        let path = std::env::temp_dir().join(format!("xo-menace-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        menace.save(path).unwrap();
+        assert_eq!(fs::metadata(path).unwrap().len() as usize, 6 + menace.len() * BOX_BYTES);
+        let loaded = Menace::load(path, Rng::new(0)).unwrap();
+        assert_eq!(loaded.boxes, menace.boxes);
+        fs::write(path, b"XOM\x01\x01\x00").unwrap();
+        assert!(Menace::load(path, Rng::new(0)).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn moves_come_back_the_right_way_round() {
+        // every position reached in play is a rotation or reflection of
+        // its box, so any square the box gives has to be empty here
+        let mut menace = Menace::new(Rng::new(3));
+        let mut rng = Rng::new(4);
+        for _ in 0..100 {
+            let mut board = Board::new(3, 3);
+            while board.result().is_none() {
+                let mv = menace.choose_move(&board).unwrap();
+                assert!(board.check(mv).is_ok(), "{} on\n{}", mv, board);
+                let moves = board.legal_moves();
+                board.play(moves[rng.below(moves.len())]).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Noughts and crosses and its relatives: bigger boards, K in a row,
+//! ultimate, cubes and Connect Four, under several sets of rules, with
+//! computer players to match.
+//!
+//! The `xo` binary is one front-end; anything else that wants to play,
+//! check or analyse games can use the same pieces:
+//!
+//! - [`Board`]: a position, the moves that led to it and the rules it's
+//!   played by. Moves are checked, played and taken back here.
+//! - [`Game`]: a board with players' names, redo and resignation, and
+//!   [`Game::run`] to take turns until the game ends.
+//! - [`Strategy`]: anything that picks moves, from a person at the
+//!   keyboard to [`minimax::Minimax`] and [`mcts::Mcts`].
+//! - [`roster::Roster`]: who sits at the board, two to eight of them,
+//!   with the names, symbols and colours they play under.
+//!
+//! ```
+//! use xo::{Board, GameResult, Move, Player, Strategy};
+//! use xo::minimax::Minimax;
+//!
+//! let mut board = Board::new(3, 3);
+//! for &(row, col) in &[(1, 1), (0, 0), (0, 2)] {
+//!     board.play(Move::new(row, col)).unwrap();
+//! }
+//! // O has to block the diagonal
+//! assert_eq!(Minimax::perfect().choose_move(&board), Some(Move::new(2, 0)));
+//! while let Some(mv) = Minimax::perfect().choose_move(&board) {
+//!     board.play(mv).unwrap();
+//! }
+//! assert_eq!(board.result(), Some(GameResult::Draw));
+//! assert_eq!(board.to_move(), Player::O);
+//! ```
+
+pub mod baseline;
+pub mod bench;
+pub mod bitboard;
+pub mod board;
+pub mod clock;
+pub mod engine;
+pub mod game;
+pub mod hint;
+pub mod http;
+pub mod learn;
+pub mod mcts;
+pub mod minimax;
+pub mod net;
+pub mod notation;
+pub mod opening;
+pub mod record;
+pub mod renju;
+pub mod replay;
+pub mod rng;
+pub mod room;
+pub mod roster;
+pub mod rules;
+pub mod server;
+pub mod solved;
+pub mod strategy;
+pub mod tournament;
+pub mod tui;
+pub mod view;
+pub mod websocket;
+
+pub use board::{Board, GameResult, Move, MoveError, Piece, Player, Variant};
+pub use game::{Game, State};
+pub use rules::Rules;
+pub use strategy::{Action, Strategy};
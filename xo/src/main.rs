@@ -1,39 +1,680 @@
+//! The `xo` command: reads the options, then sets up a game, a match or
+//! an analysis from the library's parts and gets out of the way.
+
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, stdin, stdout, IsTerminal, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use xo::clock::{Clock, TimeControl, WallClock};
+use xo::learn::Menace;
+use xo::mcts::{Budget, Mcts};
+use xo::minimax::Minimax;
+use xo::net::{Ending, Session};
+use xo::opening::Opening;
+use xo::record::GameRecord;
+use xo::replay::{self, Replay};
+use xo::rng::Rng;
+use xo::roster::{Member, Roster, Seat};
+use xo::server;
+use xo::solved::{self, Database, Oracle};
+use xo::strategy::Human;
+use xo::tournament::{self, Entrant};
+use xo::tui::{self, KeyboardPlayer, Screen, TuiView};
+use xo::view::{LineView, View};
+use xo::board::MAX_PLAYERS;
+use xo::{bench, engine, Board, Game, Player, Rules, Strategy, Variant};
+
+const USAGE: &str = "usage: xo [bench | tournament | solve | analyze POSITION | replay FILE | train
+          | engine | host | join HOST:PORT | serve] [--size N] [--k K] [--computer x|o] [engine options]
+          [--player NAME,SYMBOL,COLOUR,SEAT ... | --roster FILE]
+
+  bench           time the minimax search and move generation instead of playing
+  tournament      play the computer players against each other and rate them
+  solve           work out every 3x3 position and write the table to --db
+  analyze POSITION
+                  look a 3x3 position up in the table, given as the moves
+                  so far (\"b2 a1\") or nine squares (\"x.. .o. ...\")
+  train           teach the MENACE player 3x3 by self-play, carrying on
+                  from --table if it's there, and show how it does
+                  against random and perfect play as it goes
+  --table FILE    its bead table (default xo-menace.db)
+  --episodes N    games to train for (default 10000)
+  replay FILE     step through a saved game, with the engine's view of
+                  every move
+  --db FILE       the solved table (default xo-3x3.db); perfect play on
+                  3x3 reads it if it's there
+  engine          answer engine protocol commands on stdin, for scripts
+                  and other programs (newgame, position, go, quit), or
+                  on the network with --port
+  host            wait for another player to join over the network
+  join HOST:PORT  join a game someone is hosting
+  serve           host games for web browsers: open http://localhost:8080/
+                  to start one, and send the link to whoever you're playing
+                  or pick one from the lobby to join or watch
+  --port P        port to host on (default 7878), serve the engine on, or
+                  serve games on (default 8080)
+  --name NAME     what to call you in network games (default Player)
+  --size N        play on an N by N board (1 to 26, default 3, or 4 for a cube)
+  --k K           K marks in a row win (default: N, or 5 on boards bigger than 5)
+  --variant V     classic; ultimate: nine small boards where your move
+                  picks the board your opponent plays in next; or cube:
+                  N layers of N by N, moves written layer first (2b3);
+                  or gravity: Connect Four, N columns of N-1 rows
+                  (default 7, 4 in a row) where pieces drop to the
+                  bottom, so a move is just a column (d)
+  --rules R       standard; misere: K in a row loses; wild: either player
+                  may play X or O (b2=O); numerical: X plays odd numbers
+                  and O even ones (b2=5), and a line adding up to 15 wins;
+                  or renju: five in a row, but X may not make a double
+                  three, a double four or six in a row
+  --opening O     free: X just goes first (the default); or swap2: the
+                  first player puts down two X and an O, then the second
+                  plays X, plays O, or puts down two more and lets the
+                  first choose. Players and --computer go by seat then,
+                  the first player first. Also for tournaments, but not
+                  against the clock
+  --computer x|o  let the computer play that side
+  --player NAME,SYMBOL,COLOUR,SEAT
+                  once for each player, in turn order: X, O, then Y, Z, W,
+                  V, U and T for up to 8 on boards with room for them.
+                  Leave a field empty for its default (\"Ann,,red\"). The
+                  symbol is one character to draw instead of the letter;
+                  colours are black, red, green, yellow, blue, magenta,
+                  cyan and white; the seat is human (the default),
+                  computer (as the engine options say), a tournament
+                  player such as minimax:3 or engine:COMMAND, or
+                  remote:HOST:PORT for an engine served with --port
+  --roster FILE   the players from a file instead: a [player] line for
+                  each, then name = ..., symbol = ..., colour = ... and
+                  seat = ... lines
+  --clock SECONDS[+INCREMENT]
+                  play against the clock: each player starts with SECONDS
+                  and gets INCREMENT back with every move (60+2); out of
+                  time loses, and the computer thinks for its share of
+                  what it has left. With --tui the clock runs on screen;
+                  without, running out is noticed at the next move
+  --tui           full-screen board with a cursor (if the terminal allows)
+  --load FILE     pick up a saved game where it left off
+  --save FILE     write the game record to FILE when the game ends
+
+During a game you can also type undo, redo, hint (or ?) to have every move
+rated, save FILE, resign or quit.
+
+engine options:
+  --engine minimax|mcts
+                  how the computer searches (default minimax)
+  --depth D       limit minimax to D moves ahead
+                  (default: perfect play on 3x3, 3 on bigger boards,
+                  4 on a cube, 6 in ultimate)
+  --playouts N    let MCTS run N playouts per move (default 10000)
+  --millis MS     let MCTS think for MS milliseconds per move instead,
+                  or minimax search deeper and deeper for that long
+                  (default: 1000 on a gravity board)
+  --seed S        seed MCTS for a reproducible game, or training
+
+tournament options:
+  --players LIST  comma-separated, from random, greedy, minimax, minimax:DEPTH,
+                  minimax:MILLISms, mcts:PLAYOUTS, mcts:MILLISms,
+                  engine:COMMAND, a program speaking the engine protocol,
+                  remote:HOST:PORT, one served on the network, and
+                  menace:FILE, playing from a table train wrote
+                  (default random,greedy,minimax:2,minimax,mcts:1000,mcts:10000)
+  --games N       games per pairing, taking turns to go first (default 10)";
+
+const DEFAULT_PLAYERS: &str = "random,greedy,minimax:2,minimax,mcts:1000,mcts:10000";
+
+const DEFAULT_DB: &str = "xo-3x3.db";
+
+const DEFAULT_PORT: u16 = 7878;
+
+const DEFAULT_WEB_PORT: u16 = 8080;
+
+const DEFAULT_TABLE: &str = "xo-menace.db";
+
+/// How many times training stops to see how it's doing.
+const CHECKPOINTS: u32 = 10;
+
+enum Command {
+    Play,
+    Bench,
+    Tournament,
+    Solve,
+    Train,
+    Analyze(String),
+    Replay(String),
+    Engine,
+    Host,
+    Join(String),
+    Serve,
+}
+
+enum Engine {
+    Minimax,
+    Mcts,
+}
+
+struct Options {
+    command: Command,
+    variant: Variant,
+    rules: Rules,
+    size: usize,
+    k: usize,
+    computer: Option<Player>,
+    engine: Engine,
+    depth: Option<u32>,
+    budget: Budget,
+    seed: Option<u64>,
+    load: Option<String>,
+    save: Option<String>,
+    port: Option<u16>,
+    name: String,
+    tui: bool,
+    clock: Option<TimeControl>,
+    opening: Opening,
+    /// As given with `--player` or `--roster`; otherwise everyone is a
+    /// person at the keyboard but for `computer`.
+    roster: Option<Roster>,
+    players: Vec<Entrant>,
+    games: u32,
+    db: String,
+    table: String,
+    episodes: u32,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut command = Command::Play;
+    let mut variant = Variant::Classic;
+    let mut rules = Rules::Standard;
+    let mut size = None;
+    let mut k = None;
+    let mut computer = None;
+    let mut engine = Engine::Minimax;
+    let mut depth = None;
+    let mut budget = Budget::Playouts(10_000);
+    let mut seed = None;
+    let mut load = None;
+    let mut save = None;
+    let mut port = None;
+    let mut name = "Player".to_owned();
+    let mut tui = false;
+    let mut clock = None;
+    let mut opening = Opening::Free;
+    let mut members = Vec::new();
+    let mut roster = None;
+    let mut players = parse_players(DEFAULT_PLAYERS)?;
+    let mut games = 10;
+    let mut db = DEFAULT_DB.to_owned();
+    let mut table = DEFAULT_TABLE.to_owned();
+    let mut episodes = 10_000;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "bench" => command = Command::Bench,
+            "tournament" => command = Command::Tournament,
+            "--players" => players = parse_players(iter.next().map_or("", |s| s.as_str()))?,
+            "--games" => games = parse_arg(arg, iter.next())?,
+            "solve" => command = Command::Solve,
+            "train" => command = Command::Train,
+            "--table" => table = path_arg(arg, iter.next())?,
+            "--episodes" => episodes = parse_arg(arg, iter.next())?,
+            "engine" => command = Command::Engine,
+            "analyze" => command = Command::Analyze(iter.next().cloned().ok_or("analyze needs a position")?),
+            "replay" => command = Command::Replay(path_arg(arg, iter.next())?),
+            "--db" => db = path_arg(arg, iter.next())?,
+            "host" => command = Command::Host,
+            "serve" => command = Command::Serve,
+            "join" => command = Command::Join(iter.next().cloned().ok_or("join needs HOST:PORT")?),
+            "--port" => port = Some(parse_arg(arg, iter.next())?),
+            "--name" => name = iter.next().cloned().ok_or("--name needs a name")?,
+            "--variant" => {
+                let name = iter.next().map(|s| s.to_ascii_lowercase()).unwrap_or_default();
+                variant = Variant::from_name(&name).ok_or("--variant needs classic, ultimate, cube or gravity")?;
+            }
+            "--rules" | "-r" => {
+                let name = iter.next().map(|s| s.to_ascii_lowercase()).unwrap_or_default();
+                rules = Rules::from_name(&name).ok_or("--rules needs standard, misere, wild, numerical or renju")?;
+            }
+            "--opening" => {
+                let name = iter.next().map(|s| s.to_ascii_lowercase()).unwrap_or_default();
+                opening = Opening::from_name(&name).ok_or("--opening needs free or swap2")?;
+            }
+            "--size" | "-n" => size = Some(number_arg(arg, iter.next())?),
+            "--k" | "-k" => k = Some(number_arg(arg, iter.next())?),
+            "--computer" | "-c" => {
+                let mut letter = iter.next().map_or("".chars(), |s| s.chars());
+                computer = match (letter.next(), letter.next()) {
+                    (Some(c), None) => Some(Player::from_letter(c).ok_or("--computer needs a player's letter")?),
+                    _ => return Err("--computer needs x or o".to_owned()),
+                }
+            }
+            "--player" | "-p" => {
+                let spec = iter.next().ok_or("--player needs NAME,SYMBOL,COLOUR,SEAT")?;
+                if members.len() == MAX_PLAYERS {
+                    return Err(format!("there can't be more than {} players", MAX_PLAYERS));
+                }
+                members.push(Member::parse(spec, Player::nth(members.len()))?);
+            }
+            "--roster" => roster = Some(Roster::load(&path_arg(arg, iter.next())?)?),
+            "--engine" | "-e" => {
+                engine = match iter.next().map(|s| s.to_ascii_lowercase()).as_deref() {
+                    Some("minimax") => Engine::Minimax,
+                    Some("mcts") => Engine::Mcts,
+                    _ => return Err("--engine needs minimax or mcts".to_owned()),
+                }
+            }
+            "--depth" | "-d" => depth = Some(parse_arg(arg, iter.next())?),
+            "--playouts" => match parse_arg(arg, iter.next())? {
+                0 => return Err("--playouts needs at least one playout".to_owned()),
+                playouts => budget = Budget::Playouts(playouts),
+            },
+            "--millis" => budget = Budget::Time(Duration::from_millis(parse_arg(arg, iter.next())?)),
+            "--seed" => seed = Some(parse_arg(arg, iter.next())?),
+            "--tui" => tui = true,
+            "--clock" => clock = Some(iter.next().ok_or("--clock needs SECONDS[+INCREMENT]")?.parse()?),
+            "--load" => load = Some(path_arg(arg, iter.next())?),
+            "--save" => save = Some(path_arg(arg, iter.next())?),
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => return Err(format!("unknown argument {}", other)),
+        }
+    }
+    if !members.is_empty() {
+        if roster.is_some() {
+            return Err("give the players with --player or --roster, not both".to_owned());
+        }
+        roster = Some(Roster::new(members)?);
+    }
+    let count = roster.as_ref().map_or(2, Roster::len);
+    if count > 2 && matches!(command, Command::Host | Command::Join(_)) {
+        return Err("network games are for two players".to_owned());
+    }
+    if count > 2 && matches!(command, Command::Tournament) {
+        return Err("tournaments are for two players".to_owned());
+    }
+    if count > 2 && matches!(command, Command::Engine) {
+        return Err("the engine plays two-player games".to_owned());
+    }
+    if count > 2 && clock.is_some() {
+        return Err("games against the clock are for two players".to_owned());
+    }
+    if opening != Opening::Free {
+        if count > 2 {
+            return Err(format!("{} is for two players", opening.name()));
+        }
+        if matches!(command, Command::Host | Command::Join(_)) {
+            return Err(format!("network games don't have a {} opening", opening.name()));
+        }
+        if load.is_some() {
+            return Err(format!("a saved game can't be picked up in a {} opening", opening.name()));
+        }
+        if clock.is_some() {
+            return Err(format!("games against the clock don't have a {} opening", opening.name()));
+        }
+    }
+    let size = size.unwrap_or_else(|| variant.default_size());
+    let k = k.unwrap_or_else(|| variant.default_k(size));
+    Board::check_settings(variant, size, k, rules, count)?;
+    Ok(Options {
+        command,
+        variant,
+        rules,
+        size,
+        k,
+        computer,
+        engine,
+        depth,
+        budget,
+        seed,
+        load,
+        save,
+        port,
+        name,
+        tui,
+        clock,
+        opening,
+        roster,
+        players,
+        games,
+        db,
+        table,
+        episodes,
+    })
+}
+
+fn parse_players(list: &str) -> Result<Vec<Entrant>, String> {
+    let players = list
+        .split(',')
+        .map(|s| s.trim().parse())
+        .collect::<Result<Vec<Entrant>, String>>()?;
+    if players.len() < 2 {
+        return Err("--players needs at least two players".to_owned());
+    }
+    Ok(players)
+}
+
+fn path_arg(flag: &str, value: Option<&String>) -> Result<String, String> {
+    value.cloned().ok_or_else(|| format!("{} needs a file name", flag))
+}
+
+fn number_arg(flag: &str, value: Option<&String>) -> Result<usize, String> {
+    parse_arg(flag, value)
+}
+
+/// The number after `flag`, which has to fit in a `T`: too big is an
+/// error, not wrapped round to something that fits.
+fn parse_arg<T: FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a number", flag))?;
+    value.parse().map_err(|_| match value.parse::<u128>() {
+        Ok(_) => format!("{} {} is too big", flag, value),
+        Err(_) => format!("{} needs a number", flag),
+    })
+}
+
+fn minimax(board: &Board, opts: &Options) -> Minimax {
+    match (opts.depth, opts.budget) {
+        (Some(d), _) => Minimax::with_depth(d),
+        (None, Budget::Time(time)) => Minimax::timed(time),
+        (None, Budget::Playouts(_)) => Minimax::for_board(board),
+    }
+}
+
+/// The table from `--db`, or a fresh one if that can't be read.
+fn database(opts: &Options) -> Database {
+    Database::load(&opts.db).unwrap_or_else(|_| Database::generate())
+}
+
+fn computer(board: &Board, opts: &Options) -> Box<dyn Strategy> {
+    match opts.engine {
+        Engine::Minimax if opts.depth.is_none() && Database::covers(board) => Box::new(Oracle::new(database(opts))),
+        Engine::Minimax => Box::new(minimax(board, opts)),
+        Engine::Mcts => {
+            let rng = opts.seed.map_or_else(Rng::from_time, Rng::new);
+            Box::new(Mcts::new(opts.budget, rng))
+        }
+    }
+}
+
+/// The empty board the options ask for.
+fn new_board(opts: &Options) -> Board {
+    let players = opts.roster.as_ref().map_or(2, Roster::len);
+    Board::with_variant(opts.variant, opts.size, opts.k)
+        .with_rules(opts.rules)
+        .with_players(players)
+}
+
+/// Who's playing on `board`: the roster asked for, or people at the
+/// keyboard, with the computer in the seat `--computer` names. With
+/// colours only where they'll show.
+fn roster(board: &Board, opts: &Options) -> Result<Roster, String> {
+    let mut roster = opts.roster.clone().unwrap_or_else(|| Roster::humans(board.players()));
+    if roster.len() != board.players() {
+        return Err(format!("the game is for {} players, not {}", board.players(), roster.len()));
+    }
+    if let Some(side) = opts.computer {
+        if side.index() >= roster.len() {
+            return Err(format!("there's no player {} in a game of {}", side, roster.len()));
+        }
+        roster.member_mut(side).seat = Seat::Computer;
+    }
+    Ok(if stdout().is_terminal() { roster } else { roster.plain() })
+}
+
+fn load(path: &str) -> Result<Board, String> {
+    let record = GameRecord::load(path)?;
+    record.replay().map_err(|e| format!("{}: {}", path, e))
+}
 
 fn main() {
-    let square = "|   ";
-    let horiz = " ".to_owned() + &"-".repeat(13);
-    let dims = 3;
-    // let mut player = 0; //0 is x, 1 is o
-    let mut bot = "   ".to_owned();
-    // print game board
-    
-    for n in 1..dims+1 {
-        println!("{}", horiz);
-        println!("{}{}", n, square.repeat(dims+1));
-        bot = bot + &n.to_string() + &"   ".to_owned();
-        if n == dims {
-            println!("{}", horiz);
-            println!("{}", bot)
-        }
-    }
-
-    use std::io::{stdin,stdout,Write};
-    let mut s=String::new();
-    print!("Player 1, please make a move: ");
-    let _=stdout().flush();
-    stdin().read_line(&mut s).expect("Did not enter a correct string");
-    if let Some('\n')=s.chars().next_back() {
-        s.pop();
-    }
-    if let Some('\r')=s.chars().next_back() {
-        s.pop();
-    }
-
-    let row_b: u8 = s.as_bytes()[1];
-    let col_b: u8 = s.as_bytes()[0];
-
-    let row: char = row_b as char;
-    let col: char = col_b as char;
-    println!("you chose row {} and col {}", row, col );
-
-}
\ No newline at end of file
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("xo: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let board = match &opts.load {
+        Some(path) => load(path).unwrap_or_else(|e| {
+            eprintln!("xo: {}", e);
+            process::exit(1);
+        }),
+        None => new_board(&opts),
+    };
+    match &opts.command {
+        Command::Bench => {
+            bench::run(&board, minimax(&board, &opts));
+            return;
+        }
+        Command::Tournament => {
+            let seed = opts.seed.unwrap_or_else(|| Rng::from_time().next_u64());
+            println!("Seed {}.", seed);
+            let table = tournament::run(&board, &opts.players, opts.games, seed, opts.opening);
+            println!("\n{}", table);
+            return;
+        }
+        Command::Solve => {
+            let db = Database::generate();
+            if let Err(e) = db.save(&opts.db) {
+                eprintln!("xo: can't write {}: {}", opts.db, e);
+                process::exit(1);
+            }
+            println!("Wrote {} positions to {}.", db.len(), opts.db);
+            return;
+        }
+        Command::Train => {
+            let rng = opts.seed.map_or_else(Rng::from_time, Rng::new);
+            // a table that's there but won't load is kept, not trained over
+            let mut menace = if Path::new(&opts.table).exists() {
+                let menace = Menace::load(&opts.table, rng).unwrap_or_else(|e| {
+                    eprintln!("xo: {}", e);
+                    process::exit(1);
+                });
+                println!("Carrying on from the {} positions in {}.", menace.len(), opts.table);
+                menace
+            } else {
+                Menace::new(rng)
+            };
+            let curve = menace.train(opts.episodes, CHECKPOINTS);
+            println!("{}", curve);
+            if let Err(e) = menace.save(&opts.table) {
+                eprintln!("xo: can't write {}: {}", opts.table, e);
+                process::exit(1);
+            }
+            println!("Wrote {} positions to {}.", menace.len(), opts.table);
+            return;
+        }
+        Command::Analyze(position) => {
+            match solved::analyze(position, &database(&opts)) {
+                Ok(text) => println!("{}", text),
+                Err(e) => {
+                    eprintln!("xo: {}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        Command::Replay(path) => {
+            let replay = GameRecord::load(path)
+                .and_then(|record| Replay::new(record).map_err(|e| format!("{}: {}", path, e)));
+            match replay {
+                Ok(mut replay) => step_through(&mut replay, opts.tui),
+                Err(e) => {
+                    eprintln!("xo: {}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        Command::Engine => {
+            let stdin = stdin();
+            let served = match opts.port {
+                Some(port) => TcpListener::bind(("0.0.0.0", port)).and_then(|listener| engine::listen(&listener)),
+                None => engine::serve(stdin.lock(), stdout()),
+            };
+            if let Err(e) = served {
+                eprintln!("xo: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Command::Serve => {
+            let port = opts.port.unwrap_or(DEFAULT_WEB_PORT);
+            let served = TcpListener::bind(("0.0.0.0", port)).and_then(|listener| {
+                println!("Serving games on http://localhost:{}/", port);
+                server::listen(&listener)
+            });
+            if let Err(e) = served {
+                eprintln!("xo: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Command::Host | Command::Join(_) => {
+            if let Err(e) = play_online(&opts) {
+                eprintln!("xo: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Command::Play => {}
+    }
+    let roster = roster(&board, &opts).unwrap_or_else(|e| {
+        eprintln!("xo: {}", e);
+        process::exit(2);
+    });
+    let screen = if opts.tui {
+        match Screen::enter().map(|screen| screen.with_roster(roster.clone())) {
+            Ok(screen) => Some(Rc::new(RefCell::new(screen))),
+            Err(e) => {
+                eprintln!("xo: can't start the full-screen board ({}), using line mode", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut view: Box<dyn View> = match &screen {
+        Some(screen) => Box::new(TuiView(screen.clone())),
+        None => Box::new(LineView::new(roster.clone())),
+    };
+    if let Some(goal) = board.goal() {
+        view.message(&goal);
+    }
+    if board.layers() > 1 {
+        view.message("Moves go layer first, e.g. 2b3.");
+    }
+
+    let mut rng = opts.seed.map_or_else(Rng::from_time, Rng::new);
+    let mut players: Vec<Box<dyn Strategy>> = Vec::new();
+    let mut names = Vec::new();
+    for (side, member) in Player::all(roster.len()).zip(roster.members()) {
+        // under swap2 nobody knows their side yet, only their seat
+        let name = match opts.opening {
+            Opening::Free => member.name.clone().unwrap_or_else(|| format!("Player {}", side)),
+            Opening::Swap2 => member.name.clone().unwrap_or_else(|| format!("Player {}", side.index() + 1)),
+        };
+        let player: Box<dyn Strategy> = match (&member.seat, &screen) {
+            (Seat::Human, Some(screen)) => Box::new(KeyboardPlayer::new(&name, screen.clone())),
+            (Seat::Human, None) => Box::new(Human::new(&name)),
+            (Seat::Computer, _) => computer(&board, &opts),
+            (Seat::Bot(entrant), _) => entrant.build(&board, Rng::new(rng.next_u64())),
+        };
+        names.push(member.name.clone().unwrap_or_else(|| player.name()));
+        players.push(player);
+    }
+    if roster.len() > 2 {
+        let order: Vec<String> =
+            Player::all(roster.len()).map(|p| format!("{} ({})", names[p.index()], roster.mark(p))).collect();
+        view.message(&format!("Taking turns: {}.", order.join(", ")));
+    }
+    if opts.opening == Opening::Swap2 {
+        view.message(&format!(
+            "Swap2: {} puts down two X and an O, then {} picks a side or puts down two more.",
+            names[0], names[1]
+        ));
+    }
+    let mut game = Game::new(board, names).with_opening(opts.opening);
+    if let Some(control) = opts.clock {
+        if game.board().players() > 2 {
+            eprintln!("xo: games against the clock are for two players");
+            process::exit(2);
+        }
+        game = game.with_clock(Clock::new(control, 2, Arc::new(WallClock::new())));
+    }
+    game.run(&mut players, &|side| roster.member(side).seat != Seat::Human, view.as_mut());
+
+    if let Some(path) = &opts.save {
+        view.message(&game.save(path));
+    }
+    if game.result().is_some() {
+        view.finish(&game);
+        let mut replay = Replay::new(game.record()).expect("a game just played records cleanly");
+        replay.goto(replay.len());
+        match &screen {
+            Some(screen) => tui::replay(&mut screen.borrow_mut(), &mut replay),
+            None if stdin().is_terminal() && ask_yes_no("Step through the game?") => replay::run(&mut replay),
+            None => {}
+        }
+    }
+}
+
+/// Goes over a saved game, full-screen if asked and the terminal allows.
+fn step_through(replay: &mut Replay, tui: bool) {
+    if tui {
+        match Screen::enter() {
+            Ok(mut screen) => return tui::replay(&mut screen, replay),
+            Err(e) => eprintln!("xo: can't start the full-screen board ({}), using line mode", e),
+        }
+    }
+    replay::run(replay);
+}
+
+fn ask_yes_no(question: &str) -> bool {
+    print!("{} [y/n] ", question);
+    let _ = stdout().flush();
+    let mut s = String::new();
+    stdin().read_line(&mut s).is_ok() && s.trim().to_ascii_lowercase().starts_with('y')
+}
+
+fn play_online(opts: &Options) -> io::Result<()> {
+    let mut session = match &opts.command {
+        Command::Join(addr) => Session::join(addr.as_str(), &opts.name)?,
+        _ => {
+            let port = opts.port.unwrap_or(DEFAULT_PORT);
+            let listener = TcpListener::bind(("0.0.0.0", port))?;
+            println!("Waiting for someone to join on port {}...", port);
+            let board = new_board(opts);
+            Session::host(&listener, &opts.name, &board)?
+        }
+    };
+    println!("Playing against {}. Type say <text> to chat.", session.peer_name());
+    let mut me = Human::new(&opts.name);
+    loop {
+        println!("You are {} this game.", session.side());
+        let (game, ending) = session.play(&mut me)?;
+        if let Some(path) = &opts.save {
+            println!("{}", game.save(path));
+        }
+        match ending {
+            Ending::Finished => println!("{}", game.result_text().unwrap_or_default()),
+            Ending::Quit => return Ok(()),
+            Ending::PeerLeft => {
+                println!("{} left the game.", session.peer_name());
+                return Ok(());
+            }
+        }
+        if !session.rematch(ask_yes_no("Play again?"))? {
+            println!("Thanks for playing.");
+            return Ok(());
+        }
+    }
+}
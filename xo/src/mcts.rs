@@ -0,0 +1,379 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::board::{Board, GameResult, Move, Player, Variant};
+use crate::minimax::candidate_moves;
+use crate::rng::Rng;
+use crate::strategy::Strategy;
+
+/// Exploration constant for UCT, the usual sqrt(2).
+const EXPLORATION: f64 = 1.414;
+
+/// How long the engine may think before it has to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Budget {
+    Playouts(u32),
+    Time(Duration),
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Budget::Playouts(n) => write!(f, "{} playouts", n),
+            Budget::Time(d) => write!(f, "{}ms", d.as_millis()),
+        }
+    }
+}
+
+struct Node {
+    /// The move that led here; `None` only at the root.
+    mv: Option<Move>,
+    /// Who played `mv`. Wins are counted from their side.
+    mover: Player,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    visits: u32,
+    wins: f64,
+}
+
+impl Node {
+    fn win_rate(&self) -> f64 {
+        if self.visits == 0 {
+            0.5
+        } else {
+            self.wins / self.visits as f64
+        }
+    }
+}
+
+/// What the last search found, for showing to the player.
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub playouts: u32,
+    pub best: Option<Move>,
+    /// Chance the side to move wins by playing `best`, counting draws as half.
+    pub win_rate: f64,
+    /// Most visited line from the root.
+    pub pv: Vec<Move>,
+    /// Every root move with its visit count and win rate, most visited first.
+    pub moves: Vec<(Move, u32, f64)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pv: Vec<String> = self.pv.iter().map(|mv| mv.to_string()).collect();
+        let top: Vec<String> = self
+            .moves
+            .iter()
+            .take(3)
+            .map(|(mv, visits, rate)| format!("{} {:.0}%/{}", mv, rate * 100.0, visits))
+            .collect();
+        write!(
+            f,
+            "{:.0}% to win, {} playouts, pv {} (top: {})",
+            self.win_rate * 100.0,
+            self.playouts,
+            pv.join(" "),
+            top.join(", ")
+        )
+    }
+}
+
+/// Monte Carlo tree search with UCT selection and random playouts.
+pub struct Mcts {
+    budget: Budget,
+    /// Against the clock, the longest it may take whatever the budget.
+    limit: Option<Duration>,
+    rng: Rng,
+    nodes: Vec<Node>,
+    report: Option<Report>,
+}
+
+impl Mcts {
+    pub fn new(budget: Budget, rng: Rng) -> Mcts {
+        Mcts {
+            budget,
+            limit: None,
+            rng,
+            nodes: Vec::new(),
+            report: None,
+        }
+    }
+
+    pub fn report(&self) -> Option<&Report> {
+        self.report.as_ref()
+    }
+
+    pub fn search(&mut self, board: &Board) -> Report {
+        self.nodes.clear();
+        self.nodes.push(Node {
+            mv: None,
+            mover: board.to_move().previous(board.players()),
+            parent: None,
+            children: Vec::new(),
+            untried: self.expansions(board),
+            visits: 0,
+            wins: 0.0,
+        });
+
+        let start = Instant::now();
+        let mut playouts = 0;
+        loop {
+            match self.budget {
+                Budget::Playouts(n) if playouts >= n => break,
+                // checking the clock every playout is measurably slow on 3x3
+                Budget::Time(d) if playouts % 64 == 0 && start.elapsed() >= d => break,
+                _ => {}
+            }
+            if let Some(limit) = self.limit {
+                if playouts > 0 && playouts % 64 == 0 && start.elapsed() >= limit {
+                    break;
+                }
+            }
+            self.iterate(board);
+            playouts += 1;
+        }
+
+        let report = self.make_report(playouts);
+        self.report = Some(report.clone());
+        report
+    }
+
+    /// One round of select, expand, simulate and backpropagate.
+    fn iterate(&mut self, root: &Board) {
+        let mut board = root.clone();
+        let mut node = 0;
+
+        while self.nodes[node].untried.is_empty() && !self.nodes[node].children.is_empty() {
+            node = self.select_child(node);
+            let mv = self.nodes[node].mv.expect("only the root has no move");
+            board.play(mv).expect("tree moves are legal");
+        }
+
+        if !self.nodes[node].untried.is_empty() {
+            let pick = self.rng.below(self.nodes[node].untried.len());
+            let mv = self.nodes[node].untried.swap_remove(pick);
+            let mover = board.to_move();
+            board.play(mv).expect("untried moves are legal");
+            let child = Node {
+                mv: Some(mv),
+                mover,
+                parent: Some(node),
+                children: Vec::new(),
+                untried: self.expansions(&board),
+                visits: 0,
+                wins: 0.0,
+            };
+            self.nodes.push(child);
+            let id = self.nodes.len() - 1;
+            self.nodes[node].children.push(id);
+            node = id;
+        }
+
+        let result = self.playout(&mut board);
+
+        let mut cur = Some(node);
+        while let Some(id) = cur {
+            let n = &mut self.nodes[id];
+            n.visits += 1;
+            n.wins += match result {
+                GameResult::Win(p) if p == n.mover => 1.0,
+                GameResult::Win(_) => 0.0,
+                GameResult::Draw => 0.5,
+            };
+            cur = n.parent;
+        }
+    }
+
+    fn select_child(&self, node: usize) -> usize {
+        let parent_visits = (self.nodes[node].visits.max(1) as f64).ln();
+        let uct = |id: usize| {
+            let n = &self.nodes[id];
+            n.win_rate() + EXPLORATION * (parent_visits / n.visits as f64).sqrt()
+        };
+        let children = &self.nodes[node].children;
+        let mut best = children[0];
+        for &id in &children[1..] {
+            if uct(id) > uct(best) {
+                best = id;
+            }
+        }
+        best
+    }
+
+    /// Moves worth adding to the tree. On big boards that is the squares
+    /// near existing marks, the same pruning minimax uses.
+    fn expansions(&self, board: &Board) -> Vec<Move> {
+        if board.result().is_some() {
+            return Vec::new();
+        }
+        candidate_moves(board, None)
+    }
+
+    /// Plays uniformly random moves to the end of the game.
+    fn playout(&mut self, board: &mut Board) -> GameResult {
+        if !matches!(board.variant(), Variant::Classic | Variant::Cube) || board.rules().picks_pieces() {
+            // the legal moves depend on what has been played, so pick afresh each turn
+            while board.result().is_none() {
+                let moves = board.legal_moves();
+                let mv = moves[self.rng.below(moves.len())];
+                board.play(mv).expect("legal moves are legal");
+            }
+            return board.result().expect("the loop only ends when the game does");
+        }
+        // shuffling the empty squares once gives the same distribution as
+        // picking a random one each turn, without rescanning the board
+        let mut moves: Vec<Move> =
+            (0..board.squares()).filter(|&i| board.cell(i).is_none()).map(|i| board.square_at(i)).collect();
+        self.rng.shuffle(&mut moves);
+        while board.result().is_none() {
+            // renju forbids X squares as the stones go down, which are
+            // skipped and left for O; the first X may play is still a
+            // random one
+            let played = (0..moves.len()).rev().find(|&i| board.play(moves[i]).is_ok());
+            moves.remove(played.expect("a game that isn't over has somewhere to play"));
+        }
+        board.result().expect("the loop only ends when the game does")
+    }
+
+    fn make_report(&self, playouts: u32) -> Report {
+        let mut moves: Vec<(Move, u32, f64)> = self.nodes[0]
+            .children
+            .iter()
+            .map(|&id| {
+                let n = &self.nodes[id];
+                (n.mv.expect("children have moves"), n.visits, n.win_rate())
+            })
+            .collect();
+        moves.sort_by_key(|m| std::cmp::Reverse(m.1));
+
+        let mut pv = Vec::new();
+        let mut node = 0;
+        while let Some(&next) = self.nodes[node]
+            .children
+            .iter()
+            .max_by_key(|&&id| self.nodes[id].visits)
+        {
+            pv.push(self.nodes[next].mv.expect("children have moves"));
+            node = next;
+        }
+
+        Report {
+            playouts,
+            best: moves.first().map(|m| m.0),
+            win_rate: moves.first().map_or(0.5, |m| m.2),
+            pv,
+            moves,
+        }
+    }
+}
+
+impl Strategy for Mcts {
+    fn name(&self) -> String {
+        format!("MCTS ({})", self.budget)
+    }
+
+    fn choose_move(&mut self, board: &Board) -> Option<Move> {
+        self.search(board).best
+    }
+
+    fn comment(&self) -> Option<String> {
+        self.report().map(|r| r.to_string())
+    }
+
+    fn think_for(&mut self, time: Duration) {
+        self.limit = Some(time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+
+    fn board_after(moves: &[(usize, usize)]) -> Board {
+        let mut board = Board::new(3, 3);
+        for &(r, c) in moves {
+            board.play(Move::new(r, c)).unwrap();
+        }
+        board
+    }
+
+    #[test]
+    fn same_seed_same_search() {
+        let board = board_after(&[(1, 1)]);
+        let a = Mcts::new(Budget::Playouts(2000), Rng::new(7)).search(&board);
+        let b = Mcts::new(Budget::Playouts(2000), Rng::new(7)).search(&board);
+        assert_eq!(a.pv, b.pv);
+        assert_eq!(a.moves, b.moves);
+    }
+
+    #[test]
+    fn takes_an_immediate_win() {
+        // X: a1 b1, O: a2 b2, X to move
+        let board = board_after(&[(0, 0), (1, 0), (0, 1), (1, 1)]);
+        let report = Mcts::new(Budget::Playouts(3000), Rng::new(1)).search(&board);
+        assert_eq!(report.best, Some(Move::new(0, 2)));
+        assert!(report.win_rate > 0.9);
+    }
+
+    #[test]
+    fn blocks_an_immediate_loss() {
+        // X: a1 b1, O: b2, O to move must take c1
+        let board = board_after(&[(0, 0), (1, 1), (0, 1)]);
+        let report = Mcts::new(Budget::Playouts(5000), Rng::new(3)).search(&board);
+        assert_eq!(report.best, Some(Move::new(0, 2)));
+    }
+
+    #[test]
+    fn plays_gravity_games_out() {
+        let mut board = Board::gravity(7, 4);
+        let mut mcts = Mcts::new(Budget::Playouts(300), Rng::new(5));
+        while board.result().is_none() {
+            let mv = mcts.choose_move(&board).expect("an unfinished game has moves");
+            board.play(mv).unwrap();
+        }
+    }
+
+    #[test]
+    fn plays_renju_games_out() {
+        let mut board = Board::new(15, 5).with_rules(Rules::Renju);
+        // each playout runs on well past the point renju starts forbidding X squares
+        let mut mcts = Mcts::new(Budget::Playouts(100), Rng::new(9));
+        for _ in 0..20 {
+            if board.result().is_some() {
+                break;
+            }
+            let mv = mcts.choose_move(&board).expect("an unfinished game has moves");
+            board.play(mv).unwrap();
+        }
+    }
+
+    #[test]
+    fn playouts_end_renju_games_as_the_board_does() {
+        // a1 to f1 and a6 to f6 but for one square each, where X would make six
+        let xs = [(1, 0), (1, 4), (2, 2), (3, 3), (3, 5), (4, 1), (4, 4), (0, 0), (0, 1)];
+        let xs = [&xs[..], &[(0, 2), (0, 4), (0, 5), (5, 0), (5, 1), (5, 3), (5, 4), (5, 5)]].concat();
+        let os = [(1, 1), (1, 2), (1, 3), (1, 5), (2, 0), (2, 1), (2, 3), (2, 4), (2, 5), (3, 0), (3, 1)];
+        let os = [&os[..], &[(3, 2), (3, 4), (4, 0), (4, 2), (4, 3), (4, 5)]].concat();
+        let mut board = Board::new(6, 5).with_rules(Rules::Renju);
+        for (&(xr, xc), &(or, oc)) in xs.iter().zip(&os) {
+            board.play(Move::new(xr, xc)).unwrap();
+            board.play(Move::new(or, oc)).unwrap();
+        }
+        assert!([(0, 3), (5, 2)].iter().all(|&(r, c)| Rules::Renju.check_square(&board, Move::new(r, c)).is_err()));
+        assert_eq!(board.result(), Some(GameResult::Win(Player::O)));
+        let mut mcts = Mcts::new(Budget::Playouts(10), Rng::new(3));
+        assert_eq!(mcts.playout(&mut board.clone()), GameResult::Win(Player::O));
+    }
+
+    #[test]
+    fn time_budget_stops() {
+        let board = Board::new(15, 5);
+        let mut mcts = Mcts::new(Budget::Time(Duration::from_millis(50)), Rng::new(0));
+        let report = mcts.search(&board);
+        assert!(report.playouts > 0);
+        assert!(report.best.is_some());
+    }
+}
@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::board::{Board, GameResult, Move, Piece, Player, Variant};
+use crate::opening::Choice;
+use crate::rules::Rules;
+use crate::strategy::Strategy;
+
+/// Score of a won game. Each mark already on the board takes one off, so
+/// quicker wins (and slower losses) score better.
+pub const WIN: i32 = 1_000_000;
+const INF: i32 = WIN + 1;
+
+/// Stop the transposition table from growing without bound on big boards.
+const TABLE_LIMIT: usize = 1 << 20;
+
+/// Thinking time per move on a gravity board, where the search can go
+/// deep but no fixed depth suits both the opening and the endgame.
+const GRAVITY_MOVE_TIME: Duration = Duration::from_millis(1000);
+
+/// How many nodes to search between looks at the clock.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Mixed into the table's keys with more than two players, where a
+/// position's score depends on whose search it is.
+const ROOT_KEY: u64 = 0x9e37_79b9_7f4a_7c15;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    depth: u32,
+    score: i32,
+    bound: Bound,
+    best: Option<Move>,
+}
+
+pub struct SearchResult {
+    pub best: Option<Move>,
+    /// From the point of view of the player to move.
+    pub score: i32,
+    pub nodes: u64,
+    /// How many moves ahead the search got.
+    pub depth: u32,
+}
+
+/// Negamax search with alpha-beta pruning and a transposition table.
+///
+/// Without a depth limit it searches to the end of the game, which is
+/// perfect play on 3x3. With one it scores the horizon with a heuristic.
+/// With a time limit it deepens one move at a time until time runs out.
+///
+/// With more than two players the search is paranoid: everyone else is
+/// assumed to be out to beat the player it's searching for, which turns
+/// the game back into one of two sides.
+pub struct Minimax {
+    depth: Option<u32>,
+    time: Option<Duration>,
+    /// When a timed search has to give up on the pass it's in.
+    deadline: Option<Instant>,
+    stopped: bool,
+    table: HashMap<u64, Entry>,
+    /// Who the search is for.
+    root: Player,
+    nodes: u64,
+    last_score: Option<i32>,
+    last_depth: u32,
+}
+
+impl Minimax {
+    pub fn perfect() -> Minimax {
+        Minimax {
+            depth: None,
+            time: None,
+            deadline: None,
+            stopped: false,
+            table: HashMap::new(),
+            root: Player::X,
+            nodes: 0,
+            last_score: None,
+            last_depth: 0,
+        }
+    }
+
+    pub fn with_depth(depth: u32) -> Minimax {
+        Minimax {
+            depth: Some(depth.max(1)),
+            ..Minimax::perfect()
+        }
+    }
+
+    /// Iterative deepening: searches one move ahead, then two, and so on,
+    /// and plays the best move of the deepest search finished in `time`.
+    pub fn timed(time: Duration) -> Minimax {
+        Minimax {
+            time: Some(time),
+            ..Minimax::perfect()
+        }
+    }
+
+    /// Searches exactly on 3x3 and to a few plies on anything bigger.
+    pub fn for_board(board: &Board) -> Minimax {
+        match board.variant() {
+            // one small board at a time keeps the branching factor low
+            Variant::Ultimate => Minimax::with_depth(6),
+            Variant::Classic if board.size() <= 3 => Minimax::perfect(),
+            Variant::Classic => Minimax::with_depth(3),
+            // no pruning to nearby squares, so not much deeper than that
+            Variant::Cube => Minimax::with_depth(4),
+            // only a handful of moves each turn, so as deep as time allows
+            Variant::Gravity => Minimax::timed(GRAVITY_MOVE_TIME),
+        }
+    }
+
+    /// How many moves ahead it looks, or `None` for the end of the game
+    /// (or, with a `time` limit, as far as it gets).
+    pub fn depth(&self) -> Option<u32> {
+        self.depth
+    }
+
+    pub fn time(&self) -> Option<Duration> {
+        self.time
+    }
+
+    /// Forgets everything learned in earlier searches.
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    pub fn search(&mut self, board: &Board) -> SearchResult {
+        self.search_for(board, board.to_move())
+    }
+
+    /// Like `search`, but scored for `player` whoever is to move, which
+    /// is what rating a move needs once more than two are playing.
+    pub fn search_for(&mut self, board: &Board, player: Player) -> SearchResult {
+        let mut board = board.clone();
+        self.root = player;
+        // every move fills a square, so no game lasts longer than this
+        let remaining = (board.squares() - board.moves_played()) as u32;
+        let depth = self.depth.map_or(remaining, |d| d.min(remaining));
+        if self.table.len() > TABLE_LIMIT {
+            self.table.clear();
+        }
+        self.nodes = 0;
+        let (score, depth) = match self.time {
+            Some(time) => self.deepen(&mut board, depth, time),
+            None => (self.negamax(&mut board, depth, -INF, INF), depth),
+        };
+        let best = self.table.get(&self.key(&board)).and_then(|e| e.best);
+        let score = if self.on_side(board.to_move()) { score } else { -score };
+        self.last_score = Some(score);
+        self.last_depth = depth;
+        SearchResult {
+            best,
+            score,
+            nodes: self.nodes,
+            depth,
+        }
+    }
+
+    /// Searches ever deeper, up to `max_depth`, until `time` is up or the
+    /// result is certain. Returns the score and depth of the last search
+    /// to finish. Each one leaves its best moves in the table, so the
+    /// next tries them first and the extra passes cost little.
+    fn deepen(&mut self, board: &mut Board, max_depth: u32, time: Duration) -> (i32, u32) {
+        let start = Instant::now();
+        // the first pass always finishes, so there's a move to play
+        self.deadline = None;
+        self.stopped = false;
+        let mut done = (self.negamax(board, 1, -INF, INF), 1);
+        self.deadline = Some(start + time);
+        for depth in 2..=max_depth {
+            if done.0.abs() > WIN / 2 {
+                break;
+            }
+            let score = self.negamax(board, depth, -INF, INF);
+            if self.stopped {
+                break;
+            }
+            done = (score, depth);
+        }
+        self.deadline = None;
+        done
+    }
+
+    /// Whether `player` is on the same side as the player searched for.
+    fn on_side(&self, player: Player) -> bool {
+        player == self.root
+    }
+
+    fn key(&self, board: &Board) -> u64 {
+        match board.players() {
+            2 => board.hash(),
+            _ => board.hash() ^ ROOT_KEY.wrapping_mul(self.root.index() as u64 + 1),
+        }
+    }
+
+    /// Scores `board` for the side to move: the player searched for, or
+    /// everyone against them.
+    fn negamax(&mut self, board: &mut Board, depth: u32, mut alpha: i32, mut beta: i32) -> i32 {
+        self.nodes += 1;
+        if let Some(deadline) = self.deadline {
+            if self.nodes.is_multiple_of(CLOCK_INTERVAL) && Instant::now() >= deadline {
+                self.stopped = true;
+            }
+        }
+        if self.stopped {
+            // the pass is thrown away, so the score doesn't matter
+            return 0;
+        }
+        let mine = self.on_side(board.to_move());
+        match board.result() {
+            // usually the side to move is the one that just lost, but not in misère
+            Some(GameResult::Win(p)) if self.on_side(p) == mine => return WIN - board.moves_played() as i32,
+            Some(GameResult::Win(_)) => return -(WIN - board.moves_played() as i32),
+            Some(GameResult::Draw) => return 0,
+            None => {}
+        }
+        if depth == 0 {
+            let score = evaluate_for(board, self.root);
+            return if mine { score } else { -score };
+        }
+
+        let alpha_orig = alpha;
+        let mut hint = None;
+        let key = self.key(board);
+        if let Some(e) = self.table.get(&key) {
+            hint = e.best;
+            if e.depth >= depth {
+                match e.bound {
+                    Bound::Exact => return e.score,
+                    Bound::Lower => alpha = alpha.max(e.score),
+                    Bound::Upper => beta = beta.min(e.score),
+                }
+                if alpha >= beta {
+                    return e.score;
+                }
+            }
+        }
+
+        let mut best_score = -INF;
+        let mut best = None;
+        for mv in candidate_moves(board, hint) {
+            board.play(mv).expect("candidate moves are legal");
+            // with more than two players the next one may be on the same side
+            let score = if self.on_side(board.to_move()) == mine {
+                self.negamax(board, depth - 1, alpha, beta)
+            } else {
+                -self.negamax(board, depth - 1, -beta, -alpha)
+            };
+            board.undo();
+            if self.stopped {
+                // half-searched, so keep it out of the table
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best = Some(mv);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best_score <= alpha_orig {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(
+            key,
+            Entry {
+                depth,
+                score: best_score,
+                bound,
+                best,
+            },
+        );
+        best_score
+    }
+}
+
+impl Strategy for Minimax {
+    fn name(&self) -> String {
+        match (self.depth, self.time) {
+            (_, Some(time)) => format!("Minimax ({}ms)", time.as_millis()),
+            (Some(d), None) => format!("Minimax (depth {})", d),
+            (None, None) => "Minimax".to_owned(),
+        }
+    }
+
+    fn choose_move(&mut self, board: &Board) -> Option<Move> {
+        self.search(board).best
+    }
+
+    /// Searches deeper and deeper until the time is up, as `timed` does,
+    /// but no deeper than any depth it was given.
+    fn think_for(&mut self, time: Duration) {
+        self.time = Some(time);
+    }
+
+    /// Takes the side the search likes better.
+    fn choose_swap(&mut self, board: &Board, _choices: &[Choice]) -> Option<Choice> {
+        Some(Choice::by_score(self.search_for(board, Player::X).score))
+    }
+
+    fn comment(&self) -> Option<String> {
+        let score = describe_score(self.last_score?);
+        match self.time {
+            Some(_) => Some(format!("{}, {} moves ahead", score, self.last_depth)),
+            None => Some(score),
+        }
+    }
+}
+
+/// Turns a search score into words: forced results, or the heuristic value.
+pub fn describe_score(score: i32) -> String {
+    // won scores are WIN less the marks on the board, which never gets near WIN / 2
+    if score > WIN / 2 {
+        "forced win".to_owned()
+    } else if score < -WIN / 2 {
+        "forced loss".to_owned()
+    } else {
+        format!("score {:+}", score)
+    }
+}
+
+/// Legal moves in the order worth trying them: the table's best move from
+/// an earlier search first, then outwards from the centre. Big boards only
+/// consider squares next to a mark already played; far-off squares are
+/// almost never the answer and would blow up the branching factor.
+pub fn candidate_moves(board: &Board, hint: Option<Move>) -> Vec<Move> {
+    let n = board.size();
+    let mut moves = board.legal_moves();
+    // ultimate already narrows the moves down to one small board most turns
+    let prune = n > 5 && board.variant() == Variant::Classic;
+    if prune && board.moves_played() > 0 {
+        moves.retain(|mv| has_neighbour(board, *mv));
+    }
+    let (centre, middle_row) = ((n - 1) as isize, (board.rows() - 1) as isize);
+    let gravity = board.variant() == Variant::Gravity;
+    moves.sort_by_key(|mv| {
+        let dl = mv.layer.map_or(0, |l| (2 * l as isize - centre).abs());
+        // where a dropped piece lands isn't up to the player, only the column
+        let dr = if gravity { 0 } else { (2 * mv.row as isize - middle_row).abs() };
+        let dc = (2 * mv.col as isize - centre).abs();
+        (Some(*mv) != hint, dl.max(dr).max(dc), dl + dr + dc)
+    });
+    if prune && board.moves_played() == 0 {
+        moves.truncate(1);
+    }
+    moves
+}
+
+fn has_neighbour(board: &Board, mv: Move) -> bool {
+    let (n, rows) = (board.size() as isize, board.rows() as isize);
+    for dr in -1..=1 {
+        for dc in -1..=1 {
+            let (r, c) = (mv.row as isize + dr, mv.col as isize + dc);
+            if (dr, dc) != (0, 0)
+                && r >= 0
+                && c >= 0
+                && r < rows
+                && c < n
+                && board.get(r as usize, c as usize).is_some()
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Heuristic score for the player to move. Every stretch of K squares
+/// that only one player has marks in is still winnable by them, and is
+/// worth more the fuller it is. In misère that's the player in trouble.
+/// Wild and numerical lines belong to nobody, so they score only what
+/// the search can see.
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_for(board, board.to_move())
+}
+
+/// Like `evaluate`, for `player` whoever is to move. Every other
+/// player's lines count against them.
+pub fn evaluate_for(board: &Board, player: Player) -> i32 {
+    match (board.variant(), board.rules()) {
+        (Variant::Ultimate, _) => evaluate_ultimate(board, player),
+        (_, Rules::Standard) | (_, Rules::Renju) => evaluate_lines(board, player),
+        (_, Rules::Misere) => -evaluate_lines(board, player),
+        (_, Rules::Wild) | (_, Rules::Numerical) => 0,
+    }
+}
+
+fn evaluate_lines(board: &Board, me: Player) -> i32 {
+    let mine = board.marks(me);
+    let mut score: i64 = 0;
+    for mask in board.line_masks() {
+        let mut theirs = Player::all(board.players())
+            .filter(|&p| p != me)
+            .map(|p| board.marks(p).common(mask))
+            .filter(|&t| t > 0);
+        match (mine.common(mask), theirs.next(), theirs.next()) {
+            (0, None, _) => {}
+            (m, None, _) => score += window_value(m),
+            (0, Some(t), None) => score -= window_value(t),
+            _ => {}
+        }
+    }
+    score.max(-(WIN as i64) / 2).min(WIN as i64 / 2) as i32
+}
+
+fn window_value(marks: u32) -> i64 {
+    8i64.pow(marks - 1)
+}
+
+const LINES_3X3: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+/// The same idea as `evaluate`, twice over: lines of small boards still
+/// open to one player, weighted well above lines of marks inside the
+/// small boards that are still being played.
+fn evaluate_ultimate(board: &Board, me: Player) -> i32 {
+    let small = board.small_results();
+    let mut score: i64 = 0;
+
+    for line in &LINES_3X3 {
+        let (mut mine, mut theirs, mut dead) = (0, 0, false);
+        for &b in line {
+            match small[b] {
+                Some(GameResult::Win(p)) if p == me => mine += 1,
+                Some(GameResult::Win(_)) => theirs += 1,
+                Some(GameResult::Draw) => dead = true,
+                None => {}
+            }
+        }
+        match (mine, theirs, dead) {
+            (m, 0, false) if m > 0 => score += 64 * window_value(m),
+            (0, t, false) if t > 0 => score -= 64 * window_value(t),
+            _ => {}
+        }
+    }
+
+    for (b, result) in small.iter().enumerate() {
+        if result.is_some() {
+            continue;
+        }
+        let (top, left) = ((b / 3) * 3, (b % 3) * 3);
+        let at = |i: usize| board.get(top + i / 3, left + i % 3);
+        for line in &LINES_3X3 {
+            let mine = line.iter().filter(|&&i| at(i) == Some(Piece::Mark(me))).count() as u32;
+            let theirs = line.iter().filter(|&&i| at(i) == Some(Piece::Mark(me.other()))).count() as u32;
+            match (mine, theirs) {
+                (m, 0) if m > 0 => score += window_value(m),
+                (0, t) if t > 0 => score -= window_value(t),
+                _ => {}
+            }
+        }
+    }
+    score as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays `bot` as `side` against every possible sequence of replies.
+    fn never_loses(board: &mut Board, bot: &mut Minimax, side: Player) {
+        match board.result() {
+            Some(GameResult::Win(p)) => assert_eq!(p, side, "lost after {:?}", board),
+            Some(GameResult::Draw) => {}
+            None if board.to_move() == side => {
+                let mv = bot.choose_move(board).unwrap();
+                board.play(mv).unwrap();
+                never_loses(board, bot, side);
+                board.undo();
+            }
+            None => {
+                for mv in board.legal_moves() {
+                    board.play(mv).unwrap();
+                    never_loses(board, bot, side);
+                    board.undo();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn perfect_bot_never_loses_on_3x3() {
+        let mut bot = Minimax::perfect();
+        never_loses(&mut Board::new(3, 3), &mut bot, Player::X);
+        never_loses(&mut Board::new(3, 3), &mut bot, Player::O);
+    }
+
+    #[test]
+    fn empty_3x3_is_a_draw() {
+        assert_eq!(Minimax::perfect().search(&Board::new(3, 3)).score, 0);
+    }
+
+    #[test]
+    fn takes_the_win_before_blocking() {
+        // X: a1 b1, O: a2 b2, X to move wins on c1 rather than blocking c2
+        let mut board = Board::new(3, 3);
+        for &(r, c) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+            board.play(Move::new(r, c)).unwrap();
+        }
+        assert_eq!(Minimax::perfect().search(&board).best, Some(Move::new(0, 2)));
+    }
+
+    #[test]
+    fn three_players_each_search_for_themselves() {
+        // X has a1 b1, O has e5 e4, Y has c3 c4, and X moves first
+        let mut board = Board::new(5, 3).with_players(3);
+        for &(r, c) in &[(0, 0), (4, 4), (2, 2), (0, 1), (3, 4), (3, 2)] {
+            board.play(Move::new(r, c)).unwrap();
+        }
+        let mut bot = Minimax::with_depth(3);
+        let result = bot.search(&board);
+        assert_eq!(result.best, Some(Move::new(0, 2)));
+        assert!(result.score > WIN / 2);
+        // and O, moving after X, sees it coming
+        assert!(bot.search_for(&board, Player::O).score < -WIN / 2);
+    }
+
+    #[test]
+    fn timed_search_deepens_until_the_clock_runs_out() {
+        // X has three stacked in d, so O has to drop on top of them
+        let mut board = Board::gravity(7, 4);
+        for &col in &[3, 0, 3, 1, 3] {
+            board.play(Move::new(board.landing(col).unwrap(), col)).unwrap();
+        }
+        let mut bot = Minimax::timed(Duration::from_millis(100));
+        let result = bot.search(&board);
+        assert_eq!(result.best, Some(Move::new(2, 3)));
+        assert!(result.depth > 1);
+        // if O looks away instead, X takes the win
+        board.play(Move::new(5, 6)).unwrap();
+        let result = bot.search(&board);
+        assert!(result.score > WIN / 2);
+        assert!(bot.comment().unwrap().contains("moves ahead"));
+    }
+
+    #[test]
+    fn depth_limited_search_blocks_on_big_boards() {
+        // O has four in a row on row 8 with one open end
+        let mut board = Board::new(15, 5);
+        let x = [(0, 0), (0, 14), (14, 0), (14, 14)];
+        for (i, &(r, c)) in x.iter().enumerate() {
+            board.play(Move::new(r, c)).unwrap();
+            board.play(Move::new(7, 3 + i)).unwrap();
+        }
+        board.play(Move::new(7, 2)).unwrap();
+        board.play(Move::new(1, 1)).unwrap();
+        assert_eq!(board.to_move(), Player::X);
+        // only the far end is open, so X has to take it
+        assert_eq!(Minimax::with_depth(2).search(&board).best, Some(Move::new(7, 7)));
+    }
+}
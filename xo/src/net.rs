@@ -0,0 +1,690 @@
+//! Two-player games over TCP.
+//!
+//! The protocol is one message per line, in UTF-8:
+//!
+//! ```text
+//! HELLO xo/1 <name>          both sides, first thing after connecting
+//! GAME <size> <k> <x|o> [variant [rules]]
+//!                            host to guest: the board, and the guest's side
+//! MOVE <square>              e.g. MOVE b2
+//! CHAT <text>
+//! RESIGN
+//! REMATCH                    after a game: both send it to play again
+//! BYE                        leaving
+//! ERROR <text>               the last message was refused
+//! ```
+//!
+//! Each side keeps its own board and checks every move it is sent, so the
+//! remote player can't move out of turn or onto a taken square. A line
+//! longer than 4 KiB ends the connection.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::board::{Board, Player, Variant};
+use crate::game::Game;
+use crate::notation::parse_move;
+use crate::rules::Rules;
+use crate::strategy::{Action, Strategy};
+
+const PROTOCOL: &str = "xo/1";
+
+/// The longest line the peer may send. A longer one ends the connection,
+/// rather than being held on to for as long as the peer keeps sending.
+const MAX_LINE: usize = 4 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Hello(String),
+    Game {
+        size: usize,
+        k: usize,
+        side: Player,
+        variant: Variant,
+        rules: Rules,
+    },
+    /// The square as sent; it is only trusted once checked against the board.
+    Move(String),
+    Chat(String),
+    Resign,
+    Rematch,
+    Bye,
+    Error(String),
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Hello(name) => write!(f, "HELLO {} {}", PROTOCOL, name),
+            Message::Game {
+                size,
+                k,
+                side,
+                variant,
+                rules,
+            } => {
+                write!(f, "GAME {} {} {}", size, k, side.to_string().to_lowercase())?;
+                if *variant != Variant::Classic || *rules != Rules::Standard {
+                    write!(f, " {}", variant.name())?;
+                }
+                if *rules != Rules::Standard {
+                    write!(f, " {}", rules.name())?;
+                }
+                Ok(())
+            }
+            Message::Move(square) => write!(f, "MOVE {}", square),
+            Message::Chat(text) => write!(f, "CHAT {}", text),
+            Message::Resign => write!(f, "RESIGN"),
+            Message::Rematch => write!(f, "REMATCH"),
+            Message::Bye => write!(f, "BYE"),
+            Message::Error(text) => write!(f, "ERROR {}", text),
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Message, String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (command, rest) = match line.split_once(' ') {
+            Some((command, rest)) => (command, rest.trim()),
+            None => (line, ""),
+        };
+        let bad = || format!("bad {} message", command);
+        match command {
+            "HELLO" => {
+                let (version, name) = rest.split_once(' ').unwrap_or((rest, ""));
+                if version != PROTOCOL {
+                    return Err(format!("unsupported protocol {}", version));
+                }
+                Ok(Message::Hello(printable(name.trim())))
+            }
+            "GAME" => {
+                let parts: Vec<&str> = rest.split_whitespace().collect();
+                let (size, k, side, variant, rules) = match parts.as_slice() {
+                    [size, k, side] => (size, k, *side, "classic", "standard"),
+                    [size, k, side, variant] => (size, k, *side, *variant, "standard"),
+                    [size, k, side, variant, rules] => (size, k, *side, *variant, *rules),
+                    _ => return Err(bad()),
+                };
+                let (size, k) = (size.parse().ok(), k.parse().ok());
+                let variant = Variant::from_name(variant).ok_or_else(bad)?;
+                let rules = Rules::from_name(rules).ok_or_else(bad)?;
+                let side = match side {
+                    "x" => Player::X,
+                    "o" => Player::O,
+                    _ => return Err(bad()),
+                };
+                match (size, k) {
+                    (Some(size), Some(k)) if Board::check_settings(variant, size, k, rules, 2).is_ok() => {
+                        Ok(Message::Game {
+                            size,
+                            k,
+                            side,
+                            variant,
+                            rules,
+                        })
+                    }
+                    _ => Err(bad()),
+                }
+            }
+            "MOVE" if !rest.is_empty() => Ok(Message::Move(printable(rest))),
+            "CHAT" => Ok(Message::Chat(printable(rest))),
+            "RESIGN" => Ok(Message::Resign),
+            "REMATCH" => Ok(Message::Rematch),
+            "BYE" => Ok(Message::Bye),
+            "ERROR" => Ok(Message::Error(printable(rest))),
+            "" => Err("empty message".to_owned()),
+            _ => Err(bad()),
+        }
+    }
+}
+
+/// `text` without control characters, which the peer could otherwise use
+/// to clear the screen, retitle the window or fake a prompt when it's
+/// printed.
+fn printable(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// The other end of the connection.
+///
+/// Lines are read on a background thread so the game can look for anything
+/// the peer sent while it wasn't their turn.
+pub struct Peer {
+    name: String,
+    writer: TcpStream,
+    incoming: Receiver<Result<Message, String>>,
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "the other player disconnected")
+}
+
+impl Peer {
+    fn new(stream: TcpStream) -> io::Result<Peer> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || loop {
+            let line = match read_line(&mut reader) {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(_) => {
+                    // too long or not text: hang up rather than read on
+                    let _ = reader.get_ref().shutdown(Shutdown::Both);
+                    break;
+                }
+            };
+            if tx.send(line.parse()).is_err() {
+                break;
+            }
+        });
+        Ok(Peer {
+            name: String::new(),
+            writer: stream,
+            incoming: rx,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn send(&mut self, msg: &Message) -> io::Result<()> {
+        writeln!(self.writer, "{}", msg)?;
+        self.writer.flush()
+    }
+
+    /// Waits for the next message, answering any line that doesn't parse.
+    pub fn recv(&mut self) -> io::Result<Message> {
+        loop {
+            match self.incoming.recv().map_err(|_| disconnected())? {
+                Ok(msg) => return Ok(msg),
+                Err(e) => self.send(&Message::Error(e))?,
+            }
+        }
+    }
+
+    /// The next message if one has already arrived.
+    fn try_recv(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            match self.incoming.try_recv() {
+                Ok(Ok(msg)) => return Ok(Some(msg)),
+                Ok(Err(e)) => self.send(&Message::Error(e))?,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(disconnected()),
+            }
+        }
+    }
+
+    fn handshake(&mut self, name: &str) -> io::Result<()> {
+        self.send(&Message::Hello(name.to_owned()))?;
+        match self.recv()? {
+            Message::Hello(theirs) => {
+                self.name = if theirs.is_empty() { "Opponent".to_owned() } else { theirs };
+                Ok(())
+            }
+            other => Err(protocol_error(&format!("expected HELLO, got {}", other))),
+        }
+    }
+}
+
+/// The next line from the peer, or `None` once they've stopped sending.
+fn read_line(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    input.take(MAX_LINE as u64 + 1).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') && line.len() > MAX_LINE {
+        return Err(protocol_error("the line is too long"));
+    }
+    String::from_utf8(line).map(Some).map_err(|_| protocol_error("the line isn't UTF-8"))
+}
+
+fn hung_up(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+    )
+}
+
+fn protocol_error(text: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, text.to_owned())
+}
+
+/// How a game played over the network stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ending {
+    /// Won, lost, drawn or resigned: `Game::result` says which.
+    Finished,
+    /// The local player quit.
+    Quit,
+    /// The peer said goodbye or hung up.
+    PeerLeft,
+}
+
+/// A connection plus the board settings both sides agreed on. The host
+/// plays X in the first game and sides swap after every rematch.
+pub struct Session {
+    peer: Peer,
+    variant: Variant,
+    rules: Rules,
+    size: usize,
+    k: usize,
+    side: Player,
+}
+
+impl Session {
+    /// Waits on `listener` for someone to join a game on a board like `board`.
+    pub fn host(listener: &TcpListener, name: &str, board: &Board) -> io::Result<Session> {
+        let (stream, _) = listener.accept()?;
+        let mut peer = Peer::new(stream)?;
+        peer.handshake(name)?;
+        let (variant, rules, size, k) = (board.variant(), board.rules(), board.size(), board.k());
+        peer.send(&Message::Game {
+            size,
+            k,
+            side: Player::O,
+            variant,
+            rules,
+        })?;
+        Ok(Session {
+            peer,
+            variant,
+            rules,
+            size,
+            k,
+            side: Player::X,
+        })
+    }
+
+    pub fn join<A: ToSocketAddrs>(addr: A, name: &str) -> io::Result<Session> {
+        let mut peer = Peer::new(TcpStream::connect(addr)?)?;
+        peer.handshake(name)?;
+        match peer.recv()? {
+            Message::Game {
+                size,
+                k,
+                side,
+                variant,
+                rules,
+            } => Ok(Session {
+                peer,
+                variant,
+                rules,
+                size,
+                k,
+                side,
+            }),
+            other => Err(protocol_error(&format!("expected GAME, got {}", other))),
+        }
+    }
+
+    pub fn peer_name(&self) -> &str {
+        self.peer.name()
+    }
+
+    /// The side the local player has in the current game.
+    pub fn side(&self) -> Player {
+        self.side
+    }
+
+    /// Plays one game, with `local` choosing this side's moves.
+    pub fn play(&mut self, local: &mut dyn Strategy) -> io::Result<(Game, Ending)> {
+        let mut names = vec![local.name(), self.peer.name.clone()];
+        if self.side == Player::O {
+            names.reverse();
+        }
+        let board = Board::with_variant(self.variant, self.size, self.k).with_rules(self.rules);
+        let mut game = Game::new(board, names);
+
+        while game.result().is_none() {
+            println!("{}", game.board());
+            let ending = if game.board().to_move() == self.side {
+                self.local_turn(&mut game, local)?
+            } else {
+                self.remote_turn(&mut game)?
+            };
+            if let Some(ending) = ending {
+                return Ok((game, ending));
+            }
+        }
+        println!("{}", game.board());
+        Ok((game, Ending::Finished))
+    }
+
+    fn local_turn(&mut self, game: &mut Game, local: &mut dyn Strategy) -> io::Result<Option<Ending>> {
+        if let Some(ending) = self.drain(game)? {
+            return Ok(Some(ending));
+        }
+        if game.result().is_some() {
+            return Ok(None);
+        }
+
+        let action = local.choose_action(game.board());
+        // the peer may have resigned or left while we were thinking
+        if let Some(ending) = self.drain(game)? {
+            return Ok(Some(ending));
+        }
+        if game.result().is_some() {
+            return Ok(None);
+        }
+
+        match action {
+            Action::Move(mv) => match game.play(mv) {
+                Ok(()) => self.peer.send(&Message::Move(mv.to_string()))?,
+                Err(e) => println!("{} can't play {}: {}.", local.name(), mv, e),
+            },
+            Action::Chat(text) => self.peer.send(&Message::Chat(text))?,
+            Action::Resign => {
+                game.resign(self.side);
+                self.peer.send(&Message::Resign)?;
+            }
+            Action::Save(path) => println!("{}", game.save(&path)),
+            Action::Undo | Action::Redo => println!("Moves can't be taken back in a network game."),
+            Action::Hint => println!("No hints against another person."),
+            Action::Quit => {
+                self.peer.send(&Message::Bye)?;
+                return Ok(Some(Ending::Quit));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Handles whatever the peer sent while it was our turn. Moves sent
+    /// then are out of turn and get refused.
+    fn drain(&mut self, game: &mut Game) -> io::Result<Option<Ending>> {
+        while let Some(msg) = self.peer.try_recv()? {
+            let ending = match msg {
+                Message::Move(_) => {
+                    self.peer.send(&Message::Error("not your turn".to_owned()))?;
+                    None
+                }
+                other => self.common(game, other)?,
+            };
+            if ending.is_some() {
+                return Ok(ending);
+            }
+        }
+        Ok(None)
+    }
+
+    fn remote_turn(&mut self, game: &mut Game) -> io::Result<Option<Ending>> {
+        println!("Waiting for {}...", self.peer.name);
+        loop {
+            match self.peer.recv()? {
+                Message::Move(square) => {
+                    let checked = parse_move(&square, game.board()).and_then(|mv| game.play(mv).map(|_| mv));
+                    match checked {
+                        Ok(mv) => {
+                            println!("{} plays {}.", self.peer.name, mv);
+                            return Ok(None);
+                        }
+                        Err(e) => self.peer.send(&Message::Error(e.to_string()))?,
+                    }
+                }
+                other => {
+                    let ending = self.common(game, other)?;
+                    if ending.is_some() || game.result().is_some() {
+                        return Ok(ending);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Messages that mean the same whoever's turn it is.
+    fn common(&mut self, game: &mut Game, msg: Message) -> io::Result<Option<Ending>> {
+        match msg {
+            Message::Chat(text) => println!("{}: {}", self.peer.name, text),
+            Message::Resign => {
+                println!("{} resigns.", self.peer.name);
+                game.resign(self.side.other());
+            }
+            Message::Bye => return Ok(Some(Ending::PeerLeft)),
+            Message::Error(text) => println!("{} refused that: {}.", self.peer.name, text),
+            other => {
+                let text = format!("unexpected {}", other);
+                self.peer.send(&Message::Error(text))?;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Asks the peer for another game. Returns whether both want one, in
+    /// which case the sides are swapped for it.
+    pub fn rematch(&mut self, wanted: bool) -> io::Result<bool> {
+        let sent = self.peer.send(if wanted { &Message::Rematch } else { &Message::Bye });
+        match sent {
+            // they've already gone, which is an answer too
+            Err(e) if hung_up(&e) => return Ok(false),
+            other => other?,
+        }
+        if !wanted {
+            return Ok(false);
+        }
+        loop {
+            match self.peer.recv() {
+                Ok(Message::Rematch) => {
+                    self.side = self.side.other();
+                    return Ok(true);
+                }
+                Ok(Message::Bye) => return Ok(false),
+                Ok(Message::Chat(text)) => println!("{}: {}", self.peer.name, text),
+                // a move or resignation that crossed the end of the game
+                Ok(_) => {}
+                Err(e) if hung_up(&e) => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{GameResult, Move};
+
+    /// Plays a fixed list of moves, then resigns if asked to go on.
+    struct Scripted(Vec<Move>);
+
+    impl Strategy for Scripted {
+        fn name(&self) -> String {
+            "script".to_owned()
+        }
+
+        fn choose_move(&mut self, _board: &Board) -> Option<Move> {
+            if self.0.is_empty() {
+                None
+            } else {
+                Some(self.0.remove(0))
+            }
+        }
+
+        fn choose_action(&mut self, board: &Board) -> Action {
+            match self.choose_move(board) {
+                Some(mv) => Action::Move(mv),
+                None => Action::Resign,
+            }
+        }
+    }
+
+    fn listener() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        (listener, addr)
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = vec![
+            Message::Hello("Ann Lee".to_owned()),
+            Message::Game {
+                size: 15,
+                k: 5,
+                side: Player::O,
+                variant: Variant::Classic,
+                rules: Rules::Standard,
+            },
+            Message::Game {
+                size: 9,
+                k: 3,
+                side: Player::X,
+                variant: Variant::Ultimate,
+                rules: Rules::Standard,
+            },
+            Message::Game {
+                size: 3,
+                k: 3,
+                side: Player::O,
+                variant: Variant::Classic,
+                rules: Rules::Misere,
+            },
+            Message::Move("h8".to_owned()),
+            Message::Move("b2=5".to_owned()),
+            Message::Chat("good luck".to_owned()),
+            Message::Resign,
+            Message::Rematch,
+            Message::Bye,
+            Message::Error("not your turn".to_owned()),
+        ];
+        for msg in messages {
+            assert_eq!(msg.to_string().parse(), Ok(msg));
+        }
+        assert!("HELLO xo/2 bob".parse::<Message>().is_err());
+        assert!("GAME 3 4 x".parse::<Message>().is_err());
+        assert!("GAME 9 3 x ultimate wild".parse::<Message>().is_err());
+        assert!("MOVE".parse::<Message>().is_err());
+    }
+
+    #[test]
+    fn escapes_are_not_passed_on() {
+        let hello = "HELLO xo/1 \x1b]0;pwned\x07Eve".parse();
+        assert_eq!(hello, Ok(Message::Hello("]0;pwnedEve".to_owned())));
+        assert_eq!("CHAT \x1b[2Jhi\u{9b}H".parse(), Ok(Message::Chat("[2JhiH".to_owned())));
+        assert_eq!("ERROR no\rYour move:".parse(), Ok(Message::Error("noYour move:".to_owned())));
+    }
+
+    #[test]
+    fn endless_lines_end_the_connection() {
+        let (listener, addr) = listener();
+        let mut out = TcpStream::connect(addr.as_str()).unwrap();
+        let mut peer = Peer::new(listener.accept().unwrap().0).unwrap();
+        writeln!(out, "CHAT hi").unwrap();
+        assert_eq!(peer.recv().unwrap(), Message::Chat("hi".to_owned()));
+        // never a newline, however long it goes on
+        let _ = out.write_all("a".repeat(2 * MAX_LINE).as_bytes());
+        assert_eq!(peer.recv().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(out.read(&mut [0; 16]).unwrap_or(0), 0, "still connected");
+    }
+
+    #[test]
+    fn two_threads_play_a_game_and_a_rematch() {
+        let (listener, addr) = listener();
+        let host = thread::spawn(move || {
+            let mut session = Session::host(&listener, "host", &Board::new(3, 3)).unwrap();
+            // X wins down the a column
+            let mut moves = Scripted(vec![Move::new(0, 0), Move::new(1, 0), Move::new(2, 0)]);
+            let (game, ending) = session.play(&mut moves).unwrap();
+            assert_eq!(ending, Ending::Finished);
+            assert!(session.rematch(true).unwrap());
+            assert_eq!(session.side(), Player::O);
+            // the guest resigns straight away in the rematch
+            let (rematch, _) = session.play(&mut Scripted(vec![])).unwrap();
+            (game.result(), rematch.result())
+        });
+
+        let mut session = Session::join(addr.as_str(), "guest").unwrap();
+        assert_eq!(session.peer_name(), "host");
+        assert_eq!(session.side(), Player::O);
+        let mut moves = Scripted(vec![Move::new(0, 1), Move::new(1, 1)]);
+        let (game, _) = session.play(&mut moves).unwrap();
+        assert!(session.rematch(true).unwrap());
+        let (rematch, _) = session.play(&mut Scripted(vec![])).unwrap();
+
+        let (host_result, host_rematch) = host.join().unwrap();
+        assert_eq!(game.result(), Some(GameResult::Win(Player::X)));
+        assert_eq!(host_result, game.result());
+        assert_eq!(rematch.result(), Some(GameResult::Win(Player::O)));
+        assert_eq!(host_rematch, rematch.result());
+    }
+
+    #[test]
+    fn remote_cannot_cheat() {
+        let (listener, addr) = listener();
+        let host = thread::spawn(move || {
+            let mut session = Session::host(&listener, "host", &Board::new(3, 3)).unwrap();
+            session.play(&mut Scripted(vec![Move::new(1, 1)])).unwrap()
+        });
+
+        // a hand-rolled client that breaks the rules
+        let stream = TcpStream::connect(addr.as_str()).unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        let mut out = stream;
+        let mut next = || lines.next().unwrap().unwrap();
+        writeln!(out, "HELLO xo/1 cheat").unwrap();
+        assert_eq!(next(), "HELLO xo/1 host");
+        assert_eq!(next(), "GAME 3 3 o");
+        assert_eq!(next(), "MOVE b2");
+        writeln!(out, "MOVE b2").unwrap();
+        assert_eq!(next(), "ERROR square b2 already taken");
+        writeln!(out, "MOVE z9").unwrap();
+        assert_eq!(next(), "ERROR column z out of range");
+        writeln!(out, "FLIP table").unwrap();
+        assert_eq!(next(), "ERROR bad FLIP message");
+        writeln!(out, "MOVE a1").unwrap();
+        // now it's the host's turn, and its script has run out, so it resigns
+        assert_eq!(next(), "RESIGN");
+
+        let (game, ending) = host.join().unwrap();
+        assert_eq!(ending, Ending::Finished);
+        assert_eq!(game.board().moves(), &[Move::new(1, 1), Move::new(0, 0)]);
+        assert_eq!(game.result(), Some(GameResult::Win(Player::O)));
+    }
+
+    /// Takes its time over every move.
+    struct Slow(Scripted);
+
+    impl Strategy for Slow {
+        fn name(&self) -> String {
+            self.0.name()
+        }
+
+        fn choose_move(&mut self, board: &Board) -> Option<Move> {
+            thread::sleep(std::time::Duration::from_millis(200));
+            self.0.choose_move(board)
+        }
+    }
+
+    #[test]
+    fn moves_out_of_turn_are_refused() {
+        let (listener, addr) = listener();
+        let host = thread::spawn(move || {
+            let mut session = Session::host(&listener, "host", &Board::new(3, 3)).unwrap();
+            session.play(&mut Slow(Scripted(vec![Move::new(1, 1)]))).unwrap()
+        });
+
+        // the guest moves while the host, playing X, is still thinking
+        let stream = TcpStream::connect(addr.as_str()).unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        let mut out = stream;
+        let mut next = || lines.next().unwrap().unwrap();
+        writeln!(out, "HELLO xo/1 eager").unwrap();
+        assert_eq!(next(), "HELLO xo/1 host");
+        assert_eq!(next(), "GAME 3 3 o");
+        writeln!(out, "MOVE a1").unwrap();
+        assert_eq!(next(), "ERROR not your turn");
+        assert_eq!(next(), "MOVE b2");
+        writeln!(out, "BYE").unwrap();
+
+        let (game, ending) = host.join().unwrap();
+        assert_eq!(ending, Ending::PeerLeft);
+        assert_eq!(game.board().moves(), &[Move::new(1, 1)]);
+    }
+}
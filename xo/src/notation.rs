@@ -0,0 +1,228 @@
+use crate::board::{Board, Move, MoveError, Piece, Variant};
+
+/// Reads a move as a player types it, always column before row:
+///
+/// - `b2`, `B2`, `b 2`, `k12`: column letter then row number
+/// - `2,3` or `2 3`: column number then row number
+/// - `23`: one digit each, on boards up to 9x9
+///
+/// On a cube the layer number comes first, in front of any of those:
+/// `2b3`, `2 b3`, `2:b3` or `2 2,3`. When the rules let players choose
+/// what to put down, that goes last: `b2=O`, `b2 o` or `b2=5`. On a
+/// gravity board the column is enough, `d` or `4`: the piece drops.
+///
+/// The square is checked against `board`, so an `Ok` move can be played.
+pub fn parse_move(input: &str, board: &Board) -> Result<Move, MoveError> {
+    let s = input.trim().to_ascii_lowercase();
+    let unrecognised = || MoveError::Unrecognised(input.trim().to_owned());
+    if s.is_empty() {
+        return Err(MoveError::Empty);
+    }
+    let (s, piece) = match split_piece(&s, board) {
+        Some((square, piece)) => (square, Some(piece.ok_or_else(unrecognised)?)),
+        None => (s.as_str(), None),
+    };
+    let mut mv = if let Some(col) = dropped(s, board) {
+        let col = col?;
+        let row = board.landing(col).ok_or_else(|| MoveError::Forbidden(format!("column {} is full", s)))?;
+        Move::new(row, col)
+    } else if board.layers() == 1 {
+        square(s, board, &unrecognised)?
+    } else {
+        layered(s, board, &unrecognised)?
+    };
+    mv.piece = piece;
+    board.check(mv)?;
+    Ok(mv)
+}
+
+/// Splits the piece off the end of a move, if the rules have pieces to
+/// choose and there is one: anything after `=`, or a last word that
+/// reads as a piece.
+fn split_piece<'a>(s: &'a str, board: &Board) -> Option<(&'a str, Option<Piece>)> {
+    let rules = board.rules();
+    if !rules.picks_pieces() {
+        return None;
+    }
+    if let Some((square, piece)) = s.rsplit_once('=') {
+        return Some((square.trim(), rules.parse_piece(piece.trim())));
+    }
+    let (square, piece) = s.rsplit_once(char::is_whitespace)?;
+    let piece = rules.parse_piece(piece)?;
+    Some((square.trim(), Some(piece)))
+}
+
+/// The column of a move given as just a column, letter or number, on a
+/// gravity board. `None` if that isn't what it is.
+fn dropped(s: &str, board: &Board) -> Option<Result<usize, MoveError>> {
+    if board.variant() != Variant::Gravity {
+        return None;
+    }
+    let col = match s.as_bytes() {
+        &[letter] if letter.is_ascii_lowercase() => (letter - b'a') as usize + 1,
+        _ => number(s)?,
+    };
+    if col == 0 || col > board.size() {
+        return Some(Err(MoveError::ColumnOutOfRange(s.to_owned())));
+    }
+    Some(Ok(col - 1))
+}
+
+fn layered(s: &str, board: &Board, unrecognised: &dyn Fn() -> MoveError) -> Result<Move, MoveError> {
+    if s.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return Err(MoveError::NoLayer);
+    }
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let layer = number(&s[..digits]).ok_or_else(unrecognised)?;
+    let rest = s[digits..].trim_start_matches(|c: char| c == ',' || c == ':' || c.is_whitespace());
+    if rest.is_empty() {
+        return Err(unrecognised());
+    }
+    if layer == 0 || layer > board.layers() {
+        return Err(MoveError::LayerOutOfRange(layer));
+    }
+    let mv = square(rest, board, unrecognised)?;
+    Ok(Move::layered(layer - 1, mv.row, mv.col))
+}
+
+/// The column and row part of a move, checked against the board's size
+/// but not yet against what's on it.
+fn square(s: &str, board: &Board, unrecognised: &dyn Fn() -> MoveError) -> Result<Move, MoveError> {
+    let first = s.chars().next().ok_or(MoveError::Empty)?;
+
+    let (col, row) = if first.is_ascii_lowercase() {
+        let rest = s[1..].trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        let row = number(rest).ok_or_else(unrecognised)?;
+        let col = (first as u8 - b'a') as usize;
+        if col >= board.size() {
+            return Err(MoveError::ColumnOutOfRange(first.to_string()));
+        }
+        (col, row)
+    } else if first.is_ascii_digit() {
+        let parts: Vec<&str> = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|p| !p.is_empty())
+            .collect();
+        let (col, row) = match parts.as_slice() {
+            [col, row] => (number(col), number(row)),
+            [both] if both.len() == 2 && board.size() <= 9 => (number(&both[..1]), number(&both[1..])),
+            _ => (None, None),
+        };
+        let (col, row) = (col.ok_or_else(unrecognised)?, row.ok_or_else(unrecognised)?);
+        if col == 0 || col > board.size() {
+            return Err(MoveError::ColumnOutOfRange(col.to_string()));
+        }
+        (col - 1, row)
+    } else {
+        return Err(unrecognised());
+    };
+
+    if row == 0 || row > board.rows() {
+        return Err(MoveError::RowOutOfRange(row));
+    }
+    Ok(Move::new(row - 1, col))
+}
+
+/// Parses a plain run of digits; no signs, no spaces.
+fn number(s: &str) -> Option<usize> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Player;
+    use crate::rules::Rules;
+
+    #[test]
+    fn reads_every_notation() {
+        let board = Board::new(3, 3);
+        let b2 = Ok(Move::new(1, 1));
+        for input in &["b2", "B2", "b 2", " b,2 ", "2,2", "2 2", "22"] {
+            assert_eq!(parse_move(input, &board), b2, "{:?}", input);
+        }
+        assert_eq!(parse_move("k12", &Board::new(15, 5)), Ok(Move::new(11, 10)));
+        assert_eq!(parse_move("11,12", &Board::new(15, 5)), Ok(Move::new(11, 10)));
+    }
+
+    #[test]
+    fn explains_bad_moves() {
+        let mut board = Board::new(3, 3);
+        board.play(Move::new(0, 0)).unwrap();
+        let cases = vec![
+            ("", MoveError::Empty),
+            ("zz", MoveError::Unrecognised("zz".to_owned())),
+            ("b", MoveError::Unrecognised("b".to_owned())),
+            ("2", MoveError::Unrecognised("2".to_owned())),
+            ("?!", MoveError::Unrecognised("?!".to_owned())),
+            ("d1", MoveError::ColumnOutOfRange("d".to_owned())),
+            ("4,1", MoveError::ColumnOutOfRange("4".to_owned())),
+            ("a4", MoveError::RowOutOfRange(4)),
+            ("a0", MoveError::RowOutOfRange(0)),
+            ("a1", MoveError::SquareTaken(Move::new(0, 0))),
+        ];
+        for (input, err) in cases {
+            assert_eq!(parse_move(input, &board), Err(err), "{:?}", input);
+        }
+        assert_eq!(MoveError::RowOutOfRange(4).to_string(), "row 4 out of range");
+        assert_eq!(MoveError::SquareTaken(Move::new(0, 0)).to_string(), "square a1 already taken");
+    }
+
+    #[test]
+    fn cube_moves_lead_with_the_layer() {
+        let board = Board::cube(4, 4);
+        let b3 = Ok(Move::layered(1, 2, 1));
+        for input in &["2b3", "2 b3", "2:B3", "2 2,3", "2 23"] {
+            assert_eq!(parse_move(input, &board), b3, "{:?}", input);
+        }
+        assert_eq!(Move::layered(1, 2, 1).to_string(), "2b3");
+        assert_eq!(parse_move("b3", &board), Err(MoveError::NoLayer));
+        assert_eq!(parse_move("5b3", &board), Err(MoveError::LayerOutOfRange(5)));
+        assert_eq!(parse_move("0b3", &board), Err(MoveError::LayerOutOfRange(0)));
+        assert_eq!(parse_move("2", &board), Err(MoveError::Unrecognised("2".to_owned())));
+        assert_eq!(parse_move("2e3", &board), Err(MoveError::ColumnOutOfRange("e".to_owned())));
+    }
+
+    #[test]
+    fn pieces_go_last() {
+        let wild = Board::new(3, 3).with_rules(Rules::Wild);
+        let b2 = |piece| Ok(Move::new(1, 1).with_piece(Some(piece)));
+        for input in &["b2=O", "b2=o", "b2 o", "2,2 = o", "22 O"] {
+            assert_eq!(parse_move(input, &wild), b2(Piece::Mark(Player::O)), "{:?}", input);
+        }
+        // leaving it off plays your own mark
+        assert_eq!(parse_move("b 2", &wild), Ok(Move::new(1, 1)));
+        assert_eq!(parse_move("b2=5", &wild), Err(MoveError::Unrecognised("b2=5".to_owned())));
+
+        let numerical = Board::new(3, 3).with_rules(Rules::Numerical);
+        assert_eq!(parse_move("b2 5", &numerical), b2(Piece::Number(5)));
+        assert_eq!(Move::new(1, 1).with_piece(Some(Piece::Number(5))).to_string(), "b2=5");
+        assert!(matches!(parse_move("b2=4", &numerical), Err(MoveError::Forbidden(_))));
+        assert!(matches!(parse_move("b2", &numerical), Err(MoveError::Forbidden(_))));
+    }
+
+    #[test]
+    fn gravity_takes_just_the_column() {
+        let mut board = Board::gravity(7, 4);
+        assert_eq!(parse_move("d", &board), Ok(Move::new(5, 3)));
+        assert_eq!(parse_move("4", &board), Ok(Move::new(5, 3)));
+        assert_eq!(parse_move("d6", &board), Ok(Move::new(5, 3)));
+        assert!(matches!(parse_move("d1", &board), Err(MoveError::Forbidden(_))));
+        assert_eq!(parse_move("d7", &board), Err(MoveError::RowOutOfRange(7)));
+        assert_eq!(parse_move("h", &board), Err(MoveError::ColumnOutOfRange("h".to_owned())));
+        for _ in 0..6 {
+            let mv = parse_move("d", &board).unwrap();
+            board.play(mv).unwrap();
+        }
+        assert_eq!(parse_move("D", &board), Err(MoveError::Forbidden("column d is full".to_owned())));
+    }
+
+    #[test]
+    fn two_digit_shorthand_is_only_for_small_boards() {
+        let board = Board::new(12, 5);
+        assert_eq!(parse_move("12", &board), Err(MoveError::Unrecognised("12".to_owned())));
+    }
+}
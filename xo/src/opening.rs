@@ -0,0 +1,223 @@
+//! Opening protocols, which take the first player's advantage away on big
+//! boards by making the players agree on a start before either knows
+//! which side they'll play.
+//!
+//! Under swap2 one player puts down the first three stones, two X and an
+//! O. The other then chooses to play X, to play O, or to put down two
+//! more (an O and an X) and leave the choice to the first player. Whoever
+//! ends up with O moves next.
+//!
+//! The two players are seats here: seat 0 opens, seat 1 answers, and
+//! which of them plays X is only settled once the choosing is done.
+
+use std::fmt;
+
+use crate::board::{Board, Player};
+
+/// How a game gets started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Opening {
+    /// X simply moves first.
+    Free,
+    Swap2,
+}
+
+impl Opening {
+    pub fn name(self) -> &'static str {
+        match self {
+            Opening::Free => "free",
+            Opening::Swap2 => "swap2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Opening> {
+        match name {
+            "free" => Some(Opening::Free),
+            "swap2" => Some(Opening::Swap2),
+            _ => None,
+        }
+    }
+
+    /// A fresh opening to play through, or `None` when there's nothing to
+    /// decide.
+    pub fn start(self) -> Option<Swap2> {
+        match self {
+            Opening::Free => None,
+            Opening::Swap2 => Some(Swap2::new()),
+        }
+    }
+}
+
+/// What the player choosing in swap2 can say.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Choice {
+    PlayX,
+    PlayO,
+    /// Put down an O and an X, and let the other player choose.
+    PlaceTwo,
+}
+
+impl Choice {
+    /// How it's typed and sent in the engine protocol: `x`, `o` or `two`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Choice::PlayX => "x",
+            Choice::PlayO => "o",
+            Choice::PlaceTwo => "two",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Choice> {
+        match name {
+            "x" | "X" => Some(Choice::PlayX),
+            "o" | "O" => Some(Choice::PlayO),
+            "two" => Some(Choice::PlaceTwo),
+            _ => None,
+        }
+    }
+
+    /// The colour to take, going by a score for X: X if it's ahead,
+    /// otherwise O, which has the next move.
+    pub fn by_score(score_for_x: i32) -> Choice {
+        if score_for_x > 0 {
+            Choice::PlayX
+        } else {
+            Choice::PlayO
+        }
+    }
+}
+
+impl fmt::Display for Choice {
+    /// What the player does, to follow "chooses to".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Choice::PlayX => "play X",
+            Choice::PlayO => "play O",
+            Choice::PlaceTwo => "put down two more stones",
+        })
+    }
+}
+
+const FIRST_CHOICES: [Choice; 3] = [Choice::PlayX, Choice::PlayO, Choice::PlaceTwo];
+
+const SECOND_CHOICES: [Choice; 2] = [Choice::PlayX, Choice::PlayO];
+
+/// Stones on the board when seat 1 chooses, and when seat 0 does if it
+/// has to.
+const FIRST_STONES: usize = 3;
+const SECOND_STONES: usize = 5;
+
+/// Where a swap2 opening has got to. The stones are on the board; this
+/// only remembers what was chosen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Swap2 {
+    first: Option<Choice>,
+    second: Option<Choice>,
+}
+
+impl Swap2 {
+    pub fn new() -> Swap2 {
+        Swap2::default()
+    }
+
+    /// The seat that has to choose on `board` and what it can choose, if
+    /// it's time for a choice.
+    pub fn pending(&self, board: &Board) -> Option<(usize, &'static [Choice])> {
+        match (self.first, self.second, board.moves_played()) {
+            (None, _, FIRST_STONES) => Some((1, &FIRST_CHOICES)),
+            (Some(Choice::PlaceTwo), None, SECOND_STONES) => Some((0, &SECOND_CHOICES)),
+            _ => None,
+        }
+    }
+
+    /// Makes the pending choice, or says why `choice` can't be made.
+    pub fn choose(&mut self, board: &Board, choice: Choice) -> Result<(), String> {
+        match self.pending(board) {
+            Some((_, choices)) if choices.contains(&choice) => {}
+            Some(_) => return Err(format!("you can't {} now", choice)),
+            None => return Err("there's nothing to choose now".to_owned()),
+        }
+        match self.first {
+            None => self.first = Some(choice),
+            Some(_) => self.second = Some(choice),
+        }
+        Ok(())
+    }
+
+    /// Whether each seat knows which side it's playing.
+    pub fn is_settled(&self) -> bool {
+        self.x_seat().is_some()
+    }
+
+    fn x_seat(&self) -> Option<usize> {
+        match (self.first, self.second) {
+            (Some(Choice::PlayX), _) | (Some(Choice::PlaceTwo), Some(Choice::PlayO)) => Some(1),
+            (Some(Choice::PlayO), _) | (Some(Choice::PlaceTwo), Some(Choice::PlayX)) => Some(0),
+            _ => None,
+        }
+    }
+
+    /// The seat playing `player`'s side. Until that's settled, the opener
+    /// counts as X.
+    pub fn seat_of(&self, player: Player) -> usize {
+        let x = self.x_seat().unwrap_or(0);
+        if player == Player::X {
+            x
+        } else {
+            1 - x
+        }
+    }
+
+    /// The seat that puts the next stone down on `board`, or has to choose.
+    pub fn seat(&self, board: &Board) -> usize {
+        if let Some((seat, _)) = self.pending(board) {
+            return seat;
+        }
+        match (self.x_seat(), self.first) {
+            (Some(_), _) => self.seat_of(board.to_move()),
+            (None, Some(Choice::PlaceTwo)) => 1,
+            (None, _) => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Move;
+
+    fn after(moves: usize) -> Board {
+        let mut board = Board::new(15, 5);
+        for i in 0..moves {
+            board.play(Move::new(7, i)).unwrap();
+        }
+        board
+    }
+
+    #[test]
+    fn answering_seat_picks_a_side() {
+        let mut swap2 = Swap2::new();
+        assert_eq!((0..3).map(|n| swap2.seat(&after(n))).collect::<Vec<_>>(), [0, 0, 0]);
+        assert_eq!(swap2.pending(&after(3)), Some((1, &FIRST_CHOICES[..])));
+        assert_eq!(swap2.choose(&after(2), Choice::PlayO), Err("there's nothing to choose now".to_owned()));
+        swap2.choose(&after(3), Choice::PlayX).unwrap();
+        // the opener takes O and moves next
+        assert_eq!((swap2.seat(&after(3)), swap2.seat(&after(4))), (0, 1));
+        assert_eq!((swap2.seat_of(Player::X), swap2.seat_of(Player::O)), (1, 0));
+        assert!(swap2.is_settled());
+        assert_eq!(swap2.pending(&after(5)), None);
+    }
+
+    #[test]
+    fn two_more_stones_hand_the_choice_back() {
+        let mut swap2 = Swap2::new();
+        swap2.choose(&after(3), Choice::PlaceTwo).unwrap();
+        assert!(!swap2.is_settled());
+        assert_eq!((swap2.seat(&after(3)), swap2.seat(&after(4))), (1, 1));
+        assert_eq!(swap2.pending(&after(5)), Some((0, &SECOND_CHOICES[..])));
+        assert!(swap2.choose(&after(5), Choice::PlaceTwo).is_err());
+        swap2.choose(&after(5), Choice::PlayX).unwrap();
+        assert_eq!((swap2.seat(&after(5)), swap2.seat(&after(6))), (1, 0));
+        assert_eq!(swap2.seat_of(Player::X), 0);
+    }
+}
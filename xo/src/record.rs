@@ -0,0 +1,337 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use crate::board::{Board, GameResult, Move, MoveError, Player, Variant, MAX_SIZE};
+use crate::notation::parse_move;
+use crate::rules::Rules;
+
+/// A game written down: header tags, then the numbered move list.
+///
+/// ```text
+/// [X "Player X"]
+/// [O "Minimax"]
+/// [Size "3"]
+/// [K "3"]
+/// [Result "draw"]
+///
+/// 1. b2 a1 2. c3 a3 3. a2 c2 4. b1 b3 5. c1
+/// ```
+///
+/// Games other than classic have a `Variant` tag, e.g. `[Variant "ultimate"]`,
+/// and games under other rules a `Rules` tag, e.g. `[Rules "misere"]`.
+/// With more than two players the others are named by their marks in
+/// turn order, `[Y "..."]` and so on, and a move number starts each round.
+/// `Result` is the winner's mark, `draw`, or `*` for a game still in
+/// progress. A win the moves don't reach on the board means the other
+/// side resigned.
+/// Unknown tags are ignored so older builds can read newer records.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRecord {
+    /// One per player, in turn order.
+    pub names: Vec<String>,
+    pub variant: Variant,
+    pub rules: Rules,
+    pub size: usize,
+    pub k: usize,
+    pub result: Option<GameResult>,
+    pub moves: Vec<Move>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    /// A header line that isn't `[Name "value"]`.
+    BadTag(String),
+    /// A tag we know with a value we can't use.
+    BadValue(&'static str, String),
+    /// Move number `ply` (counted from 1) can't be played.
+    IllegalMove(usize, MoveError),
+    /// The header claims a result the moves don't reach.
+    ResultMismatch,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::BadTag(line) => write!(f, "malformed tag line: {}", line),
+            RecordError::BadValue(tag, value) => write!(f, "bad {} tag: \"{}\"", tag, value),
+            RecordError::IllegalMove(ply, e) => write!(f, "move {}: {}", ply, e),
+            RecordError::ResultMismatch => write!(f, "the moves don't reach the recorded result"),
+        }
+    }
+}
+
+impl Error for RecordError {}
+
+impl GameRecord {
+    /// Plays the moves out on a fresh board, checking each one and that
+    /// the game ends the way the header says.
+    pub fn replay(&self) -> Result<Board, RecordError> {
+        let mut board = self.board();
+        for (i, &mv) in self.moves.iter().enumerate() {
+            board.play(mv).map_err(|e| RecordError::IllegalMove(i + 1, e))?;
+        }
+        check_result(&board, self.result)?;
+        Ok(board)
+    }
+
+    /// The empty board the game was played on.
+    pub fn board(&self) -> Board {
+        Board::with_variant(self.variant, self.size, self.k)
+            .with_rules(self.rules)
+            .with_players(self.names.len())
+    }
+
+    pub fn load(path: &str) -> Result<GameRecord, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+        text.parse().map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+fn check_result(board: &Board, recorded: Option<GameResult>) -> Result<(), RecordError> {
+    match (board.result(), recorded) {
+        (played, recorded) if played == recorded => Ok(()),
+        // resigned before the end
+        (None, Some(GameResult::Win(_))) => Ok(()),
+        _ => Err(RecordError::ResultMismatch),
+    }
+}
+
+fn result_tag(result: Option<GameResult>) -> String {
+    match result {
+        Some(GameResult::Win(p)) => p.to_string(),
+        Some(GameResult::Draw) => "draw".to_owned(),
+        None => "*".to_owned(),
+    }
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (p, name) in Player::all(self.names.len()).zip(&self.names) {
+            writeln!(f, "[{} \"{}\"]", p, name)?;
+        }
+        if self.variant != Variant::Classic {
+            writeln!(f, "[Variant \"{}\"]", self.variant.name())?;
+        }
+        if self.rules != Rules::Standard {
+            writeln!(f, "[Rules \"{}\"]", self.rules.name())?;
+        }
+        writeln!(f, "[Size \"{}\"]", self.size)?;
+        writeln!(f, "[K \"{}\"]", self.k)?;
+        writeln!(f, "[Result \"{}\"]", result_tag(self.result))?;
+        writeln!(f)?;
+        let mut line = String::new();
+        let players = self.names.len();
+        for (i, mv) in self.moves.iter().enumerate() {
+            if i % players == 0 {
+                // keep lines short enough to read in a terminal
+                if i > 0 && i % (10 * players) == 0 {
+                    writeln!(f, "{}", line.trim_end())?;
+                    line.clear();
+                }
+                line += &format!("{}. ", i / players + 1);
+            }
+            line += &format!("{} ", mv);
+        }
+        writeln!(f, "{}", line.trim_end())
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<GameRecord, RecordError> {
+        let mut record = GameRecord {
+            names: Player::all(2).map(|p| format!("Player {}", p)).collect(),
+            variant: Variant::Classic,
+            rules: Rules::Standard,
+            size: 3,
+            k: 3,
+            result: None,
+            moves: Vec::new(),
+        };
+        let mut k = None;
+        let mut lines = s.lines().map(str::trim).peekable();
+
+        while let Some(line) = lines.peek().copied() {
+            if line.is_empty() {
+                lines.next();
+                continue;
+            }
+            if !line.starts_with('[') {
+                break;
+            }
+            lines.next();
+            let (name, value) = parse_tag(line).ok_or_else(|| RecordError::BadTag(line.to_owned()))?;
+            let bad = |tag| RecordError::BadValue(tag, value.to_owned());
+            match name {
+                // a player's name, under their mark
+                tag if mark(tag).is_some() => {
+                    let p = mark(tag).expect("just checked").index();
+                    while record.names.len() <= p {
+                        let next = Player::nth(record.names.len());
+                        record.names.push(format!("Player {}", next));
+                    }
+                    record.names[p] = value.to_owned();
+                }
+                "Variant" => record.variant = Variant::from_name(value).ok_or_else(|| bad("Variant"))?,
+                "Rules" => record.rules = Rules::from_name(value).ok_or_else(|| bad("Rules"))?,
+                "Size" => {
+                    record.size = value.parse().ok().filter(|n| (1..=MAX_SIZE).contains(n)).ok_or_else(|| bad("Size"))?
+                }
+                "K" => k = Some(value.parse().map_err(|_| bad("K"))?),
+                "Result" => {
+                    record.result = match value {
+                        "draw" => Some(GameResult::Draw),
+                        "*" => None,
+                        _ => Some(GameResult::Win(mark(value).ok_or_else(|| bad("Result"))?)),
+                    }
+                }
+                _ => {}
+            }
+        }
+        record.k = k.unwrap_or(record.size);
+        if record.k == 0 || record.k > record.size {
+            return Err(RecordError::BadValue("K", record.k.to_string()));
+        }
+
+        if record.rules.supports(record.variant, record.size, record.k).is_err() {
+            return Err(RecordError::BadValue("Rules", record.rules.name().to_owned()));
+        }
+        let players = record.names.len();
+        if Board::check_settings(record.variant, record.size, record.k, record.rules, players).is_err() {
+            return Err(RecordError::BadValue("players", players.to_string()));
+        }
+
+        // parse against a live board so mistakes point at the right move
+        let mut board = record.board();
+        record.size = board.size();
+        record.k = board.k();
+        let tokens = lines.flat_map(str::split_whitespace).filter(|t| !t.ends_with('.'));
+        for (i, token) in tokens.enumerate() {
+            let mv = parse_move(token, &board).map_err(|e| RecordError::IllegalMove(i + 1, e))?;
+            board.play(mv).map_err(|e| RecordError::IllegalMove(i + 1, e))?;
+            record.moves.push(mv);
+        }
+        check_result(&board, record.result)?;
+        Ok(record)
+    }
+}
+
+/// The player whose mark is `s`, if it's one.
+fn mark(s: &str) -> Option<Player> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Player::from_letter(c),
+        _ => None,
+    }
+}
+
+/// Splits `[Name "value"]` into its parts.
+fn parse_tag(line: &str) -> Option<(&str, &str)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAWN: &str = "[X \"Alice\"]
+[O \"Minimax\"]
+[Size \"3\"]
+[K \"3\"]
+[Result \"draw\"]
+
+1. b2 a1 2. c3 a3 3. a2 c2 4. b1 b3 5. c1
+";
+
+    #[test]
+    fn round_trips() {
+        let record: GameRecord = DRAWN.parse().unwrap();
+        assert_eq!(record.names, ["Alice", "Minimax"]);
+        assert_eq!(record.moves.len(), 9);
+        assert_eq!(record.result, Some(GameResult::Draw));
+        assert_eq!(record.to_string(), DRAWN);
+        assert!(record.replay().unwrap().is_full());
+    }
+
+    #[test]
+    fn points_at_the_bad_move() {
+        let text = DRAWN.replace("4. b1", "4. a1");
+        assert_eq!(
+            text.parse::<GameRecord>(),
+            Err(RecordError::IllegalMove(7, MoveError::SquareTaken(Move::new(0, 0))))
+        );
+        let text = DRAWN.replace("[Result \"draw\"]", "[Result \"*\"]");
+        assert_eq!(text.parse::<GameRecord>(), Err(RecordError::ResultMismatch));
+        assert!(matches!("[Size 3]".parse::<GameRecord>(), Err(RecordError::BadTag(_))));
+    }
+
+    #[test]
+    fn unfinished_games_can_be_resumed() {
+        let record: GameRecord = "[Size \"15\"]\n[K \"5\"]\n[Result \"*\"]\n\n1. h8 h9 2. i9\n".parse().unwrap();
+        let board = record.replay().unwrap();
+        assert_eq!(board.size(), 15);
+        assert_eq!(board.to_move(), Player::O);
+    }
+
+    #[test]
+    fn ultimate_games_keep_their_variant() {
+        let text = "[Variant \"ultimate\"]\n[Result \"*\"]\n\n1. e5 e4\n";
+        let record: GameRecord = text.parse().unwrap();
+        assert_eq!(record.variant, Variant::Ultimate);
+        assert_eq!(record.size, 9);
+        assert!(record.to_string().contains("[Variant \"ultimate\"]"));
+        // e4 sends X to the top board, so e6 is out of bounds
+        let bad = text.replace("e4", "e4 2. e6");
+        assert!(matches!(
+            bad.parse::<GameRecord>(),
+            Err(RecordError::IllegalMove(3, MoveError::Forbidden(_)))
+        ));
+    }
+
+    #[test]
+    fn cube_moves_keep_their_layer() {
+        let text = "[Variant \"cube\"]\n[Size \"4\"]\n[K \"4\"]\n[Result \"*\"]\n\n1. 2b2 3c3\n";
+        let record: GameRecord = text.parse().unwrap();
+        assert_eq!(record.moves, vec![Move::layered(1, 1, 1), Move::layered(2, 2, 2)]);
+        assert_eq!(record.to_string().parse::<GameRecord>().unwrap().moves, record.moves);
+        assert!(matches!(
+            text.replace("3c3", "c3").parse::<GameRecord>(),
+            Err(RecordError::IllegalMove(2, MoveError::NoLayer))
+        ));
+    }
+
+    #[test]
+    fn three_players_take_rounds() {
+        // Y takes column c while the others look elsewhere
+        let text = "[X \"Ann\"]\n[O \"Bob\"]\n[Y \"Cy\"]\n[Size \"4\"]\n[K \"3\"]\n[Result \"Y\"]\n\n\
+                    1. a1 b1 c1 2. a2 b2 c2 3. d4 d3 c3\n";
+        let record: GameRecord = text.parse().unwrap();
+        assert_eq!(record.names, ["Ann", "Bob", "Cy"]);
+        assert_eq!(record.result, Some(GameResult::Win(Player::nth(2))));
+        assert_eq!(record.to_string(), text);
+        assert!(matches!(
+            text.replace("[Size \"4\"]", "[Variant \"ultimate\"]").parse::<GameRecord>(),
+            Err(RecordError::BadValue("players", _))
+        ));
+    }
+
+    #[test]
+    fn rules_and_pieces_are_kept() {
+        let text = "[Rules \"numerical\"]\n[Result \"*\"]\n\n1. b2=5 a1=8\n";
+        let record: GameRecord = text.parse().unwrap();
+        assert_eq!(record.rules, Rules::Numerical);
+        assert_eq!(record.to_string().parse::<GameRecord>(), Ok(record));
+        assert!("[Rules \"numerical\"]\n[Size \"4\"]\n".parse::<GameRecord>().is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! Renju's handicaps on the first player, for five in a row on big boards.
+//! X may not make an overline (six or more in a row), a double four or a
+//! double three, and only exactly five wins for X. O may do all of those,
+//! and an overline wins for O. X left with only fouls to play loses.
+//!
+//! A three only counts if it can still become an open four, `.XXXX.`,
+//! with a move X would be allowed to make, so telling a double three
+//! means looking at the moves that would finish each three, and at the
+//! threes those make in turn.
+
+use std::fmt;
+
+use crate::board::{Board, Move, Piece, Player};
+
+/// How many moves deep the double three check follows threes whose
+/// finishing move might be a foul itself. Real games rarely need two.
+const MAX_DEPTH: u32 = 3;
+
+/// Across, down and the two diagonals.
+const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+/// The fewest of X's stones that have to be in line with a move for it
+/// to be a foul: two for each of a double three's threes.
+const FEWEST_STONES: usize = 4;
+
+/// A move X isn't allowed to make.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Foul {
+    Overline,
+    DoubleFour,
+    DoubleThree,
+}
+
+impl fmt::Display for Foul {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Foul::Overline => "an overline",
+            Foul::DoubleFour => "a double four",
+            Foul::DoubleThree => "a double three",
+        })
+    }
+}
+
+/// Why X may not play `mv` on `board`, if it's a foul. `mv` has to be
+/// an empty square; a move that makes exactly five is never a foul.
+pub fn foul(board: &Board, mv: Move) -> Option<Foul> {
+    if stones_in_line(board, mv) < FEWEST_STONES {
+        return None;
+    }
+    let mut stones = Stones::new(board);
+    let (r, c) = (mv.row as isize, mv.col as isize);
+    stones.set(r, c, Some(Player::X));
+    stones.foul(r, c, 0)
+}
+
+/// The five X's stone on `mv` has just made, in order, if it made exactly
+/// five in some direction. That's the only way X wins under renju.
+pub fn five(board: &Board, mv: Move) -> Option<Vec<Move>> {
+    let stones = Stones::new(board);
+    let (r, c) = (mv.row as isize, mv.col as isize);
+    DIRECTIONS.iter().find(|&&d| stones.run(r, c, d) == 5).map(|&(dr, dc)| {
+        let back = stones.reach(r, c, (-dr, -dc)) as isize;
+        (0..5).map(|i| Move::new((r + dr * (i - back)) as usize, (c + dc * (i - back)) as usize)).collect()
+    })
+}
+
+/// How many of X's stones are within four squares of `mv` along the lines
+/// through it: a quick way to rule out most squares before looking closer.
+fn stones_in_line(board: &Board, mv: Move) -> usize {
+    let n = board.size() as isize;
+    let mut count = 0;
+    for &(dr, dc) in &DIRECTIONS {
+        for i in (-4..=4).filter(|&i| i != 0) {
+            let (r, c) = (mv.row as isize + dr * i, mv.col as isize + dc * i);
+            if (0..n).contains(&r)
+                && (0..n).contains(&c)
+                && board.get(r as usize, c as usize) == Some(Piece::Mark(Player::X))
+            {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// A copy of the board's marks to try moves out on.
+struct Stones {
+    size: isize,
+    cells: Vec<Option<Player>>,
+}
+
+impl Stones {
+    fn new(board: &Board) -> Stones {
+        let n = board.size();
+        let cells = (0..n * n)
+            .map(|i| match board.get(i / n, i % n) {
+                Some(Piece::Mark(p)) => Some(p),
+                _ => None,
+            })
+            .collect();
+        Stones { size: n as isize, cells }
+    }
+
+    fn on_board(&self, r: isize, c: isize) -> bool {
+        (0..self.size).contains(&r) && (0..self.size).contains(&c)
+    }
+
+    fn at(&self, r: isize, c: isize) -> Option<Player> {
+        self.cells[(r * self.size + c) as usize]
+    }
+
+    fn is_x(&self, r: isize, c: isize) -> bool {
+        self.on_board(r, c) && self.at(r, c) == Some(Player::X)
+    }
+
+    fn is_empty(&self, r: isize, c: isize) -> bool {
+        self.on_board(r, c) && self.at(r, c).is_none()
+    }
+
+    fn set(&mut self, r: isize, c: isize, stone: Option<Player>) {
+        self.cells[(r * self.size + c) as usize] = stone;
+    }
+
+    /// X's stones in a row stepping along `d` from (`r`, `c`), not
+    /// counting that square.
+    fn reach(&self, r: isize, c: isize, (dr, dc): (isize, isize)) -> usize {
+        let mut count = 0;
+        while self.is_x(r + dr * (count as isize + 1), c + dc * (count as isize + 1)) {
+            count += 1;
+        }
+        count
+    }
+
+    /// The run of X's stones along `d` through (`r`, `c`), counting that
+    /// square.
+    fn run(&self, r: isize, c: isize, (dr, dc): (isize, isize)) -> usize {
+        1 + self.reach(r, c, (dr, dc)) + self.reach(r, c, (-dr, -dc))
+    }
+
+    /// The empty squares along `d`, as steps from (`r`, `c`), where X
+    /// would make exactly five with the stone on (`r`, `c`) in it.
+    fn fives(&mut self, r: isize, c: isize, (dr, dc): (isize, isize)) -> Vec<isize> {
+        let mut steps = Vec::new();
+        for i in (-4..=4).filter(|&i| i != 0) {
+            let (er, ec) = (r + dr * i, c + dc * i);
+            if !self.is_empty(er, ec) {
+                continue;
+            }
+            self.set(er, ec, Some(Player::X));
+            let between = (1..i.abs()).all(|j| self.is_x(r + dr * j * i.signum(), c + dc * j * i.signum()));
+            if between && self.run(er, ec, (dr, dc)) == 5 {
+                steps.push(i);
+            }
+            self.set(er, ec, None);
+        }
+        steps
+    }
+
+    /// How many fours the stone on (`r`, `c`) is part of along `d`. An
+    /// open four has two ways to five but is still one four; `X.XXX.X`
+    /// is two.
+    fn fours(&mut self, r: isize, c: isize, d: (isize, isize)) -> usize {
+        match self.fives(r, c, d).as_slice() {
+            [a, b] if b - a == 5 => 1,
+            steps => steps.len(),
+        }
+    }
+
+    fn open_four(&mut self, r: isize, c: isize, d: (isize, isize)) -> bool {
+        matches!(self.fives(r, c, d).as_slice(), [a, b] if b - a == 5)
+    }
+
+    /// Whether the stone on (`r`, `c`) is part of a three along `d`: a
+    /// move X may make turns it into an open four.
+    fn three(&mut self, r: isize, c: isize, (dr, dc): (isize, isize), depth: u32) -> bool {
+        for i in (-4..=4).filter(|&i| i != 0) {
+            let (er, ec) = (r + dr * i, c + dc * i);
+            if !self.is_empty(er, ec) {
+                continue;
+            }
+            self.set(er, ec, Some(Player::X));
+            let real = self.open_four(r, c, (dr, dc)) && self.foul(er, ec, depth + 1).is_none();
+            self.set(er, ec, None);
+            if real {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether X's stone on (`r`, `c`) makes a foul.
+    fn foul(&mut self, r: isize, c: isize, depth: u32) -> Option<Foul> {
+        if DIRECTIONS.iter().any(|&d| self.run(r, c, d) == 5) {
+            return None;
+        }
+        if DIRECTIONS.iter().any(|&d| self.run(r, c, d) > 5) {
+            return Some(Foul::Overline);
+        }
+        let fours: Vec<usize> = DIRECTIONS.iter().map(|&d| self.fours(r, c, d)).collect();
+        if fours.iter().sum::<usize>() >= 2 {
+            return Some(Foul::DoubleFour);
+        }
+        if depth < MAX_DEPTH {
+            // a four and a three together are allowed, so the four's line isn't a three as well
+            let threes = DIRECTIONS.iter().zip(&fours).filter(|&(&d, &f)| f == 0 && self.three(r, c, d, depth)).count();
+            if threes >= 2 {
+                return Some(Foul::DoubleThree);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{GameResult, MoveError};
+    use crate::notation::parse_move;
+    use crate::rules::Rules;
+
+    /// A 15x15 renju board with X's and O's stones where the lists say,
+    /// and X to move.
+    fn board(xs: &[&str], os: &[&str]) -> Board {
+        let mut board = Board::new(15, 5).with_rules(Rules::Renju);
+        let filler = ["a15", "c15", "e15", "g15", "i15", "k15", "m15", "o15"];
+        let mut spare = filler.iter();
+        for i in 0..xs.len().max(os.len()) {
+            let x = xs.get(i).copied().unwrap_or_else(|| spare.next().unwrap());
+            let o = os.get(i).copied().unwrap_or_else(|| spare.next().unwrap());
+            board.play(square(x)).unwrap();
+            board.play(square(o)).unwrap();
+        }
+        board
+    }
+
+    /// Read off an empty board, as `parse_move` won't give back a foul.
+    fn square(name: &str) -> Move {
+        parse_move(name, &Board::new(15, 5)).unwrap()
+    }
+
+    fn foul_at(board: &Board, name: &str) -> Option<Foul> {
+        foul(board, square(name))
+    }
+
+    #[test]
+    fn finds_each_foul() {
+        // h8 joins f8 g8 across and h6 h7 down: two open threes
+        let b = board(&["f8", "g8", "h6", "h7"], &["a1", "a3", "a5", "a7"]);
+        assert_eq!(foul_at(&b, "h8"), Some(Foul::DoubleThree));
+        // with one end of the row blocked, only the one down is a three
+        let b = board(&["f8", "g8", "h6", "h7"], &["e8", "a3", "a5", "a7"]);
+        assert_eq!(foul_at(&b, "h8"), None);
+        // two fours, each blocked at one end
+        let b = board(&["e8", "f8", "g8", "h5", "h6", "h7"], &["d8", "h4", "a1", "a3", "a5", "a7"]);
+        assert_eq!(foul_at(&b, "h8"), Some(Foul::DoubleFour));
+        // both fours in the one line: X.XXX.X
+        let b = board(&["d8", "f8", "g8", "j8"], &["a1", "a3", "a5", "a7"]);
+        assert_eq!(foul_at(&b, "h8"), Some(Foul::DoubleFour));
+        // six in a row
+        let b = board(&["c8", "d8", "e8", "g8", "h8"], &["a1", "a3", "a5", "a7", "a9"]);
+        assert_eq!(foul_at(&b, "f8"), Some(Foul::Overline));
+        // a four and a three together are fine
+        let b = board(&["e8", "f8", "g8", "h6", "h7"], &["d8", "a3", "a5", "a7", "a9"]);
+        assert_eq!(foul_at(&b, "h8"), None);
+    }
+
+    #[test]
+    fn five_beats_a_foul() {
+        // h8 makes five across and six down, and the five wins
+        let xs = ["d8", "e8", "f8", "g8", "h4", "h5", "h6", "h7", "h9"];
+        let mut b = board(&xs, &["a1", "a3", "a5", "a7", "a9", "a11", "a13", "c1", "c3"]);
+        assert_eq!(foul_at(&b, "h8"), None);
+        b.play(square("h8")).unwrap();
+        assert_eq!(b.result(), Some(GameResult::Win(Player::X)));
+        let line: Vec<String> = b.winning_line().unwrap().iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(line, ["d8", "e8", "f8", "g8", "h8"]);
+    }
+
+    #[test]
+    fn a_three_only_counts_if_it_can_be_opened() {
+        // i8 makes g8 h8 i8 across and i6 i7 i8 down, but the only squares
+        // that would open the row into a four, f8 and j8, are overlines
+        let xs = ["g8", "h8", "i6", "i7", "f5", "f6", "f7", "f9", "f10", "j5", "j6", "j7", "j9", "j10"];
+        let os = ["a1", "a3", "a5", "a7", "a9", "a11", "a13", "c1", "c3", "c5", "c7", "c9", "c11", "c13"];
+        let b = board(&xs, &os);
+        assert_eq!((foul_at(&b, "f8"), foul_at(&b, "j8")), (Some(Foul::Overline), Some(Foul::Overline)));
+        assert_eq!(foul_at(&b, "i8"), None);
+    }
+
+    #[test]
+    fn only_x_is_held_back() {
+        let b = board(&["c8", "d8", "e8", "g8", "h8"], &["a1", "a3", "a5", "a7", "a9"]);
+        let f8 = square("f8");
+        assert_eq!(
+            b.check(f8),
+            Err(MoveError::Forbidden("f8 makes an overline, which renju forbids for X".to_owned()))
+        );
+        assert!(!b.legal_moves().contains(&f8));
+        // O may make six, and wins with it
+        let mut b = board(&["a1", "a3", "a5", "a7", "a9"], &["c8", "d8", "e8", "g8", "h8"]);
+        b.play(square("o1")).unwrap();
+        b.play(f8).unwrap();
+        assert_eq!(b.result(), Some(GameResult::Win(Player::O)));
+        assert_eq!(b.winning_line().unwrap().len(), 6);
+    }
+}
@@ -0,0 +1,260 @@
+//! Going back over a recorded game a move at a time, in either direction,
+//! with the engine's opinion of every move so the mistakes stand out.
+
+use std::fmt;
+use std::io::{stdin, stdout, Write};
+
+use crate::board::{Board, GameResult, Move, Player};
+use crate::hint::{self, Verdict};
+use crate::record::{GameRecord, RecordError};
+
+/// What the engine made of one move of the game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Note {
+    pub player: Player,
+    pub played: Move,
+    /// What the move played leads to.
+    pub verdict: Verdict,
+    /// The best move there was, and what it leads to.
+    pub best: (Move, Verdict),
+}
+
+impl Note {
+    /// Whether the move threw away something the player had: a win that
+    /// is no longer forced, or a game that now can't be saved.
+    pub fn blunder(&self) -> bool {
+        outcome(self.best.1) > outcome(self.verdict)
+    }
+}
+
+/// 1 for a forced win, -1 for a forced loss, 0 for anything else.
+fn outcome(verdict: Verdict) -> i32 {
+    match verdict {
+        Verdict::Win(_) => 1,
+        Verdict::Loss(_) => -1,
+        Verdict::Draw | Verdict::Score(_) => 0,
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} played {}, which {}", self.player, self.played, self.verdict)?;
+        if self.best.1 == self.verdict {
+            return write!(f, ", as good as anything.");
+        }
+        write!(f, ". Best was {}, which {}.", self.best.0, self.best.1)?;
+        if self.blunder() {
+            write!(f, " Blunder!")?;
+        }
+        Ok(())
+    }
+}
+
+/// A recorded game and how far into it we are.
+pub struct Replay {
+    record: GameRecord,
+    board: Board,
+    /// One per move, worked out the first time that move is shown.
+    notes: Vec<Option<Note>>,
+}
+
+impl Replay {
+    /// Starts before the first move. Fails if the record doesn't hold up.
+    pub fn new(record: GameRecord) -> Result<Replay, RecordError> {
+        record.replay()?;
+        let board = record.board();
+        Ok(Replay {
+            notes: vec![None; record.moves.len()],
+            record,
+            board,
+        })
+    }
+
+    /// The position after the moves so far.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// How many moves in we are.
+    pub fn ply(&self) -> usize {
+        self.board.moves_played()
+    }
+
+    /// How many moves the game had.
+    pub fn len(&self) -> usize {
+        self.record.moves.len()
+    }
+
+    /// Whether there are no moves to step through.
+    pub fn is_empty(&self) -> bool {
+        self.record.moves.is_empty()
+    }
+
+    /// Moves to just after move `ply`, or the end if the game is shorter.
+    pub fn goto(&mut self, ply: usize) {
+        let ply = ply.min(self.len());
+        while self.ply() > ply {
+            self.board.undo();
+        }
+        while self.ply() < ply {
+            let mv = self.record.moves[self.ply()];
+            self.board.play(mv).expect("the record was checked when it was loaded");
+        }
+    }
+
+    /// One move on. `false` at the end.
+    pub fn forward(&mut self) -> bool {
+        let ply = self.ply();
+        self.goto(ply + 1);
+        self.ply() > ply
+    }
+
+    /// One move back. `false` at the start.
+    pub fn back(&mut self) -> bool {
+        match self.ply() {
+            0 => false,
+            ply => {
+                self.goto(ply - 1);
+                true
+            }
+        }
+    }
+
+    /// What the engine thinks of the move that led here, or `None` before
+    /// the first move.
+    pub fn note(&mut self) -> Option<&Note> {
+        let ply = self.ply();
+        if ply == 0 {
+            return None;
+        }
+        if self.notes[ply - 1].is_none() {
+            let mut before = self.board.clone();
+            let played = before.undo().expect("there are moves to take back");
+            let analysis = hint::analyse(&before);
+            // on big boards the move may be one the engine didn't consider
+            let verdict = match analysis.moves.iter().find(|&&(mv, _)| mv == played) {
+                Some(&(_, verdict)) => verdict,
+                None => hint::rate(&before, vec![played]).moves[0].1,
+            };
+            self.notes[ply - 1] = Some(Note {
+                player: before.to_move(),
+                played,
+                verdict,
+                best: analysis.moves[0],
+            });
+        }
+        self.notes[ply - 1].as_ref()
+    }
+
+    /// How the game ended, once we're at the last move.
+    pub fn ending(&self) -> Option<String> {
+        if self.ply() < self.len() {
+            return None;
+        }
+        let name = |p: Player| &self.record.names[p.index()];
+        match (self.board.result(), self.record.result) {
+            (Some(GameResult::Win(p)), _) => Some(format!("{} ({}) won.", name(p), p)),
+            (Some(GameResult::Draw), _) => Some("The game was drawn.".to_owned()),
+            (None, Some(GameResult::Win(p))) => Some(format!("{} ({}) resigned.", name(p.other()), p.other())),
+            (None, _) => Some("The game stopped here, unfinished.".to_owned()),
+        }
+    }
+
+    /// What to show under the board: the last move and what it was worth.
+    pub fn caption(&mut self) -> Vec<String> {
+        let ply = self.ply();
+        let mut lines = vec![match self.note() {
+            Some(note) => format!("Move {}: {}", ply, note),
+            None => {
+                let mut sides: Vec<String> = (Player::all(self.record.names.len()).zip(&self.record.names))
+                    .map(|(p, name)| format!("{} ({})", name, p))
+                    .collect();
+                let last = sides.pop().unwrap_or_default();
+                format!("{} against {}, before the first move.", sides.join(", "), last)
+            }
+        }];
+        lines.extend(self.ending());
+        lines
+    }
+}
+
+/// Steps through a game at the keyboard, printing the board each time.
+pub fn run(replay: &mut Replay) {
+    println!("Enter or n steps forward, b back, a number jumps to that move, s and e go to the start and end, q stops.");
+    loop {
+        println!("{}", replay.board());
+        for line in replay.caption() {
+            println!("{}", line);
+        }
+        print!("[{}/{}] ", replay.ply(), replay.len());
+        let _ = stdout().flush();
+        let mut s = String::new();
+        if stdin().read_line(&mut s).unwrap_or(0) == 0 {
+            return;
+        }
+        match s.trim() {
+            "" | "n" | "next" => {
+                if !replay.forward() {
+                    println!("That was the last move.");
+                }
+            }
+            "b" | "back" => {
+                if !replay.back() {
+                    println!("This is the start.");
+                }
+            }
+            "s" | "start" => replay.goto(0),
+            "e" | "end" => replay.goto(replay.len()),
+            "q" | "quit" => return,
+            other => match other.parse() {
+                Ok(ply) => replay.goto(ply),
+                Err(_) => println!("Sorry, {} isn't a replay command.", other),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(moves: &str) -> Replay {
+        let record: GameRecord = format!("[Result \"X\"]\n\n{}\n", moves).parse().unwrap();
+        Replay::new(record).unwrap()
+    }
+
+    #[test]
+    fn steps_both_ways_and_jumps() {
+        let mut replay = game("1. b2 a2 2. a1 c3 3. c1 b1 4. a3");
+        assert_eq!(replay.ply(), 0);
+        assert!(!replay.back());
+        assert!(replay.forward());
+        assert_eq!(replay.board().last_move(), Some(Move::new(1, 1)));
+        replay.goto(100);
+        assert_eq!(replay.ply(), 7);
+        assert!(!replay.forward());
+        assert!(replay.ending().unwrap().contains("won"));
+        let end = replay.board().hash();
+        replay.goto(3);
+        assert!(replay.ending().is_none());
+        replay.goto(7);
+        assert_eq!(replay.board().hash(), end);
+    }
+
+    #[test]
+    fn finds_the_blunder() {
+        // a2 lets X set up two threats with a1; O had to take a corner
+        let mut replay = game("1. b2 a2 2. a1 c3 3. c1 b1 4. a3");
+        replay.goto(2);
+        let note = replay.note().unwrap().clone();
+        assert_eq!(note.player, Player::O);
+        assert_eq!(note.verdict, Verdict::Loss(6));
+        assert_eq!(note.best.1, Verdict::Draw);
+        assert!(note.blunder());
+        assert!(note.to_string().ends_with("Blunder!"));
+        // X's reply keeps the win, as good as anything
+        replay.forward();
+        assert!(!replay.note().unwrap().blunder());
+        assert!(replay.caption()[0].starts_with("Move 3: X played a1, which wins in"));
+    }
+}
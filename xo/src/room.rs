@@ -0,0 +1,306 @@
+//! One game on the server, as a state machine: a player sits down, a move
+//! is made, a player resigns. Each of those is an `Event`, and `Room::apply`
+//! is the only way the game changes.
+//!
+//! Every event is kept, and queued for everyone watching the moment it
+//! happens. Whoever starts watching late, a player back after a reload or
+//! a spectator turning up halfway, is sent them all from the start, so
+//! they see the game unfold as everyone else did.
+
+use std::sync::mpsc::Sender;
+
+use crate::board::{column_letter, Board, GameResult, Move, Player, Variant};
+use crate::game::Game;
+use crate::http::{json_list, json_string};
+use crate::websocket::Frame;
+
+/// Something that happened to a game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A player, by name, took the next seat: X's, then O's.
+    Joined(String),
+    Moved(Move),
+    Resigned(Player),
+}
+
+/// The queue to an open WebSocket, and whose it is: a player's, or a
+/// spectator's if it came without a seat's token.
+struct Watcher {
+    id: u64,
+    updates: Sender<Frame>,
+    seat: Option<Player>,
+}
+
+/// A game, whatever it's been through, and who's watching it.
+pub struct Room {
+    id: u32,
+    /// Empty, to start the game on once both seats are taken.
+    board: Board,
+    /// The players so far, X first.
+    names: Vec<String>,
+    game: Option<Game>,
+    /// Everything that's happened, oldest first.
+    events: Vec<Event>,
+    watchers: Vec<Watcher>,
+    /// The id the next watcher gets.
+    next_watcher: u64,
+}
+
+impl Room {
+    /// Nobody sitting at `board` yet.
+    pub fn new(id: u32, board: Board) -> Room {
+        Room {
+            id,
+            board,
+            names: Vec::new(),
+            game: None,
+            events: Vec::new(),
+            watchers: Vec::new(),
+            next_watcher: 0,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The game, once both players are there.
+    pub fn game(&self) -> Option<&Game> {
+        self.game.as_ref()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.names.len() == 2
+    }
+
+    /// Whether it's finished: won, drawn or resigned.
+    pub fn is_over(&self) -> bool {
+        self.game().and_then(Game::result).is_some()
+    }
+
+    /// Moves the game on by `event`, or says why it can't be. Nothing
+    /// changes if it can't.
+    pub fn apply(&mut self, event: &Event) -> Result<(), String> {
+        match event {
+            Event::Joined(_) if self.is_full() => return Err("both players are here already".to_owned()),
+            Event::Joined(name) => {
+                self.names.push(name.clone());
+                if self.is_full() {
+                    self.game = Some(Game::new(self.board.clone(), self.names.clone()));
+                }
+            }
+            Event::Moved(mv) => {
+                let game = self.game.as_mut().ok_or("the game hasn't started")?;
+                game.play(*mv).map_err(|e| e.to_string())?;
+            }
+            Event::Resigned(side) => {
+                let game = self.game.as_mut().ok_or("the game hasn't started")?;
+                if game.result().is_some() {
+                    return Err("the game is over".to_owned());
+                }
+                game.resign(*side);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `event`, keeps it, and queues it for everyone watching,
+    /// forgetting any that have gone.
+    pub fn record(&mut self, event: Event) -> Result<(), String> {
+        self.apply(&event)?;
+        let update = self.event_json(&event, false);
+        self.events.push(event);
+        self.watchers.retain(|watcher| watcher.updates.send(Frame::Text(update.clone())).is_ok());
+        Ok(())
+    }
+
+    /// Starts queueing events for `updates`, beginning with every one so
+    /// far. `seat` is the player it belongs to, if it does. Nothing is
+    /// written here: whoever reads the queue does that, so a socket slow to
+    /// take them holds up nobody else. Returns the id to `unwatch` with.
+    pub fn watch(&mut self, updates: Sender<Frame>, seat: Option<Player>) -> u64 {
+        let id = self.next_watcher;
+        self.next_watcher += 1;
+        let mut replay = Room::new(self.id, self.board.clone());
+        for event in &self.events {
+            replay.apply(event).expect("it applied the first time");
+            if updates.send(Frame::Text(replay.event_json(event, true))).is_err() {
+                return id;
+            }
+        }
+        self.watchers.push(Watcher { id, updates, seat });
+        id
+    }
+
+    /// Stops queueing events for the watcher `watch` gave `id`, as soon as
+    /// its socket closes rather than at the next event.
+    pub fn unwatch(&mut self, id: u64) {
+        self.watchers.retain(|watcher| watcher.id != id);
+    }
+
+    /// Watchers without a seat.
+    pub fn spectators(&self) -> usize {
+        self.watchers.iter().filter(|w| w.seat.is_none()).count()
+    }
+
+    /// `event` as it's sent, with the game as it stood after it. Events
+    /// sent to catch a late watcher up are marked as a `replay`.
+    fn event_json(&self, event: &Event, replay: bool) -> String {
+        let (kind, details) = match event {
+            Event::Joined(name) => {
+                let side = Player::nth(self.names.len() - 1);
+                ("joined", format!("\"side\":{},\"name\":{}", side_json(side), json_string(name)))
+            }
+            Event::Moved(mv) => {
+                let game = self.game().expect("moves come after both players join");
+                let side = game.board().to_move().previous(2);
+                ("move", format!("\"side\":{},\"move\":{}", side_json(side), json_string(&mv.to_string())))
+            }
+            Event::Resigned(side) => ("resigned", format!("\"side\":{}", side_json(*side))),
+        };
+        format!("{{\"event\":\"{}\",{},\"replay\":{},\"game\":{}}}", kind, details, replay, self.to_json())
+    }
+
+    /// How the lobby lists it: the settings, who's in it, where it's got
+    /// to, and how many are watching.
+    pub fn summary_json(&self) -> String {
+        format!(
+            "{{\"id\":{},\"variant\":{},\"rules\":{},\"size\":{},\"k\":{},\"players\":{},\"status\":\"{}\",\"spectators\":{}}}",
+            self.id,
+            json_string(self.board.variant().name()),
+            json_string(self.board.rules().name()),
+            self.board.size(),
+            self.board.k(),
+            json_list(self.names.iter().map(|n| json_string(n))),
+            self.status(),
+            self.spectators()
+        )
+    }
+
+    fn status(&self) -> &'static str {
+        match self.game() {
+            None => "waiting",
+            Some(_) if self.is_over() => "over",
+            Some(_) => "playing",
+        }
+    }
+
+    /// The game as the page draws it: the settings, who's playing, whose
+    /// turn it is or how it ended, the moves so far, and every square,
+    /// layer by layer and row by row, with what to send to play there.
+    pub fn to_json(&self) -> String {
+        let board = self.game().map_or(&self.board, Game::board);
+        let result = self.game().and_then(Game::result);
+        let to_move = match (&self.game, result) {
+            (Some(_), None) => Some(board.to_move()),
+            _ => None,
+        };
+        let winner = match result {
+            Some(GameResult::Win(p)) => Some(p),
+            _ => None,
+        };
+        let squares = |layer| {
+            json_list(
+                (0..board.rows())
+                    .map(|row| json_list((0..board.size()).map(|col| cell_json(board, board.square(layer, row, col))))),
+            )
+        };
+        let line = board.winning_line().unwrap_or_default();
+        format!(
+            "{{\"id\":{},\"variant\":{},\"rules\":{},\"size\":{},\"k\":{},\"pieces\":{},\"players\":{},\
+             \"status\":\"{}\",\"to_move\":{},\"result\":{},\"winner\":{},\"moves\":{},\"line\":{},\"layers\":{}}}",
+            self.id,
+            json_string(board.variant().name()),
+            json_string(board.rules().name()),
+            board.size(),
+            board.k(),
+            board.rules().picks_pieces(),
+            json_list(self.names.iter().map(|n| json_string(n))),
+            self.status(),
+            to_move.map_or("null".to_owned(), side_json),
+            self.game().and_then(Game::result_text).map_or("null".to_owned(), |text| json_string(&text)),
+            winner.map_or("null".to_owned(), side_json),
+            json_list(board.moves().iter().map(|mv| json_string(&mv.to_string()))),
+            json_list(line.iter().map(|mv| json_string(&mv.to_string()))),
+            json_list((0..board.layers()).map(squares))
+        )
+    }
+}
+
+/// One square: its name, what to send to play there, and what's on it.
+fn cell_json(board: &Board, mv: Move) -> String {
+    // on a gravity board it's the column that's played
+    let at = match board.variant() {
+        Variant::Gravity => column_letter(mv.col).to_string(),
+        _ => mv.to_string(),
+    };
+    let piece = board.at(mv).map_or("null".to_owned(), |p| json_string(&p.to_string()));
+    format!("{{\"square\":{},\"at\":{},\"piece\":{}}}", json_string(&mv.to_string()), json_string(&at), piece)
+}
+
+fn side_json(side: Player) -> String {
+    json_string(&side.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn events_take_the_game_from_seat_to_finish() {
+        let mut room = Room::new(1, Board::new(3, 3));
+        assert!(room.record(Event::Moved(Move::new(0, 0))).is_err());
+        room.record(Event::Joined("Ann".to_owned())).unwrap();
+        room.record(Event::Joined("Bob".to_owned())).unwrap();
+        assert_eq!(room.record(Event::Joined("Cy".to_owned())), Err("both players are here already".to_owned()));
+        room.record(Event::Moved(Move::new(1, 1))).unwrap();
+        // a refused move leaves nothing behind
+        assert!(room.record(Event::Moved(Move::new(1, 1))).is_err());
+        assert_eq!(room.events.len(), 3);
+        room.record(Event::Resigned(Player::O)).unwrap();
+        assert!(room.is_over());
+        assert!(room.record(Event::Resigned(Player::X)).is_err());
+        let last = room.event_json(&room.events[3].clone(), false);
+        assert!(last.starts_with("{\"event\":\"resigned\",\"side\":\"O\",\"replay\":false,"), "{}", last);
+        assert!(last.contains("\"result\":\"Bob resigns. Ann wins!\""));
+    }
+
+    #[test]
+    fn late_watchers_are_caught_up_first() {
+        let mut room = Room::new(1, Board::new(3, 3));
+        room.record(Event::Joined("Ann".to_owned())).unwrap();
+        room.record(Event::Joined("Bob".to_owned())).unwrap();
+        let (updates, queue) = mpsc::channel();
+        room.watch(updates, None);
+        room.record(Event::Moved(Move::new(1, 1))).unwrap();
+        assert_eq!(room.spectators(), 1);
+        let sent: Vec<_> = queue.try_iter().collect();
+        let starts = [
+            "{\"event\":\"joined\",\"side\":\"X\",\"name\":\"Ann\",\"replay\":true,",
+            "{\"event\":\"joined\",\"side\":\"O\",\"name\":\"Bob\",\"replay\":true,",
+            "{\"event\":\"move\",\"side\":\"X\",\"move\":\"b2\",\"replay\":false,",
+        ];
+        assert_eq!(sent.len(), starts.len());
+        for (frame, start) in sent.iter().zip(starts) {
+            assert!(matches!(frame, Frame::Text(json) if json.starts_with(start)), "{:?}", frame);
+        }
+        // and forgotten once they've gone
+        drop(queue);
+        room.record(Event::Moved(Move::new(0, 0))).unwrap();
+        assert_eq!(room.spectators(), 0);
+    }
+
+    #[test]
+    fn watchers_can_leave_between_events() {
+        let mut room = Room::new(1, Board::new(3, 3));
+        let (updates, _queue) = mpsc::channel();
+        let first = room.watch(updates.clone(), None);
+        let second = room.watch(updates, Some(Player::X));
+        room.unwatch(first);
+        assert_eq!(room.spectators(), 0);
+        assert_eq!(room.watchers.len(), 1);
+        room.unwatch(second);
+        assert!(room.watchers.is_empty());
+    }
+}
@@ -0,0 +1,325 @@
+//! Who is playing: a name, a symbol and a colour for every seat at the
+//! board, and whether a person, the computer or another program sits in it.
+//!
+//! A roster comes from `--player` options or a file of `[player]`
+//! sections, one per seat in turn order:
+//!
+//! ```text
+//! # the Sunday game
+//! [player]
+//! name = Ann
+//! symbol = ●
+//! colour = red
+//!
+//! [player]
+//! name = Bot
+//! symbol = ▲
+//! colour = cyan
+//! seat = minimax:3
+//! ```
+//!
+//! Anything left out takes its default: the player's letter for the
+//! symbol, the terminal's own colour, and a person at this keyboard.
+
+use std::fs;
+use std::str::FromStr;
+
+use crate::board::{Board, Move, Piece, Player, MAX_PLAYERS};
+use crate::tournament::Entrant;
+
+/// The eight colours every ANSI terminal has, and its own default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colour {
+    Default,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+const COLOURS: [(Colour, &str); 9] = [
+    (Colour::Default, "default"),
+    (Colour::Black, "black"),
+    (Colour::Red, "red"),
+    (Colour::Green, "green"),
+    (Colour::Yellow, "yellow"),
+    (Colour::Blue, "blue"),
+    (Colour::Magenta, "magenta"),
+    (Colour::Cyan, "cyan"),
+    (Colour::White, "white"),
+];
+
+impl Colour {
+    pub fn from_name(name: &str) -> Option<Colour> {
+        COLOURS.iter().find(|(_, n)| *n == name).map(|&(c, _)| c)
+    }
+
+    pub fn name(self) -> &'static str {
+        COLOURS.iter().find(|&&(c, _)| c == self).map_or("default", |(_, n)| n)
+    }
+
+    /// `text` in this colour. Only the foreground is set and reset, so
+    /// highlighting around it, like the cursor's reverse video, survives.
+    pub fn paint(self, text: &str) -> String {
+        match self {
+            Colour::Default => text.to_owned(),
+            colour => format!("\x1b[{}m{}\x1b[39m", 30 + colour as u8 - 1, text),
+        }
+    }
+}
+
+/// Who makes the moves for one player.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Seat {
+    /// Someone at this keyboard.
+    Human,
+    /// The computer, searching as the engine options say.
+    Computer,
+    /// A computer player of a set strength, named as in tournaments:
+    /// `minimax:3`, `mcts:1000`, or `engine:COMMAND` and
+    /// `remote:HOST:PORT` for programs elsewhere.
+    Bot(Entrant),
+}
+
+impl FromStr for Seat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Seat, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "human" => Ok(Seat::Human),
+            "computer" => Ok(Seat::Computer),
+            _ => s.parse().map(Seat::Bot),
+        }
+    }
+}
+
+/// One player's place in the game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    /// `None` leaves it to whoever sits there: "Player X" for a person,
+    /// the engine's own name for the computer.
+    pub name: Option<String>,
+    /// Shown on the board in place of the player's letter. Moves are
+    /// still typed with the letter where the rules ask for a mark.
+    pub symbol: char,
+    pub colour: Colour,
+    pub seat: Seat,
+}
+
+impl Member {
+    /// A person playing `player`'s letter in the terminal's colour.
+    pub fn new(player: Player) -> Member {
+        Member {
+            name: None,
+            symbol: player.letter(),
+            colour: Colour::Default,
+            seat: Seat::Human,
+        }
+    }
+
+    /// Parses `NAME[,SYMBOL[,COLOUR[,SEAT]]]` from the command line, for
+    /// `player`. Empty fields keep their defaults; the seat takes the
+    /// rest of the line, commas and all.
+    pub fn parse(spec: &str, player: Player) -> Result<Member, String> {
+        let mut member = Member::new(player);
+        let mut fields = spec.splitn(4, ',').map(str::trim);
+        for key in &["name", "symbol", "colour", "seat"] {
+            match fields.next() {
+                Some(value) if !value.is_empty() => member.set(key, value)?,
+                _ => {}
+            }
+        }
+        Ok(member)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "name" => self.name = Some(value.to_owned()),
+            "symbol" => {
+                let mut chars = value.chars();
+                self.symbol = match (chars.next(), chars.next()) {
+                    (Some(c), None) if !c.is_whitespace() => c,
+                    _ => return Err(format!("a symbol is one character, not \"{}\"", value)),
+                }
+            }
+            "colour" | "color" => {
+                self.colour = Colour::from_name(&value.to_ascii_lowercase())
+                    .ok_or_else(|| format!("unknown colour {}", value))?
+            }
+            "seat" => self.seat = value.parse()?,
+            _ => return Err(format!("unknown setting {}", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Everyone in the game, in turn order: the first plays X, the second O,
+/// then Y, Z and so on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Roster {
+    members: Vec<Member>,
+}
+
+impl Roster {
+    /// Checks there are 2 to `MAX_PLAYERS` members and no two share a
+    /// symbol.
+    pub fn new(members: Vec<Member>) -> Result<Roster, String> {
+        if !(2..=MAX_PLAYERS).contains(&members.len()) {
+            return Err(format!("there must be between 2 and {} players", MAX_PLAYERS));
+        }
+        for (i, member) in members.iter().enumerate() {
+            if members[..i].iter().any(|m| m.symbol == member.symbol) {
+                return Err(format!("two players can't both use {}", member.symbol));
+            }
+        }
+        Ok(Roster { members })
+    }
+
+    /// `players` people at this keyboard, with the usual letters.
+    pub fn humans(players: usize) -> Roster {
+        Roster::new(Player::all(players).map(Member::new).collect()).expect("letters differ")
+    }
+
+    /// Reads a roster file.
+    pub fn load(path: &str) -> Result<Roster, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+        text.parse().map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    pub fn member(&self, player: Player) -> &Member {
+        &self.members[player.index()]
+    }
+
+    pub fn member_mut(&mut self, player: Player) -> &mut Member {
+        &mut self.members[player.index()]
+    }
+
+    /// The same players without colours, for output that isn't going to
+    /// a terminal.
+    pub fn plain(&self) -> Roster {
+        let members = self.members.iter().map(|m| Member { colour: Colour::Default, ..m.clone() }).collect();
+        Roster { members }
+    }
+
+    /// `player`'s symbol in their colour.
+    pub fn mark(&self, player: Player) -> String {
+        let member = self.member(player);
+        member.colour.paint(&member.symbol.to_string())
+    }
+
+    /// What to draw on `mv`'s square instead of a player's letter, for
+    /// `Board::render_labelled`.
+    pub fn label(&self, board: &Board, mv: Move) -> Option<String> {
+        match board.at(mv) {
+            Some(Piece::Mark(p)) if p.index() < self.len() => Some(self.mark(p)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Roster {
+    fn default() -> Roster {
+        Roster::humans(2)
+    }
+}
+
+impl FromStr for Roster {
+    type Err = String;
+
+    /// Parses the file format in the module docs.
+    fn from_str(s: &str) -> Result<Roster, String> {
+        let mut members: Vec<Member> = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            let at = |e: String| format!("line {}: {}", i + 1, e);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "[player]" {
+                if members.len() == MAX_PLAYERS {
+                    return Err(at(format!("there can't be more than {} players", MAX_PLAYERS)));
+                }
+                members.push(Member::new(Player::nth(members.len())));
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| at(format!("expected key = value, not {}", line)))?;
+            let member = members.last_mut().ok_or_else(|| at("settings come after a [player] line".to_owned()))?;
+            member.set(&key.trim().to_ascii_lowercase(), value.trim()).map_err(at)?;
+        }
+        Roster::new(members)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "# three of us
+[player]
+name = Ann
+symbol = ●
+colour = red
+
+[player]
+Name = Bot
+color = Cyan
+seat = minimax:3
+
+[player]
+symbol = ▲
+seat = engine:./bot --fast
+";
+
+    #[test]
+    fn reads_a_file() {
+        let roster: Roster = FILE.parse().unwrap();
+        assert_eq!(roster.len(), 3);
+        assert_eq!(roster.member(Player::X).name.as_deref(), Some("Ann"));
+        assert_eq!(roster.member(Player::O).symbol, 'O');
+        assert_eq!(roster.member(Player::O).colour, Colour::Cyan);
+        assert_eq!(roster.member(Player::O).seat, Seat::Bot(Entrant::Minimax(Some(3))));
+        let third = roster.member(Player::nth(2));
+        assert_eq!((third.name.as_ref(), third.symbol), (None, '▲'));
+        assert_eq!(third.seat, Seat::Bot(Entrant::External("./bot --fast".to_owned())));
+        assert_eq!(roster.mark(Player::X), "\x1b[31m●\x1b[39m");
+        assert_eq!(roster.plain().mark(Player::X), "●");
+    }
+
+    #[test]
+    fn says_what_is_wrong() {
+        let error = |text: &str| text.parse::<Roster>().unwrap_err();
+        assert_eq!(error("name = Ann"), "line 1: settings come after a [player] line");
+        assert!(error("[player]\nsymbol = AB\n[player]").starts_with("line 2: a symbol is one character"));
+        assert!(error("[player]\ncolour = mauve\n[player]").contains("unknown colour mauve"));
+        assert!(error("[player]\nseat = wizard\n[player]").contains("unknown player wizard"));
+        assert!(error("[player]\n[player]\nsymbol = X").contains("both use X"));
+        assert!(error("[player]").contains("between 2"));
+    }
+
+    #[test]
+    fn reads_the_command_line() {
+        let member = Member::parse("Ann,★,yellow,engine:./bot a,b", Player::O).unwrap();
+        assert_eq!(member.name.as_deref(), Some("Ann"));
+        assert_eq!((member.symbol, member.colour), ('★', Colour::Yellow));
+        assert_eq!(member.seat, Seat::Bot(Entrant::External("./bot a,b".to_owned())));
+        let member = Member::parse(",,,computer", Player::nth(3)).unwrap();
+        assert_eq!((member.name, member.symbol, member.seat), (None, 'Z', Seat::Computer));
+        assert!(Member::parse("Ann,,pink", Player::X).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::board::{Board, GameResult, Move, MoveError, Piece, Player, Variant};
+use crate::renju;
+
+/// What a line adds up to in numerical tic-tac-toe: the magic constant of
+/// a 3x3 magic square.
+const MAGIC_SUM: u8 = 15;
+
+/// How the game is won and what players put down, on whatever board the
+/// `Variant` lays out. Everything that differs between rule sets goes
+/// through here, so the board, the engines and the display don't need to
+/// know which one is in play.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rules {
+    /// K of your own mark in a row wins.
+    Standard,
+    /// K of your own mark in a row loses.
+    Misere,
+    /// Either player may put down either mark, and whoever completes K of
+    /// the same mark in a row wins.
+    Wild,
+    /// X puts down the odd numbers 1 to 9 and O the even ones, each only
+    /// once. Whoever completes a line adding up to 15 wins.
+    Numerical,
+    /// Five in a row, with X held back to make up for going first: no
+    /// overlines, double fours or double threes, and only exactly five
+    /// wins for X. See `renju`.
+    Renju,
+}
+
+impl Rules {
+    pub fn name(self) -> &'static str {
+        match self {
+            Rules::Standard => "standard",
+            Rules::Misere => "misere",
+            Rules::Wild => "wild",
+            Rules::Numerical => "numerical",
+            Rules::Renju => "renju",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rules> {
+        match name {
+            "standard" => Some(Rules::Standard),
+            "misere" | "misère" => Some(Rules::Misere),
+            "wild" => Some(Rules::Wild),
+            "numerical" => Some(Rules::Numerical),
+            "renju" => Some(Rules::Renju),
+            _ => None,
+        }
+    }
+
+    /// A line telling players what's different, or `None` for the usual game.
+    pub fn describe(self, board: &Board) -> Option<String> {
+        match self {
+            Rules::Standard => None,
+            Rules::Misere => Some(format!("Misère: whoever gets {} in a row loses.", board.k())),
+            Rules::Wild => Some(format!(
+                "Wild: play X or O, e.g. b2=O. Whoever completes {} of a kind in a row wins.",
+                board.k()
+            )),
+            Rules::Numerical => Some(
+                "Numerical: X plays odd numbers, O even, e.g. b2=5. A full line adding up to 15 wins.".to_owned(),
+            ),
+            Rules::Renju => Some(
+                "Renju: five in a row wins, but X may not make a double three, a double four or six in a row.".to_owned(),
+            ),
+        }
+    }
+
+    /// Whether these rules make sense on that board.
+    pub fn supports(self, variant: Variant, size: usize, k: usize) -> Result<(), String> {
+        match (self, variant) {
+            (Rules::Standard, _) => Ok(()),
+            (_, Variant::Ultimate) => Err(format!("{} rules don't work with ultimate", self.name())),
+            (Rules::Numerical, Variant::Classic) if size == 3 && k == 3 => Ok(()),
+            (Rules::Numerical, _) => Err("numerical is played on a 3x3 board".to_owned()),
+            (Rules::Renju, Variant::Classic) if k == 5 => Ok(()),
+            (Rules::Renju, _) => Err("renju is five in a row on a classic board".to_owned()),
+            (Rules::Misere, _) | (Rules::Wild, _) => Ok(()),
+        }
+    }
+
+    /// Whether a move says what to put down as well as where.
+    pub fn picks_pieces(self) -> bool {
+        match self {
+            Rules::Standard | Rules::Misere | Rules::Renju => false,
+            Rules::Wild | Rules::Numerical => true,
+        }
+    }
+
+    /// Everything the player to move could put on an empty square. `None`
+    /// stands for their own mark.
+    pub fn pieces(self, board: &Board) -> Vec<Option<Piece>> {
+        match self {
+            Rules::Standard | Rules::Misere | Rules::Renju => vec![None],
+            Rules::Wild => vec![Some(Piece::Mark(Player::X)), Some(Piece::Mark(Player::O))],
+            Rules::Numerical => numbers_for(board.to_move())
+                .filter(|&n| !on_board(board, n))
+                .map(|n| Some(Piece::Number(n)))
+                .collect(),
+        }
+    }
+
+    /// Whether the player to move may put `piece` down.
+    pub fn check_piece(self, board: &Board, piece: Option<Piece>) -> Result<(), MoveError> {
+        let me = board.to_move();
+        match self {
+            Rules::Standard | Rules::Misere | Rules::Renju => match piece {
+                None => Ok(()),
+                Some(Piece::Mark(p)) if p == me => Ok(()),
+                Some(_) => Err(MoveError::Forbidden(format!("you can only play {} here", me))),
+            },
+            Rules::Wild => match piece {
+                None | Some(Piece::Mark(_)) => Ok(()),
+                Some(_) => Err(MoveError::Forbidden("you can play X or O".to_owned())),
+            },
+            Rules::Numerical => match piece {
+                Some(Piece::Number(n)) if !numbers_for(me).any(|m| m == n) => {
+                    Err(MoveError::Forbidden(format!("{} plays {}", me, parity(me))))
+                }
+                Some(Piece::Number(n)) if on_board(board, n) => {
+                    Err(MoveError::Forbidden(format!("{} is already on the board", n)))
+                }
+                Some(Piece::Number(_)) => Ok(()),
+                _ => Err(MoveError::Forbidden(format!(
+                    "say which number to play, e.g. b2={}",
+                    numbers_for(me).find(|&n| !on_board(board, n)).unwrap_or(1)
+                ))),
+            },
+        }
+    }
+
+    /// Whether the player to move may put anything at all on `mv`'s empty
+    /// square. Only renju ever says no, to X's fouls.
+    pub fn check_square(self, board: &Board, mv: Move) -> Result<(), MoveError> {
+        if self != Rules::Renju || board.to_move() != Player::X {
+            return Ok(());
+        }
+        match renju::foul(board, mv) {
+            Some(foul) => Err(MoveError::Forbidden(format!("{} makes {}, which renju forbids for X", mv, foul))),
+            None => Ok(()),
+        }
+    }
+
+    /// How the game ends when the board isn't full but the player to move
+    /// may not go on any of the empty squares. Only renju's fouls can
+    /// leave X like that, and as X would have to foul, O wins.
+    pub fn stalemate(self, board: &Board) -> Option<GameResult> {
+        if self != Rules::Renju || board.to_move() != Player::X || board.is_full() {
+            return None;
+        }
+        let mut open = (0..board.squares()).filter(|&i| board.cell(i).is_none()).map(|i| board.square_at(i));
+        if open.any(|mv| self.check_square(board, mv).is_ok()) {
+            None
+        } else {
+            Some(GameResult::Win(Player::O))
+        }
+    }
+
+    /// Reads the piece part of a move, such as the `O` of `b2=O`.
+    pub fn parse_piece(self, s: &str) -> Option<Piece> {
+        match (self, s) {
+            (Rules::Numerical, _) => s.parse().ok().map(Piece::Number),
+            (_, "x") | (_, "X") => Some(Piece::Mark(Player::X)),
+            (_, "o") | (_, "O") => Some(Piece::Mark(Player::O)),
+            _ => None,
+        }
+    }
+
+    /// The line `mv` has just completed, if it completed one.
+    pub fn completed_line(self, board: &Board, mv: Move) -> Option<Vec<Move>> {
+        match self {
+            Rules::Renju if board.at(mv) == Some(Piece::Mark(Player::X)) => renju::five(board, mv),
+            Rules::Standard | Rules::Misere | Rules::Wild | Rules::Renju => board.run_through(mv),
+            Rules::Numerical => {
+                let idx = board.index(mv);
+                let line = board.lines().iter().find(|line| {
+                    line.contains(&idx)
+                        && line.iter().all(|&i| board.cell(i).is_some())
+                        && line.iter().map(|&i| number_in(board.cell(i))).sum::<u8>() == MAGIC_SUM
+                })?;
+                Some(line.iter().map(|&i| board.square_at(i)).collect())
+            }
+        }
+    }
+
+    /// Who has won now that `mv` is on the board, if anyone. The player
+    /// who made it is still `board.to_move()`.
+    pub fn winner_after(self, board: &Board, mv: Move) -> Option<Player> {
+        let won = match self {
+            Rules::Standard | Rules::Misere | Rules::Wild => board.makes_line(mv),
+            Rules::Numerical => self.completed_line(board, mv).is_some(),
+            // an overline alone is a foul for X, but can come with a five
+            Rules::Renju if board.to_move() == Player::X => renju::five(board, mv).is_some(),
+            Rules::Renju => board.makes_line(mv),
+        };
+        if !won {
+            return None;
+        }
+        match self {
+            Rules::Misere => Some(board.to_move().other()),
+            _ => Some(board.to_move()),
+        }
+    }
+}
+
+/// Numerical is a two-player game: X has the odd numbers, O the even.
+fn numbers_for(player: Player) -> impl Iterator<Item = u8> {
+    let first = player.index() as u8 + 1;
+    (first..=9).step_by(2)
+}
+
+fn parity(player: Player) -> &'static str {
+    if player == Player::X {
+        "odd numbers"
+    } else {
+        "even numbers"
+    }
+}
+
+fn on_board(board: &Board, n: u8) -> bool {
+    (0..9).any(|i| board.cell(i) == Some(Piece::Number(n)))
+}
+
+fn number_in(piece: Option<Piece>) -> u8 {
+    match piece {
+        Some(Piece::Number(n)) => n,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::GameResult;
+    use crate::minimax::{Minimax, WIN};
+    use crate::notation::parse_move;
+
+    fn board_after(rules: Rules, moves: &[&str]) -> Board {
+        let mut board = Board::new(3, 3).with_rules(rules);
+        for mv in moves {
+            let mv = parse_move(mv, &board).unwrap();
+            board.play(mv).unwrap();
+        }
+        board
+    }
+
+    #[test]
+    fn misere_line_loses() {
+        let board = board_after(Rules::Misere, &["a1", "b1", "a2", "b2", "a3"]);
+        assert_eq!(board.result(), Some(GameResult::Win(Player::O)));
+        assert_eq!(board.winning_line().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn wild_line_wins_for_whoever_finishes_it() {
+        // O completes a row of Xs
+        let board = board_after(Rules::Wild, &["a1=X", "c3=O", "b1=X", "c1=X"]);
+        assert_eq!(board.result(), Some(GameResult::Win(Player::O)));
+        assert_eq!(board.legal_moves().len(), 0);
+        assert_eq!(board_after(Rules::Wild, &["a1=O"]).legal_moves().len(), 16);
+    }
+
+    #[test]
+    fn numerical_lines_add_up_to_fifteen() {
+        // anyone's numbers count: 8 + 1 + 6 along the top row
+        let board = board_after(Rules::Numerical, &["b1=1", "a1=8", "c2=3", "a3=2", "c3=5"]);
+        assert_eq!(board.result(), None);
+        let board = board_after(Rules::Numerical, &["b1=1", "a1=8", "c2=3", "a3=2", "c3=5", "c1=6"]);
+        assert_eq!(board.result(), Some(GameResult::Win(Player::O)));
+        let line: Vec<String> = board.winning_line().unwrap().iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(line, ["a1", "b1", "c1"]);
+        // O has played 8 and 2, so 4 and 6 are left for the four empty squares
+        let board = board_after(Rules::Numerical, &["b1=1", "a1=8", "c2=3", "a3=2", "c3=5"]);
+        assert_eq!(board.legal_moves().len(), 2 * 4);
+        assert_eq!(
+            board.check(Move::new(1, 0).with_piece(Some(Piece::Number(2)))),
+            Err(MoveError::Forbidden("2 is already on the board".to_owned()))
+        );
+    }
+
+    #[test]
+    fn rules_only_fit_some_boards() {
+        assert!(Rules::Misere.supports(Variant::Cube, 4, 4).is_ok());
+        assert!(Rules::Wild.supports(Variant::Ultimate, 9, 3).is_err());
+        assert!(Rules::Numerical.supports(Variant::Classic, 4, 4).is_err());
+        assert!(Rules::Renju.supports(Variant::Classic, 15, 5).is_ok());
+        assert!(Rules::Renju.supports(Variant::Gravity, 15, 5).is_err());
+        assert!(Rules::Renju.supports(Variant::Classic, 15, 4).is_err());
+    }
+
+    #[test]
+    fn perfect_play_under_each_rule() {
+        // misère 3x3 is a draw; wild is a win for whoever starts
+        assert_eq!(Minimax::perfect().search(&board_after(Rules::Misere, &[])).score, 0);
+        assert!(Minimax::perfect().search(&board_after(Rules::Wild, &[])).score > WIN / 2);
+    }
+}
@@ -0,0 +1,517 @@
+//! Games in the browser: `xo serve` hosts any number of two-player games
+//! at once, over a small HTTP API, and pushes everything that happens in
+//! them over WebSockets, to the players and to anyone else watching. The
+//! page itself is served from `/`.
+//!
+//! ```text
+//! GET  /                        the page
+//! GET  /games                   the lobby: every game not yet over
+//! POST /games                   name, and optionally variant, rules, size
+//!                               and k: a new game, with the caller as X
+//! GET  /games/<id>              where the game stands
+//! POST /games/<id>/join         name: take the O seat
+//! POST /games/<id>/moves        token, move: e.g. move=b2
+//! POST /games/<id>/resign       token
+//! GET  /games/<id>/socket       WebSocket: every event so far, then each
+//!                               new one as it happens; with a player's
+//!                               token, or none to spectate
+//! ```
+//!
+//! Parameters go in a form-encoded body (or the query string), and
+//! answers come back as JSON: the game, or `{"error": ...}` with a 4xx
+//! status. Creating or joining a game hands out a token that has to come
+//! with that player's moves, so nobody can move for anyone else, and
+//! spectators can only watch.
+
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::board::{Board, Player, Variant};
+use crate::http::{json_list, json_string, Request, Response};
+use crate::notation::parse_move;
+use crate::room::{Event, Room};
+use crate::rules::Rules;
+use crate::websocket::{self, Frame};
+
+/// The page, a board that plays through the API.
+const PAGE: &str = include_str!("client.html");
+
+/// Most games held at once, finished ones included.
+const MAX_GAMES: usize = 1000;
+
+/// How long a finished game is kept, for its page to show how it ended,
+/// and how long one nobody does anything in is, before making room for
+/// new games.
+const FINISHED_KEPT: Duration = Duration::from_secs(10 * 60);
+const IDLE_KEPT: Duration = Duration::from_secs(60 * 60);
+
+/// How long a client has to send its request, and how long a page has to
+/// take an update before it's given up on.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const PUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a page watching a game can say nothing before it's pinged,
+/// and then how long it has to answer before it's taken to be gone.
+const PING_AFTER: Duration = Duration::from_secs(30);
+
+/// Longest name a player can go by.
+const MAX_NAME: usize = 40;
+
+/// Every game being played, shared by the connections' threads.
+pub struct Server {
+    games: Mutex<Games>,
+}
+
+struct Games {
+    /// By id, so the lobby lists them oldest first.
+    tables: BTreeMap<u32, Table>,
+    next_id: u32,
+    /// A secret key from the OS's randomness, and how many tokens have
+    /// been made with it.
+    keys: RandomState,
+    tokens: u64,
+}
+
+/// A game, and the token each player in it moves with, X's first.
+struct Table {
+    room: Room,
+    tokens: Vec<String>,
+    /// When a player last did something.
+    touched: Instant,
+}
+
+type Reply = Result<Response, Response>;
+
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            games: Mutex::new(Games {
+                tables: BTreeMap::new(),
+                next_id: 1,
+                keys: RandomState::new(),
+                tokens: 0,
+            }),
+        }
+    }
+
+    fn games(&self) -> MutexGuard<'_, Games> {
+        // a thread that panicked can't have left a game half-changed:
+        // events are checked before anything is touched
+        self.games.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Answers one connection: a request, or a WebSocket for as long as
+    /// it stays open.
+    pub fn connection(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let request = match Request::read(&mut reader) {
+            Ok(request) => request,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                return Response::error(400, &e.to_string()).write_to(&mut &stream)
+            }
+            Err(e) => return Err(e),
+        };
+        if let ("GET", ["games", id, "socket"]) = (request.method.as_str(), segments(&request).as_slice()) {
+            return match (id.parse(), request.header("sec-websocket-key")) {
+                (Ok(id), Some(key)) if request.header_has("upgrade", "websocket") => {
+                    let token = request.params().remove("token");
+                    self.watch(id, key, token.as_deref(), stream, reader)
+                }
+                _ => Response::error(400, "that's for WebSockets").write_to(&mut &stream),
+            };
+        }
+        self.respond(&request).unwrap_or_else(|e| e).write_to(&mut &stream)
+    }
+
+    fn respond(&self, request: &Request) -> Reply {
+        let params = request.params();
+        let param = |name: &str| params.get(name).map(|v| v.trim()).filter(|v| !v.is_empty());
+        match (request.method.as_str(), segments(request).as_slice()) {
+            ("GET", [""]) => Ok(Response::html(PAGE)),
+            ("GET", ["games"]) => Ok(Response::json(200, self.lobby())),
+            ("POST", ["games"]) => self.create(param("name"), &param),
+            ("GET", ["games", id]) => Ok(Response::json(200, self.games().table(game_id(id)?)?.room.to_json())),
+            ("POST", ["games", id, "join"]) => self.join(game_id(id)?, param("name")),
+            ("POST", ["games", id, "moves"]) => {
+                let mv = param("move").ok_or_else(|| Response::error(400, "which move?"))?;
+                self.act(game_id(id)?, param("token"), |room, side| {
+                    let board = room.game().expect("only started games get this far").board();
+                    if board.to_move() != side {
+                        return Err(Response::error(409, "it's not your turn"));
+                    }
+                    parse_move(mv, board).map(Event::Moved).map_err(|e| Response::error(400, &e.to_string()))
+                })
+            }
+            ("POST", ["games", id, "resign"]) => {
+                self.act(game_id(id)?, param("token"), |_, side| Ok(Event::Resigned(side)))
+            }
+            (_, [""]) | (_, ["games", ..]) => Err(Response::error(405, "not with that method")),
+            _ => Err(Response::error(404, "there's nothing here")),
+        }
+    }
+
+    /// Every game that isn't over, oldest first: those waiting for a
+    /// player to join, and those being played, to watch.
+    fn lobby(&self) -> String {
+        let games = self.games();
+        let open = games.tables.values().filter(|t| !t.room.is_over()).map(|t| t.room.summary_json());
+        format!("{{\"games\":{}}}", json_list(open))
+    }
+
+    fn create<'a>(&self, name: Option<&str>, param: &dyn Fn(&str) -> Option<&'a str>) -> Reply {
+        let bad = |text: &str| Response::error(400, text);
+        let variant = match param("variant") {
+            Some(name) => Variant::from_name(&name.to_ascii_lowercase()).ok_or_else(|| bad("unknown variant"))?,
+            None => Variant::Classic,
+        };
+        let rules = match param("rules") {
+            Some(name) => Rules::from_name(&name.to_ascii_lowercase()).ok_or_else(|| bad("unknown rules"))?,
+            None => Rules::Standard,
+        };
+        let number =
+            |name: &str| param(name).map(|n| n.parse::<usize>().map_err(|_| bad(&format!("{} needs a number", name))));
+        let size = number("size").transpose()?.unwrap_or_else(|| variant.default_size());
+        let k = number("k").transpose()?.unwrap_or_else(|| variant.default_k(size));
+        Board::check_settings(variant, size, k, rules, 2).map_err(|e| bad(&e))?;
+        let board = Board::with_variant(variant, size, k).with_rules(rules);
+
+        let mut games = self.games();
+        let now = Instant::now();
+        games.sweep(now);
+        if games.tables.len() >= MAX_GAMES {
+            return Err(Response::error(409, "there are too many games going already"));
+        }
+        let id = games.next_id;
+        games.next_id += 1;
+        let token = games.token();
+        let mut room = Room::new(id, board);
+        room.record(Event::Joined(player_name(name, Player::X))).expect("the first seat is free");
+        let table = Table {
+            room,
+            tokens: vec![token],
+            touched: now,
+        };
+        let seat = table.seat_json(Player::X);
+        games.tables.insert(id, table);
+        Ok(Response::json(201, seat))
+    }
+
+    fn join(&self, id: u32, name: Option<&str>) -> Reply {
+        let mut games = self.games();
+        let token = games.token();
+        let table = games.table_mut(id)?;
+        table.room.record(Event::Joined(player_name(name, Player::O))).map_err(|e| Response::error(409, &e))?;
+        table.tokens.push(token);
+        table.touched = Instant::now();
+        Ok(Response::json(200, table.seat_json(Player::O)))
+    }
+
+    /// Lets the player with `token` do something to the game, if it's
+    /// under way: `event` says what, or why not.
+    fn act(&self, id: u32, token: Option<&str>, event: impl FnOnce(&Room, Player) -> Result<Event, Response>) -> Reply {
+        let mut games = self.games();
+        let table = games.table_mut(id)?;
+        let side = table.seat(token).ok_or_else(|| Response::error(403, "that's not a player's token"))?;
+        if table.room.game().is_none() {
+            return Err(Response::error(409, "the game hasn't started"));
+        }
+        if table.room.is_over() {
+            return Err(Response::error(409, "the game is over"));
+        }
+        let event = event(&table.room, side)?;
+        table.room.record(event).map_err(|e| Response::error(400, &e))?;
+        table.touched = Instant::now();
+        Ok(Response::json(200, table.room.to_json()))
+    }
+
+    /// Upgrades the connection to a WebSocket and keeps it on the game's
+    /// list until it closes, or goes quiet and doesn't answer a ping.
+    /// Updates are written from a thread of their own, outside the lock.
+    /// Nothing the page sends is needed, but it has to be read for pings,
+    /// pongs and the close.
+    fn watch(
+        &self,
+        id: u32,
+        key: &str,
+        token: Option<&str>,
+        stream: TcpStream,
+        mut reader: BufReader<TcpStream>,
+    ) -> io::Result<()> {
+        let (updates, queue) = mpsc::channel();
+        let watching = self.games().table_mut(id).map(|table| {
+            let seat = table.seat(token);
+            table.room.watch(updates.clone(), seat)
+        });
+        let watcher = match watching {
+            Ok(watcher) => watcher,
+            Err(refusal) => return refusal.write_to(&mut &stream),
+        };
+        // the catching up is queued already, and goes out after this
+        write!(
+            &stream,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            websocket::accept_key(key)
+        )?;
+        stream.set_write_timeout(Some(PUSH_TIMEOUT))?;
+        let pusher = stream.try_clone()?;
+        thread::spawn(move || push(pusher, queue));
+        stream.set_read_timeout(Some(PING_AFTER))?;
+        let mut pinged = false;
+        loop {
+            // waiting for the start of a frame, so a timeout can't cut one in two
+            let answer = match reader.fill_buf().map(|waiting| waiting.is_empty()) {
+                Ok(false) => {
+                    pinged = false;
+                    match Frame::read(&mut reader) {
+                        Ok(Frame::Ping(data)) => Some(Frame::Pong(data)),
+                        Ok(Frame::Close) | Err(_) => break,
+                        Ok(_) => None,
+                    }
+                }
+                Err(e) if timed_out(&e) && !pinged => {
+                    pinged = true;
+                    Some(Frame::Ping(Vec::new()))
+                }
+                _ => break,
+            };
+            // queued, so it can't land in the middle of an update
+            if let Some(answer) = answer {
+                if updates.send(answer).is_err() {
+                    break;
+                }
+            }
+        }
+        if let Ok(table) = self.games().table_mut(id) {
+            table.room.unwatch(watcher);
+        }
+        let _ = updates.send(Frame::Close);
+        Ok(())
+    }
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+/// Serves games to everyone who connects to `listener`, each connection
+/// in a thread of its own, as `xo serve` does.
+pub fn listen(listener: &TcpListener) -> io::Result<()> {
+    let server = Arc::new(Server::new());
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let server = server.clone();
+        thread::spawn(move || {
+            let _ = server.connection(stream);
+        });
+    }
+    Ok(())
+}
+
+/// Whether a read gave up for want of anything to read.
+fn timed_out(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+/// Writes the frames queued for one WebSocket until it's closed or stops
+/// taking them, then hangs up.
+fn push(stream: TcpStream, queue: Receiver<Frame>) {
+    for frame in queue {
+        if (&stream).write_all(&frame.to_bytes(None)).is_err() || frame == Frame::Close {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+impl Games {
+    fn table(&self, id: u32) -> Result<&Table, Response> {
+        self.tables.get(&id).ok_or_else(no_such_game)
+    }
+
+    fn table_mut(&mut self, id: u32) -> Result<&mut Table, Response> {
+        self.tables.get_mut(&id).ok_or_else(no_such_game)
+    }
+
+    /// Lets go of the games not worth keeping any more at `now`.
+    fn sweep(&mut self, now: Instant) {
+        self.tables.retain(|_, table| table.is_kept(now));
+    }
+
+    /// A token nobody can work out from the ones they've been given: the
+    /// count hashed with the secret key, rather than anything from a
+    /// generator whose state a player could recover.
+    fn token(&mut self) -> String {
+        let mut half = || {
+            self.tokens += 1;
+            let mut hasher = self.keys.build_hasher();
+            hasher.write_u64(self.tokens);
+            hasher.finish()
+        };
+        format!("{:016x}{:016x}", half(), half())
+    }
+}
+
+impl Table {
+    /// Whether it's still worth keeping at `now`: finished not long ago,
+    /// or not left alone for long.
+    fn is_kept(&self, now: Instant) -> bool {
+        let kept = if self.room.is_over() { FINISHED_KEPT } else { IDLE_KEPT };
+        now.duration_since(self.touched) < kept
+    }
+
+    /// The side `token` is for, if it's a player's.
+    fn seat(&self, token: Option<&str>) -> Option<Player> {
+        let token = token?;
+        self.tokens.iter().position(|mine| same_token(mine, token)).map(Player::nth)
+    }
+
+    /// What a player is told on taking a seat: the token to move with,
+    /// the side it's for, and the game.
+    fn seat_json(&self, side: Player) -> String {
+        format!(
+            "{{\"id\":{},\"token\":{},\"side\":{},\"game\":{}}}",
+            self.room.id(),
+            json_string(&self.tokens[side.index()]),
+            json_string(&side.to_string()),
+            self.room.to_json()
+        )
+    }
+}
+
+/// Whether `a` and `b` are the same token, looking at every byte whatever
+/// the first difference, so timing a guess doesn't tell how much of it is
+/// right.
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The path split at its slashes, `[""]` for `/`.
+fn segments(request: &Request) -> Vec<&str> {
+    request.path.trim_matches('/').split('/').collect()
+}
+
+fn game_id(id: &str) -> Result<u32, Response> {
+    id.parse().map_err(|_| no_such_game())
+}
+
+fn no_such_game() -> Response {
+    Response::error(404, "there's no such game")
+}
+
+/// The name asked for, kept to one short line, or X's or O's default.
+fn player_name(name: Option<&str>, side: Player) -> String {
+    match name {
+        Some(name) => name.chars().filter(|c| !c.is_control()).take(MAX_NAME).collect(),
+        None => format!("Player {}", side.index() + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(server: &Server, method: &str, path: &str, body: &str) -> Response {
+        let request = format!("{} {} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body);
+        let request = Request::read(&mut request.as_bytes()).unwrap();
+        server.respond(&request).unwrap_or_else(|e| e)
+    }
+
+    fn post(server: &Server, path: &str, body: &str) -> Response {
+        send(server, "POST", path, body)
+    }
+
+    fn body(response: &Response) -> String {
+        String::from_utf8(response.body.clone()).unwrap()
+    }
+
+    fn field(response: &Response, name: &str) -> String {
+        let body = body(response);
+        let start = body.find(&format!("\"{}\":\"", name)).unwrap() + name.len() + 4;
+        body[start..].split('"').next().unwrap().to_owned()
+    }
+
+    #[test]
+    fn only_the_player_to_move_can_move() {
+        let server = Server::new();
+        let created = post(&server, "/games", "name=Ann&size=4&k=3");
+        assert_eq!(created.status, 201);
+        let ann = field(&created, "token");
+        assert_eq!(post(&server, "/games/1/moves", &format!("token={}&move=b2", ann)).status, 409);
+        let joined = post(&server, "/games/1/join", "name=Bob");
+        let bob = field(&joined, "token");
+        assert_eq!(field(&joined, "side"), "O");
+        assert_eq!(post(&server, "/games/1/join", "name=Cy").status, 409);
+        assert_eq!(post(&server, "/games/1/moves", &format!("token={}&move=b2", bob)).status, 409);
+        assert_eq!(post(&server, "/games/1/moves", "token=guess&move=b2").status, 403);
+        let moved = post(&server, "/games/1/moves", &format!("token={}&move=b2", ann));
+        assert_eq!((moved.status, field(&moved, "to_move")), (200, "O".to_owned()));
+        assert_eq!(post(&server, "/games/1/moves", &format!("token={}&move=b2", bob)).status, 400);
+        let resigned = post(&server, "/games/1/resign", &format!("token={}", bob));
+        assert_eq!(field(&resigned, "result"), "Bob resigns. Ann wins!");
+        assert_eq!(post(&server, "/games/2/join", "").status, 404);
+    }
+
+    #[test]
+    fn tokens_match_only_in_full() {
+        let token = "0123456789abcdef0123456789abcdef";
+        assert!(same_token(token, token));
+        assert!(!same_token(token, "0123456789abcdef0123456789abcdee"));
+        assert!(!same_token(token, "1123456789abcdef0123456789abcdef"));
+        assert!(!same_token(token, "0123456789abcdef"));
+        assert!(!same_token(token, ""));
+    }
+
+    #[test]
+    fn refuses_boards_that_cant_be_played() {
+        let server = Server::new();
+        assert_eq!(post(&server, "/games", "size=3&k=4").status, 400);
+        assert_eq!(post(&server, "/games", "variant=hex").status, 400);
+        let gravity = post(&server, "/games", "variant=gravity");
+        assert_eq!(field(&gravity, "at"), "a");
+    }
+
+    #[test]
+    fn the_lobby_lists_games_until_they_end() {
+        let server = Server::new();
+        post(&server, "/games", "name=Ann");
+        post(&server, "/games", "name=Cy&variant=cube");
+        let joined = post(&server, "/games/1/join", "name=Bob");
+        let lobby = body(&send(&server, "GET", "/games", ""));
+        assert!(lobby.starts_with("{\"games\":[{\"id\":1,"), "{}", lobby);
+        assert!(lobby.contains("\"players\":[\"Ann\",\"Bob\"],\"status\":\"playing\",\"spectators\":0}"));
+        assert!(lobby.contains("\"players\":[\"Cy\"],\"status\":\"waiting\""));
+        post(&server, "/games/1/resign", &format!("token={}", field(&joined, "token")));
+        let lobby = body(&send(&server, "GET", "/games", ""));
+        assert!(lobby.starts_with("{\"games\":[{\"id\":2,") && !lobby.contains("Ann"), "{}", lobby);
+    }
+
+    #[test]
+    fn makes_room_by_letting_old_games_go() {
+        let server = Server::new();
+        for _ in 0..MAX_GAMES {
+            assert_eq!(post(&server, "/games", "name=Ann").status, 201);
+        }
+        assert_eq!(post(&server, "/games", "name=Cy").status, 409);
+        let joined = post(&server, "/games/1/join", "name=Bob");
+        post(&server, "/games/1/resign", &format!("token={}", field(&joined, "token")));
+        // a game that's just ended is still there to see
+        assert_eq!(post(&server, "/games", "name=Cy").status, 409);
+        let later = Instant::now() + FINISHED_KEPT;
+        server.games().sweep(later);
+        assert_eq!(send(&server, "GET", "/games/1", "").status, 404);
+        // as is one waiting for someone to join, until it's been a long while
+        assert_eq!(server.games().tables.len(), MAX_GAMES - 1);
+        server.games().sweep(later + IDLE_KEPT);
+        assert_eq!(post(&server, "/games", "name=Cy").status, 201);
+    }
+}
@@ -0,0 +1,446 @@
+//! Classic 3x3 solved outright: every reachable position, its value with
+//! perfect play and the moves that keep it, in a table small enough to
+//! keep on disk and look up instantly.
+//!
+//! Positions are stored once per symmetry class. A position's key is its
+//! squares read as a base-3 number (0 empty, 1 X, 2 O, a1 the lowest
+//! digit), and the class is filed under the smallest key among its eight
+//! rotations and reflections, as `Board::symmetries` gives them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use crate::bitboard::Bitboard;
+use crate::board::{Board, GameResult, Move, Player, Variant};
+use crate::rules::Rules;
+use crate::strategy::Strategy;
+
+/// Start of a saved table, so any other file is refused.
+const MAGIC: &[u8; 4] = b"XO3\x01";
+
+/// Bytes per position: key (2), value (1), best moves (2).
+const ENTRY_BYTES: usize = 5;
+
+/// How a position ends with perfect play, for the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// What the table knows about one position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Value {
+    pub outcome: Outcome,
+    /// Plies until the game ends, with the winner hurrying and the loser
+    /// holding out as long as they can.
+    pub plies: u8,
+}
+
+impl Value {
+    /// The same game seen from the other side, one ply earlier.
+    fn flipped(self) -> Value {
+        let outcome = match self.outcome {
+            Outcome::Win => Outcome::Loss,
+            Outcome::Draw => Outcome::Draw,
+            Outcome::Loss => Outcome::Win,
+        };
+        Value {
+            outcome,
+            plies: self.plies + 1,
+        }
+    }
+
+    /// Higher is better for the side to move.
+    fn rank(self) -> i32 {
+        match self.outcome {
+            Outcome::Win => 100 - self.plies as i32,
+            Outcome::Draw => 0,
+            Outcome::Loss => -100 + self.plies as i32,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        let outcome = match self.outcome {
+            Outcome::Loss => 0,
+            Outcome::Draw => 1,
+            Outcome::Win => 2,
+        };
+        outcome | self.plies << 4
+    }
+
+    fn from_byte(b: u8) -> Option<Value> {
+        let outcome = match b & 0x0f {
+            0 => Outcome::Loss,
+            1 => Outcome::Draw,
+            2 => Outcome::Win,
+            _ => return None,
+        };
+        Some(Value { outcome, plies: b >> 4 })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = if self.plies == 1 { "" } else { "s" };
+        match self.outcome {
+            Outcome::Win => write!(f, "win in {} ply{}", self.plies, plural),
+            Outcome::Loss => write!(f, "loss in {} ply{}", self.plies, plural),
+            Outcome::Draw => write!(f, "draw"),
+        }
+    }
+}
+
+/// A position looked up in the table, turned back to the way it is on
+/// the board.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lookup {
+    pub value: Value,
+    /// Every move that keeps `value`, in reading order.
+    pub best: Vec<Move>,
+}
+
+/// The solved table: one entry per reachable position up to symmetry.
+#[derive(Clone, Debug)]
+pub struct Database {
+    entries: HashMap<u16, (Value, u16)>,
+    symmetries: Vec<Vec<usize>>,
+}
+
+impl Database {
+    fn empty() -> Database {
+        Database {
+            entries: HashMap::new(),
+            symmetries: Board::new(3, 3).symmetries(),
+        }
+    }
+
+    /// Works out every position from scratch. Takes a few milliseconds.
+    pub fn generate() -> Database {
+        let mut db = Database::empty();
+        db.solve(&mut Board::new(3, 3));
+        db
+    }
+
+    /// Whether `board` is a game the table covers: classic 3x3.
+    pub fn covers(board: &Board) -> bool {
+        board.variant() == Variant::Classic
+            && board.size() == 3
+            && board.k() == 3
+            && board.rules() == Rules::Standard
+            && board.players() == 2
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Negamax over the whole game, filling in the table as it goes.
+    fn solve(&mut self, board: &mut Board) -> Value {
+        let (key, symmetry) = self.canonical(board);
+        if let Some(&(value, _)) = self.entries.get(&key) {
+            return value;
+        }
+        let (value, best) = match board.result() {
+            // the side to move never made the winning line
+            Some(GameResult::Win(_)) => (Value { outcome: Outcome::Loss, plies: 0 }, 0),
+            Some(GameResult::Draw) => (Value { outcome: Outcome::Draw, plies: 0 }, 0),
+            None => {
+                let mut scored = Vec::new();
+                for mv in board.legal_moves() {
+                    board.play(mv).expect("legal moves can be played");
+                    scored.push((mv, self.solve(board).flipped()));
+                    board.undo();
+                }
+                let value = scored.iter().map(|&(_, v)| v).max_by_key(|v| v.rank()).expect("an open board has moves");
+                let best = scored.iter().filter(|&&(_, v)| v == value).fold(0, |mask, &(mv, _)| mask | bit(mv));
+                (value, best)
+            }
+        };
+        // the moves found are for this orientation; file them for the canonical one
+        let perm = &self.symmetries[symmetry];
+        let best = (0..9).filter(|&i| best & 1 << i != 0).fold(0, |mask, i| mask | 1 << perm[i]);
+        self.entries.insert(key, (value, best));
+        value
+    }
+
+    /// What perfect play makes of `board`, or `None` if the table doesn't
+    /// cover it.
+    pub fn lookup(&self, board: &Board) -> Option<Lookup> {
+        if !Database::covers(board) {
+            return None;
+        }
+        let (key, symmetry) = self.canonical(board);
+        let &(value, best) = self.entries.get(&key)?;
+        let perm = &self.symmetries[symmetry];
+        let best = (0..9).filter(|&i| best & 1 << perm[i] != 0).map(square).collect();
+        Some(Lookup { value, best })
+    }
+
+    fn canonical(&self, board: &Board) -> (u16, usize) {
+        canonical(&self.symmetries, board)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut keys: Vec<&u16> = self.entries.keys().collect();
+        keys.sort();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(keys.len() as u16).to_le_bytes());
+        for key in keys {
+            let (value, best) = self.entries[key];
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.push(value.to_byte());
+            bytes.extend_from_slice(&best.to_le_bytes());
+        }
+        fs::write(path, bytes)
+    }
+
+    pub fn load(path: &str) -> Result<Database, String> {
+        let bytes = fs::read(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+        let bad = || format!("{} isn't a solved-game table", path);
+        let body = bytes.strip_prefix(&MAGIC[..]).ok_or_else(bad)?;
+        if body.len() < 2 {
+            return Err(bad());
+        }
+        let count = u16::from_le_bytes([body[0], body[1]]) as usize;
+        let body = &body[2..];
+        if body.len() != count * ENTRY_BYTES {
+            return Err(bad());
+        }
+        let mut db = Database::empty();
+        for entry in body.chunks(ENTRY_BYTES) {
+            let key = u16::from_le_bytes([entry[0], entry[1]]);
+            let value = Value::from_byte(entry[2]).ok_or_else(bad)?;
+            let best = u16::from_le_bytes([entry[3], entry[4]]);
+            db.entries.insert(key, (value, best));
+        }
+        Ok(db)
+    }
+}
+
+/// The smallest key among a 3x3 position's `symmetries`, and which
+/// symmetry gives it. Also how `learn` files its boxes.
+pub(crate) fn canonical(symmetries: &[Vec<usize>], board: &Board) -> (u16, usize) {
+    let (x, o) = (board.marks(Player::X), board.marks(Player::O));
+    symmetries
+        .iter()
+        .enumerate()
+        .map(|(s, perm)| (key_of(&x.permuted(perm), &o.permuted(perm)), s))
+        .min()
+        .expect("the identity is always there")
+}
+
+fn square(i: usize) -> Move {
+    Move::new(i / 3, i % 3)
+}
+
+fn bit(mv: Move) -> u16 {
+    1 << (mv.row * 3 + mv.col)
+}
+
+/// A position as a base-3 number.
+fn key_of(x: &Bitboard, o: &Bitboard) -> u16 {
+    (0..9).rev().fold(0, |key, i| {
+        key * 3
+            + if x.contains(i) {
+                1
+            } else if o.contains(i) {
+                2
+            } else {
+                0
+            }
+    })
+}
+
+/// Reads a position for `xo analyze`: either nine squares in reading
+/// order, such as `x.o.x....` (`.`, `-` or `_` for empty), or the moves
+/// that lead to it, such as `b2 a1 c3`. The position has to be one a real
+/// game can reach.
+pub fn parse_position(text: &str) -> Result<Board, String> {
+    let squares: Vec<char> = text.chars().filter(|c| !c.is_whitespace() && *c != '/').collect();
+    let is_grid = squares.len() == 9 && squares.iter().all(|c| "xXoO.-_".contains(*c));
+    if !is_grid {
+        let mut board = Board::new(3, 3);
+        for word in text.split_whitespace() {
+            let mv = crate::notation::parse_move(word, &board).map_err(|e| format!("{}: {}", word, e))?;
+            board.play(mv).map_err(|e| format!("{}: {}", word, e))?;
+        }
+        return Ok(board);
+    }
+    let target: Vec<Option<Player>> = squares
+        .iter()
+        .map(|c| match c {
+            'x' | 'X' => Some(Player::X),
+            'o' | 'O' => Some(Player::O),
+            _ => None,
+        })
+        .collect();
+    let mut board = Board::new(3, 3);
+    if reach(&mut board, &target) {
+        Ok(board)
+    } else {
+        Err(format!("no game reaches {}", text.trim()))
+    }
+}
+
+/// Looks for an order of play that ends up at `target` without the game
+/// finishing early.
+fn reach(board: &mut Board, target: &[Option<Player>]) -> bool {
+    let remaining = (0..9).filter(|&i| target[i].is_some() && board.cell(i).is_none()).count();
+    if remaining == 0 {
+        return true;
+    }
+    if board.result().is_some() {
+        return false;
+    }
+    for i in 0..9 {
+        if board.cell(i).is_none() && target[i] == Some(board.to_move()) {
+            board.play(square(i)).expect("empty squares are legal");
+            if reach(board, target) {
+                return true;
+            }
+            board.undo();
+        }
+    }
+    false
+}
+
+/// What perfect play makes of a 3x3 position, given as for
+/// `parse_position`, and of every move from it: the board and then a line
+/// per move.
+pub fn analyze(position: &str, db: &Database) -> Result<String, String> {
+    let mut board = parse_position(position)?;
+    let mut out = format!("{}\n\n", board);
+    if let Some(result) = board.result() {
+        out += &format!("The game is over: {}.", result);
+        return Ok(out);
+    }
+    let side = board.to_move();
+    let found = db.lookup(&board).ok_or("that position isn't in the table")?;
+    let best: Vec<String> = found.best.iter().map(|mv| mv.to_string()).collect();
+    out += &format!("{} to move: {}. Best: {}.", side, found.value, best.join(" "));
+    for mv in board.legal_moves() {
+        board.play(mv).expect("legal moves can be played");
+        let after = match board.result() {
+            Some(GameResult::Draw) => "draws".to_owned(),
+            Some(_) => "wins on the spot".to_owned(),
+            // the table speaks for the opponent now
+            None => {
+                let value = db.lookup(&board).expect("every reachable position is in the table").value;
+                match value.outcome {
+                    Outcome::Win => format!("loses in {}", value.plies + 1),
+                    Outcome::Loss => format!("wins in {}", value.plies + 1),
+                    Outcome::Draw => "draws".to_owned(),
+                }
+            }
+        };
+        board.undo();
+        out += &format!("\n  {:<4}{}", mv.to_string(), after);
+    }
+    Ok(out)
+}
+
+/// Plays straight from the table: always one of the best moves, taking
+/// the first in reading order so games are repeatable.
+pub struct Oracle {
+    db: Database,
+    last: Option<Value>,
+}
+
+impl Oracle {
+    pub fn new(db: Database) -> Oracle {
+        Oracle { db, last: None }
+    }
+}
+
+impl Strategy for Oracle {
+    fn name(&self) -> String {
+        "Solved".to_owned()
+    }
+
+    fn choose_move(&mut self, board: &Board) -> Option<Move> {
+        let found = self.db.lookup(board)?;
+        self.last = Some(found.value);
+        found.best.first().copied()
+    }
+
+    fn comment(&self) -> Option<String> {
+        self.last.map(|v| v.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minimax::Minimax;
+
+    #[test]
+    fn the_table_has_every_position_once() {
+        let db = Database::generate();
+        // 5,478 legal positions fall into 765 classes under symmetry
+        assert_eq!(db.len(), 765);
+        let empty = db.lookup(&Board::new(3, 3)).unwrap();
+        assert_eq!(empty.value, Value { outcome: Outcome::Draw, plies: 9 });
+        assert_eq!(empty.best.len(), 9, "every first move draws");
+    }
+
+    #[test]
+    fn lookups_agree_with_search() {
+        let db = Database::generate();
+        let mut rng = crate::rng::Rng::new(3);
+        for _ in 0..50 {
+            let mut board = Board::new(3, 3);
+            while board.result().is_none() {
+                let found = db.lookup(&board).unwrap();
+                let search = Minimax::perfect().search(&board);
+                let outcome = match search.score {
+                    0 => Outcome::Draw,
+                    s if s > 0 => Outcome::Win,
+                    _ => Outcome::Loss,
+                };
+                assert_eq!(found.value.outcome, outcome, "{}", board);
+                assert!(found.best.contains(&search.best.unwrap()) || found.value.outcome == Outcome::Draw);
+                let moves = board.legal_moves();
+                board.play(moves[rng.below(moves.len())]).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let db = Database::generate();
+        let path = std::env::temp_dir().join(format!("xo-solved-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        db.save(path).unwrap();
+        assert_eq!(fs::metadata(path).unwrap().len() as usize, 6 + 765 * ENTRY_BYTES);
+        let loaded = Database::load(path).unwrap();
+        assert_eq!(loaded.entries, db.entries);
+        fs::write(path, b"not a table").unwrap();
+        assert!(Database::load(path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_positions_both_ways() {
+        let grid = parse_position("x.. .o. ..x").unwrap();
+        let moves = parse_position("a1 b2 c3").unwrap();
+        assert_eq!(grid.hash(), moves.hash());
+        // three moves in, so O is to move
+        assert_eq!(grid.to_move(), Player::O);
+        assert!(parse_position("xxx ooo ...").is_err());
+        assert!(parse_position("xx. ... ...").is_err());
+
+        let db = Database::generate();
+        // O must take an edge here or lose
+        let found = db.lookup(&grid).unwrap();
+        assert_eq!(found.value.outcome, Outcome::Draw);
+        assert_eq!(found.best, vec![Move::new(0, 1), Move::new(1, 0), Move::new(1, 2), Move::new(2, 1)]);
+    }
+}
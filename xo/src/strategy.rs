@@ -0,0 +1,183 @@
+use std::io::{self, stdin, stdout, Write};
+use std::time::Duration;
+
+use crate::board::{Board, Move, Player, Variant};
+use crate::clock::Clock;
+use crate::minimax::evaluate_for;
+use crate::notation::parse_move;
+use crate::opening::Choice;
+
+/// What a player asks for on their turn. Engines only ever move; people
+/// can also step through the history, ask for a hint, save, chat or give
+/// up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Move(Move),
+    Undo,
+    Redo,
+    Hint,
+    Save(String),
+    Chat(String),
+    Resign,
+    Quit,
+}
+
+/// Anything that can pick a move: a person at the keyboard or an engine.
+pub trait Strategy {
+    /// Shown in prompts and announcements, e.g. "Player X" or "Computer".
+    fn name(&self) -> String;
+
+    /// Picks a move for `board.to_move()`. `None` means the player gave up
+    /// (or, for a human, closed stdin).
+    fn choose_move(&mut self, board: &Board) -> Option<Move>;
+
+    /// Like `choose_move`, but for players who can do more than move.
+    fn choose_action(&mut self, board: &Board) -> Action {
+        match self.choose_move(board) {
+            Some(mv) => Action::Move(mv),
+            None => Action::Quit,
+        }
+    }
+
+    /// What an engine thought of its last move, if it has anything to say.
+    fn comment(&self) -> Option<String> {
+        None
+    }
+
+    /// In a game against the clock, how long the engine may take over its
+    /// next move. People, and engines too quick to need it, ignore it.
+    fn think_for(&mut self, _time: Duration) {}
+
+    /// In a game against the clock, the clocks as they stand when it's a
+    /// person's turn, theirs running. The full-screen board counts it
+    /// down while they choose and gives up the moment it runs out; others
+    /// ignore it.
+    fn use_clock(&mut self, _clock: &Clock) {}
+
+    /// In a swap2 opening, which of `choices` to make with the stones on
+    /// `board`. By default whichever side looks better at a glance.
+    /// `None` means the player gave up.
+    fn choose_swap(&mut self, board: &Board, _choices: &[Choice]) -> Option<Choice> {
+        Some(Choice::by_score(evaluate_for(board, Player::X)))
+    }
+}
+
+/// Reads moves from stdin, asking again until it gets one that parses.
+/// Also understands `undo`, `redo`, `hint` (or `?`), `save <file>`,
+/// `say <text>`, `resign` and `quit`.
+pub struct Human {
+    name: String,
+}
+
+impl Human {
+    pub fn new(name: &str) -> Human {
+        Human {
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl Strategy for Human {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose_move(&mut self, board: &Board) -> Option<Move> {
+        loop {
+            match self.choose_action(board) {
+                Action::Move(mv) => return Some(mv),
+                Action::Quit => return None,
+                _ => println!("Sorry, you can only make a move here."),
+            }
+        }
+    }
+
+    fn choose_action(&mut self, board: &Board) -> Action {
+        loop {
+            print!("{}, please make a move ({}): ", self.name, how_to_move(board));
+            let _ = stdout().flush();
+
+            let s = match read_answer() {
+                Ok(Some(s)) => s,
+                // stdin closed, nobody left to play
+                Ok(None) => return Action::Quit,
+                Err(e) => {
+                    println!("Sorry, {}. Try again.", e);
+                    continue;
+                }
+            };
+            let mut words = s.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("undo"), None) => return Action::Undo,
+                (Some("redo"), None) => return Action::Redo,
+                (Some("hint"), None) | (Some("?"), None) => return Action::Hint,
+                (Some("quit"), None) => return Action::Quit,
+                (Some("resign"), None) => return Action::Resign,
+                (Some("say"), Some(_)) => {
+                    let text = s.trim_start()["say".len()..].trim();
+                    return Action::Chat(text.to_owned());
+                }
+                (Some("save"), Some(path)) => return Action::Save(path.to_owned()),
+                (Some("save"), None) => {
+                    println!("Sorry, save needs a file name, e.g. save game.xo.");
+                    continue;
+                }
+                _ => {}
+            }
+            match parse_move(&s, board) {
+                Ok(mv) => return Action::Move(mv),
+                Err(e) => println!("Sorry, {}. Try again.", e),
+            }
+        }
+    }
+
+    fn choose_swap(&mut self, _board: &Board, choices: &[Choice]) -> Option<Choice> {
+        let options: Vec<String> = choices.iter().map(|c| format!("{} to {}", c.name(), c)).collect();
+        loop {
+            print!("{}, type {}: ", self.name, options.join(", "));
+            let _ = stdout().flush();
+            let s = match read_answer() {
+                Ok(Some(s)) => s,
+                Ok(None) => return None,
+                Err(e) => {
+                    println!("Sorry, {}. Try again.", e);
+                    continue;
+                }
+            };
+            match Choice::from_name(s.trim()) {
+                Some(choice) if choices.contains(&choice) => return Some(choice),
+                _ if s.trim() == "quit" => return None,
+                _ => println!("Sorry, that isn't one of the choices."),
+            }
+        }
+    }
+}
+
+/// The next line typed, `None` once stdin is closed or can't be read any
+/// more, or the error for a line that isn't text, to ask again.
+fn read_answer() -> io::Result<Option<String>> {
+    let mut s = String::new();
+    match stdin().read_line(&mut s) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(s)),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Err(e),
+        Err(_) => Ok(None),
+    }
+}
+
+/// What goes in a move on this board, with an example.
+fn how_to_move(board: &Board) -> String {
+    if board.variant() == Variant::Gravity {
+        return "column, e.g. d".to_owned();
+    }
+    let (mut what, mut example) = ("column then row".to_owned(), Move::new(1, 1));
+    if board.layers() > 1 {
+        what = format!("layer, {}", what);
+        example = Move::layered(1, 1, 1);
+    }
+    if board.rules().picks_pieces() {
+        what += ", then what to put there";
+        example = example.with_piece(board.rules().pieces(board).first().copied().flatten());
+    }
+    format!("{}, e.g. {}", what, example)
+}
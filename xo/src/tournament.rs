@@ -0,0 +1,324 @@
+//! Round-robin matches between engines, so changes to them can be judged
+//! by results rather than by feel.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::baseline::{Greedy, RandomPlayer};
+use crate::board::{Board, GameResult, Player};
+use crate::engine::External;
+use crate::learn::Menace;
+use crate::mcts::{Budget, Mcts};
+use crate::minimax::Minimax;
+use crate::opening::Opening;
+use crate::rng::Rng;
+use crate::strategy::Strategy;
+
+/// The Elo rating of an average player in the field.
+const AVERAGE_ELO: f64 = 1500.0;
+
+/// One player in a tournament, as given on the command line:
+/// `random`, `greedy`, `minimax`, `minimax:4` (depth), `minimax:200ms`
+/// (time per move), `mcts:5000` (playouts), `mcts:200ms`,
+/// `engine:./bot --fast` for a program speaking the engine protocol, or
+/// `remote:host:7879` for one listening on the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Entrant {
+    Random,
+    Greedy,
+    /// `None` picks the depth for the board, as in a normal game.
+    Minimax(Option<u32>),
+    TimedMinimax(Duration),
+    Mcts(Budget),
+    /// The command that starts it.
+    External(String),
+    /// Where it listens, `HOST:PORT`.
+    Remote(String),
+    /// The bead table it plays from, as `xo train` saves it. An
+    /// unreadable table plays as an untrained one.
+    Menace(String),
+}
+
+impl Entrant {
+    /// A fresh player for one game.
+    pub fn build(&self, board: &Board, rng: Rng) -> Box<dyn Strategy> {
+        match self {
+            Entrant::Random => Box::new(RandomPlayer::new(rng)),
+            Entrant::Greedy => Box::new(Greedy::new(rng)),
+            Entrant::Minimax(Some(depth)) => Box::new(Minimax::with_depth(*depth)),
+            Entrant::Minimax(None) => Box::new(Minimax::for_board(board)),
+            Entrant::TimedMinimax(time) => Box::new(Minimax::timed(*time)),
+            Entrant::Mcts(budget) => Box::new(Mcts::new(*budget, rng)),
+            Entrant::External(command) => Box::new(External::new(command)),
+            Entrant::Remote(addr) => Box::new(External::connect(addr)),
+            Entrant::Menace(path) => Box::new(Menace::load(path, rng.clone()).unwrap_or_else(|_| Menace::new(rng))),
+        }
+    }
+}
+
+impl FromStr for Entrant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Entrant, String> {
+        // commands are case-sensitive, everything else isn't
+        if let Some(command) = s.strip_prefix("engine:").filter(|c| !c.trim().is_empty()) {
+            return Ok(Entrant::External(command.trim().to_owned()));
+        }
+        if let Some(addr) = s.strip_prefix("remote:").filter(|a| a.contains(':')) {
+            return Ok(Entrant::Remote(addr.trim().to_owned()));
+        }
+        if let Some(path) = s.strip_prefix("menace:").filter(|p| !p.trim().is_empty()) {
+            return Ok(Entrant::Menace(path.trim().to_owned()));
+        }
+        let lower = s.to_ascii_lowercase();
+        let s = lower.as_str();
+        let bad = || format!("unknown player {}", s);
+        let (kind, setting) = match s.split_once(':') {
+            Some((kind, setting)) => (kind, Some(setting)),
+            None => (s, None),
+        };
+        match (kind, setting) {
+            ("random", None) => Ok(Entrant::Random),
+            ("greedy", None) => Ok(Entrant::Greedy),
+            ("minimax", None) => Ok(Entrant::Minimax(None)),
+            ("minimax", Some(depth)) => match depth.strip_suffix("ms") {
+                Some(ms) => ms.parse().map(|ms| Entrant::TimedMinimax(Duration::from_millis(ms))),
+                None => depth.parse().map(|d| Entrant::Minimax(Some(d))),
+            }
+            .map_err(|_| bad()),
+            ("mcts", None) => Ok(Entrant::Mcts(Budget::Playouts(10_000))),
+            ("mcts", Some(budget)) => match budget.strip_suffix("ms") {
+                Some(ms) => ms.parse().map(|ms| Entrant::Mcts(Budget::Time(Duration::from_millis(ms)))),
+                None => budget.parse().map(|n| Entrant::Mcts(Budget::Playouts(n))),
+            }
+            .map_err(|_| bad()),
+            _ => Err(bad()),
+        }
+    }
+}
+
+/// Who beat whom, and how often.
+#[derive(Clone, Debug)]
+pub struct Crosstable {
+    pub names: Vec<String>,
+    /// `scores[i][j]` is wins, draws and losses of player `i` against `j`.
+    pub scores: Vec<Vec<[u32; 3]>>,
+    pub games_per_pairing: u32,
+}
+
+/// Plays `games` games between every pair of entrants on boards like
+/// `board`, each going first in every other game: taking X, or under
+/// swap2 putting the first stones down. Results are printed as each
+/// pairing finishes. The same seed plays the same games, as long as no
+/// entrant searches on a clock.
+pub fn run(board: &Board, entrants: &[Entrant], games: u32, seed: u64, opening: Opening) -> Crosstable {
+    let mut rng = Rng::new(seed);
+    let names: Vec<String> = entrants
+        .iter()
+        .map(|e| e.build(board, Rng::new(0)).name())
+        .collect();
+    let n = entrants.len();
+    let mut scores = vec![vec![[0; 3]; n]; n];
+    for a in 0..n {
+        for b in a + 1..n {
+            for game in 0..games {
+                let (x, o) = if game % 2 == 0 { (a, b) } else { (b, a) };
+                let mut players = [
+                    entrants[x].build(board, Rng::new(rng.next_u64())),
+                    entrants[o].build(board, Rng::new(rng.next_u64())),
+                ];
+                let (winner, loser) = match play(board, &mut players, opening) {
+                    GameResult::Win(Player::X) => (x, o),
+                    GameResult::Win(_) => (o, x),
+                    GameResult::Draw => {
+                        scores[a][b][1] += 1;
+                        scores[b][a][1] += 1;
+                        continue;
+                    }
+                };
+                scores[winner][loser][0] += 1;
+                scores[loser][winner][2] += 1;
+            }
+            let [w, d, l] = scores[a][b];
+            println!("{} vs {}: +{} ={} -{}", names[a], names[b], w, d, l);
+        }
+    }
+    Crosstable {
+        names,
+        scores,
+        games_per_pairing: games,
+    }
+}
+
+/// Plays one game to the end. A player with no move or choice to give
+/// loses. The result is by seat, so a win for X is a win for the first
+/// of `players` whichever side they ended up on.
+fn play(board: &Board, players: &mut [Box<dyn Strategy>; 2], opening: Opening) -> GameResult {
+    let mut board = board.clone();
+    let mut swap2 = opening.start();
+    while board.result().is_none() {
+        if let Some(swap2) = &mut swap2 {
+            if let Some((seat, choices)) = swap2.pending(&board) {
+                match players[seat].choose_swap(&board, choices) {
+                    Some(choice) if swap2.choose(&board, choice).is_ok() => continue,
+                    _ => return GameResult::Win(Player::nth(1 - seat)),
+                }
+            }
+        }
+        let seat = swap2.map_or(board.to_move().index(), |s| s.seat(&board));
+        let mv = match players[seat].choose_move(&board) {
+            Some(mv) => mv,
+            None => return GameResult::Win(Player::nth(1 - seat)),
+        };
+        if board.play(mv).is_err() {
+            return GameResult::Win(Player::nth(1 - seat));
+        }
+    }
+    match board.result().expect("the loop only ends when the game does") {
+        GameResult::Win(p) => GameResult::Win(Player::nth(swap2.map_or(p.index(), |s| s.seat_of(p)))),
+        GameResult::Draw => GameResult::Draw,
+    }
+}
+
+/// Chance a player rated `diff` points above their opponent scores a win.
+fn expected(diff: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-diff / 400.0))
+}
+
+impl Crosstable {
+    /// Wins, draws and losses against the whole field.
+    pub fn totals(&self, i: usize) -> [u32; 3] {
+        self.scores[i].iter().fold([0; 3], |t, s| [t[0] + s[0], t[1] + s[1], t[2] + s[2]])
+    }
+
+    /// Ratings that best explain the results, averaging `AVERAGE_ELO`.
+    ///
+    /// Each player also gets one imaginary draw against an average
+    /// player, which keeps someone who won (or lost) every game at a
+    /// finite rating instead of running off to infinity.
+    pub fn elo(&self) -> Vec<f64> {
+        let n = self.names.len();
+        let mut ratings = vec![0.0; n];
+        for _ in 0..10_000 {
+            let mut largest_step: f64 = 0.0;
+            for i in 0..n {
+                let [w, d, _] = self.totals(i);
+                let mut score = w as f64 + 0.5 * d as f64 + 0.5;
+                let mut expect = expected(ratings[i]);
+                let mut games = 1.0;
+                for j in 0..n {
+                    let played = self.scores[i][j].iter().sum::<u32>() as f64;
+                    expect += played * expected(ratings[i] - ratings[j]);
+                    games += played;
+                }
+                score -= expect;
+                let step = 200.0 * score / games;
+                ratings[i] += step;
+                largest_step = largest_step.max(step.abs());
+            }
+            if largest_step < 0.001 {
+                break;
+            }
+        }
+        let mean = ratings.iter().sum::<f64>() / n.max(1) as f64;
+        ratings.iter().map(|r| r - mean + AVERAGE_ELO).collect()
+    }
+}
+
+impl fmt::Display for Crosstable {
+    /// Best first, with each cell the row player's wins, draws and losses
+    /// against the column player.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let elo = self.elo();
+        let mut order: Vec<usize> = (0..self.names.len()).collect();
+        order.sort_by(|&a, &b| elo[b].partial_cmp(&elo[a]).unwrap_or(std::cmp::Ordering::Equal));
+        let width = self.names.iter().map(|s| s.len()).max().unwrap_or(0);
+
+        writeln!(f, "{} games per pairing, W-D-L from the row player's side", self.games_per_pairing)?;
+        write!(f, "    {:width$}", "", width = width)?;
+        for rank in 1..=order.len() {
+            write!(f, " {:>8}", rank)?;
+        }
+        writeln!(f, "  {:>5} {:>5} {:>5}  {:>5}  {:>5}", "W", "D", "L", "Score", "Elo")?;
+        for (rank, &i) in order.iter().enumerate() {
+            write!(f, "{:>2}. {:width$}", rank + 1, self.names[i], width = width)?;
+            for &j in &order {
+                if i == j {
+                    write!(f, " {:>8}", "-")?;
+                } else {
+                    let [w, d, l] = self.scores[i][j];
+                    write!(f, " {:>8}", format!("{}-{}-{}", w, d, l))?;
+                }
+            }
+            let [w, d, l] = self.totals(i);
+            let played = (w + d + l).max(1) as f64;
+            let score = (w as f64 + 0.5 * d as f64) / played;
+            writeln!(f, "  {:>5} {:>5} {:>5}  {:>4.0}%  {:>5.0}", w, d, l, score * 100.0, elo[i])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_entrants() {
+        assert_eq!("random".parse(), Ok(Entrant::Random));
+        assert_eq!("minimax:4".parse(), Ok(Entrant::Minimax(Some(4))));
+        assert_eq!("minimax:50ms".parse(), Ok(Entrant::TimedMinimax(Duration::from_millis(50))));
+        assert_eq!("mcts:500".parse(), Ok(Entrant::Mcts(Budget::Playouts(500))));
+        assert_eq!("mcts:20ms".parse(), Ok(Entrant::Mcts(Budget::Time(Duration::from_millis(20)))));
+        assert!("minimax:deep".parse::<Entrant>().is_err());
+        assert!("alphazero".parse::<Entrant>().is_err());
+        assert_eq!("engine:./Bot -q".parse(), Ok(Entrant::External("./Bot -q".to_owned())));
+        assert!("engine:".parse::<Entrant>().is_err());
+        assert_eq!("remote:Host:7879".parse(), Ok(Entrant::Remote("Host:7879".to_owned())));
+        assert_eq!("menace:Boxes.db".parse(), Ok(Entrant::Menace("Boxes.db".to_owned())));
+        assert!("remote:host".parse::<Entrant>().is_err());
+    }
+
+    #[test]
+    fn perfect_play_never_loses_and_rates_higher() {
+        let entrants = [Entrant::Random, Entrant::Minimax(None), Entrant::Greedy];
+        let table = run(&Board::new(3, 3), &entrants, 6, 1, Opening::Free);
+        for j in 0..3 {
+            assert_eq!(table.scores[1][j][2], 0, "minimax lost to {}", table.names[j]);
+        }
+        // everyone met everyone else six times, and both sides agree
+        assert_eq!(table.totals(0).iter().sum::<u32>(), 12);
+        assert_eq!(table.scores[0][2], [table.scores[2][0][2], table.scores[2][0][1], table.scores[2][0][0]]);
+
+        let elo = table.elo();
+        assert!(elo[1] > elo[0]);
+        assert!((elo.iter().sum::<f64>() / 3.0 - AVERAGE_ELO).abs() < 1e-6);
+        assert!(elo.iter().all(|r| r.is_finite()));
+    }
+
+    #[test]
+    fn swap2_matches_count_by_player_not_side() {
+        let entrants = [Entrant::Random, Entrant::Minimax(Some(2))];
+        let table = run(&Board::new(9, 5), &entrants, 4, 3, Opening::Swap2);
+        assert_eq!(table.totals(1)[0], 4, "{:?}", table.scores);
+    }
+
+    #[test]
+    fn even_results_give_even_ratings() {
+        let table = Crosstable {
+            names: vec!["a".to_owned(), "b".to_owned()],
+            scores: vec![vec![[0; 3], [3, 4, 3]], vec![[3, 4, 3], [0; 3]]],
+            games_per_pairing: 10,
+        };
+        let elo = table.elo();
+        assert!((elo[0] - elo[1]).abs() < 1e-6);
+        // 7.5 out of 10 is close to 200 points
+        let table = Crosstable {
+            scores: vec![vec![[0; 3], [7, 1, 2]], vec![[2, 1, 7], [0; 3]]],
+            ..table
+        };
+        let elo = table.elo();
+        assert!((150.0..200.0).contains(&(elo[0] - elo[1])), "{:?}", elo);
+    }
+}
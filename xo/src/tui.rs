@@ -0,0 +1,382 @@
+//! Full-screen play: the board is redrawn in place and the player moves a
+//! highlighted cursor instead of typing squares.
+//!
+//! Raw keyboard input comes from `stty`, and drawing is plain ANSI escape
+//! codes, so this works in any Unix terminal without extra crates.
+
+use std::cell::RefCell;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::board::{Board, Move, Variant};
+use crate::clock::Clock;
+use crate::game::Game;
+use crate::hint::Analysis;
+use crate::opening::Choice;
+use crate::replay::Replay;
+use crate::roster::Roster;
+use crate::strategy::{Action, Strategy};
+use crate::view::View;
+
+const CLEAR: &str = "\x1b[H\x1b[2J";
+const HIDE_CURSOR: &str = "\x1b[?25l";
+const SHOW_CURSOR: &str = "\x1b[?25h";
+const REVERSE: &str = "\x1b[7m";
+const WINNING: &str = "\x1b[1;32m";
+const RESET: &str = "\x1b[0m";
+
+/// How many recent messages stay on screen under the board.
+const STATUS_LINES: usize = 3;
+
+/// A read waits a fifth of a second for a key (`time 2` to `stty`). One
+/// that comes back empty well before then found the end of the input
+/// rather than waiting for it.
+const GONE_WITHIN: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Char(char),
+    /// Ctrl-C, Ctrl-D or end of input.
+    Quit,
+}
+
+/// The terminal while we own it. Dropping it puts everything back.
+pub struct Screen {
+    saved: String,
+    status: Vec<String>,
+    /// The last hint, shown on the board until the player moves.
+    hint: Option<Analysis>,
+    /// Everyone's time, in a game against the clock.
+    clock: Option<String>,
+    roster: Roster,
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let out = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !out.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_owned())
+}
+
+impl Screen {
+    /// Switches the terminal to raw mode. Fails when stdin or stdout isn't
+    /// a terminal, so callers can fall back to line mode.
+    pub fn enter() -> io::Result<Screen> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return Err(io::Error::other("not a terminal"));
+        }
+        let saved = stty(&["-g"])?;
+        // reads give up after a fifth of a second with nothing typed, so
+        // a running clock can be redrawn
+        stty(&["raw", "-echo", "min", "0", "time", "2"])?;
+        print!("{}", HIDE_CURSOR);
+        io::stdout().flush()?;
+        Ok(Screen {
+            saved,
+            status: Vec::new(),
+            hint: None,
+            clock: None,
+            roster: Roster::default(),
+        })
+    }
+
+    /// Draws the players' marks with the roster's symbols and colours.
+    pub fn with_roster(mut self, roster: Roster) -> Screen {
+        self.roster = roster;
+        self
+    }
+
+    fn message(&mut self, text: &str) {
+        self.status.push(text.to_owned());
+        if self.status.len() > STATUS_LINES {
+            self.status.remove(0);
+        }
+    }
+
+    /// Redraws everything. `cursor` is the square to highlight for the
+    /// player choosing a move, `line` the squares of a win.
+    fn draw(&mut self, board: &Board, cursor: Option<Move>, line: &[Move], prompt: &str) {
+        let mut out = String::from(CLEAR);
+        let hint = self.hint.as_ref();
+        let style = |mv| {
+            if Some(mv) == cursor {
+                Some((REVERSE, RESET))
+            } else if line.contains(&mv) {
+                Some((WINNING, RESET))
+            } else {
+                None
+            }
+        };
+        let roster = &self.roster;
+        let label = |mv| hint.and_then(|h| h.label(mv)).map(String::from).or_else(|| roster.label(board, mv));
+        out += &board.render_labelled("\r\n", &style, &label);
+        out += "\r\n\r\n";
+        if let Some(clock) = &self.clock {
+            out += &format!("{}\r\n", clock);
+        }
+        for text in &self.status {
+            out += &format!("{}\r\n", text);
+        }
+        out += prompt;
+        print!("{}", out);
+        let _ = io::stdout().flush();
+    }
+
+    /// The next key, or `None` if nothing was typed for a moment.
+    fn read_key(&mut self) -> Option<Key> {
+        let mut stdin = io::stdin();
+        let mut byte = || {
+            let mut buf = [0u8; 1];
+            stdin.read(&mut buf).map(|n| if n == 1 { Some(buf[0]) } else { None })
+        };
+        let asked = Instant::now();
+        let key = match byte() {
+            // empty both when nothing was typed and at the end of the input,
+            // which doesn't make it wait
+            Ok(None) if asked.elapsed() < GONE_WITHIN => Key::Quit,
+            Ok(None) => return None,
+            Err(_) | Ok(Some(3)) | Ok(Some(4)) => Key::Quit,
+            Ok(Some(b'\r')) | Ok(Some(b'\n')) | Ok(Some(b' ')) => Key::Enter,
+            // arrow keys arrive as ESC [ A and friends
+            Ok(Some(0x1b)) => match (byte(), byte()) {
+                (Ok(Some(b'[')), Ok(Some(b'A'))) => Key::Up,
+                (Ok(Some(b'[')), Ok(Some(b'B'))) => Key::Down,
+                (Ok(Some(b'[')), Ok(Some(b'C'))) => Key::Right,
+                (Ok(Some(b'[')), Ok(Some(b'D'))) => Key::Left,
+                _ => Key::Char('\x1b'),
+            },
+            Ok(Some(b)) => Key::Char(b as char),
+        };
+        Some(key)
+    }
+
+    /// The next key, however long it takes.
+    fn wait_key(&mut self) -> Key {
+        loop {
+            if let Some(key) = self.read_key() {
+                return key;
+            }
+        }
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        print!("{}\r\n", SHOW_CURSOR);
+        let _ = io::stdout().flush();
+        let _ = stty(&[self.saved.as_str()]);
+    }
+}
+
+/// Shows a local game on the shared screen.
+pub struct TuiView(pub Rc<RefCell<Screen>>);
+
+impl View for TuiView {
+    fn show(&mut self, board: &Board) {
+        let mut screen = self.0.borrow_mut();
+        let prompt = format!("{} to move", screen.roster.mark(board.to_move()));
+        screen.draw(board, None, &[], &prompt);
+    }
+
+    fn message(&mut self, text: &str) {
+        self.0.borrow_mut().message(text);
+    }
+
+    fn clock(&mut self, clock: &Clock) {
+        self.0.borrow_mut().clock = Some(clock.to_string());
+    }
+
+    fn hint(&mut self, _board: &Board, analysis: &Analysis) {
+        let mut screen = self.0.borrow_mut();
+        screen.message(&analysis.summary());
+        screen.hint = Some(analysis.clone());
+    }
+
+    fn finish(&mut self, game: &Game) {
+        let line = game.board().winning_line().unwrap_or_default();
+        let text = game.result_text().unwrap_or_default();
+        self.0.borrow_mut().draw(game.board(), None, &line, &text);
+    }
+}
+
+/// A person at the keyboard, picking squares with the cursor.
+pub struct KeyboardPlayer {
+    name: String,
+    screen: Rc<RefCell<Screen>>,
+    cursor: Option<Move>,
+    /// Running down while they choose, in a game against the clock.
+    clock: Option<Clock>,
+}
+
+impl KeyboardPlayer {
+    pub fn new(name: &str, screen: Rc<RefCell<Screen>>) -> KeyboardPlayer {
+        KeyboardPlayer {
+            name: name.to_owned(),
+            screen,
+            cursor: None,
+            clock: None,
+        }
+    }
+}
+
+impl Strategy for KeyboardPlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose_move(&mut self, board: &Board) -> Option<Move> {
+        loop {
+            match self.choose_action(board) {
+                Action::Move(mv) => return Some(mv),
+                Action::Quit => return None,
+                _ => {}
+            }
+        }
+    }
+
+    fn choose_action(&mut self, board: &Board) -> Action {
+        let (last, last_row) = (board.size() - 1, board.rows() - 1);
+        let mut cursor = self.cursor.unwrap_or_else(|| board.centre());
+        let gravity = board.variant() == Variant::Gravity;
+        let layers = if board.layers() > 1 { ", [ ] change layer" } else { "" };
+        // what to put down, when the rules give a choice
+        let pieces = board.rules().pieces(board);
+        let mut pick = 0;
+        let mut screen = self.screen.borrow_mut();
+        let mut redraw = true;
+        loop {
+            if let Some(clock) = &self.clock {
+                if clock.flagged().is_some() {
+                    // the game sees the flag before it sees this
+                    return Action::Quit;
+                }
+                let time = Some(clock.to_string());
+                redraw |= screen.clock != time;
+                screen.clock = time;
+            }
+            if redraw {
+                if gravity {
+                    // only the column is the player's to pick; the piece drops
+                    cursor.row = board.landing(cursor.col).unwrap_or(0);
+                }
+                let placing = match pieces[pick] {
+                    Some(piece) => format!(", Tab changes piece ({})", piece),
+                    None => String::new(),
+                };
+                let prompt = format!(
+                    "{} ({}): arrows/WASD move{}{}, Enter places, ? hint, u undo, r redo, q quit",
+                    self.name,
+                    screen.roster.mark(board.to_move()),
+                    layers,
+                    placing
+                );
+                screen.draw(board, Some(cursor), &[], &prompt);
+            }
+            // nothing typed yet: only the clock might need redrawing
+            let key = match screen.read_key() {
+                Some(key) => key,
+                None => {
+                    redraw = false;
+                    continue;
+                }
+            };
+            redraw = true;
+            match key {
+                Key::Up | Key::Char('w') | Key::Char('k') => cursor.row = cursor.row.saturating_sub(1),
+                Key::Down | Key::Char('s') | Key::Char('j') => cursor.row = (cursor.row + 1).min(last_row),
+                Key::Left | Key::Char('a') | Key::Char('h') => cursor.col = cursor.col.saturating_sub(1),
+                Key::Right | Key::Char('d') | Key::Char('l') => cursor.col = (cursor.col + 1).min(last),
+                Key::Char('[') => cursor.layer = cursor.layer.map(|l| l.saturating_sub(1)),
+                Key::Char(']') => cursor.layer = cursor.layer.map(|l| (l + 1).min(last)),
+                Key::Char('\t') => pick = (pick + 1) % pieces.len(),
+                Key::Enter => match board.check(cursor.with_piece(pieces[pick])) {
+                    Ok(()) => {
+                        self.cursor = Some(cursor);
+                        screen.hint = None;
+                        return Action::Move(cursor.with_piece(pieces[pick]));
+                    }
+                    Err(e) => screen.message(&format!("Sorry, {}.", e)),
+                },
+                Key::Char('?') => return Action::Hint,
+                Key::Char('u') => {
+                    screen.hint = None;
+                    return Action::Undo;
+                }
+                Key::Char('r') => {
+                    screen.hint = None;
+                    return Action::Redo;
+                }
+                Key::Char('q') | Key::Quit => return Action::Quit,
+                Key::Char(_) => {}
+            }
+        }
+    }
+
+    fn use_clock(&mut self, clock: &Clock) {
+        self.clock = Some(clock.clone());
+    }
+
+    /// One key per choice: x, o, or t for two more stones.
+    fn choose_swap(&mut self, board: &Board, choices: &[Choice]) -> Option<Choice> {
+        let options: Vec<String> = choices.iter().map(|c| format!("{} {}", &c.name()[..1], c)).collect();
+        let prompt = format!("{}: {}, q quit", self.name, options.join(", "));
+        let mut screen = self.screen.borrow_mut();
+        loop {
+            screen.draw(board, None, &[], &prompt);
+            match screen.wait_key() {
+                Key::Char('q') | Key::Quit => return None,
+                Key::Char(key) => {
+                    if let Some(&choice) = choices.iter().find(|c| c.name().starts_with(key)) {
+                        return Some(choice);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Steps through a game with the arrow keys, redrawing the board each
+/// move with the last move highlighted.
+pub fn replay(screen: &mut Screen, replay: &mut Replay) {
+    let mut typed = String::new();
+    loop {
+        screen.status = replay.caption();
+        let line = match replay.ending() {
+            Some(_) => replay.board().winning_line().unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let prompt = format!(
+            "[{}/{}] left/right step, s start, e end, number + Enter jumps, q quits {}",
+            replay.ply(),
+            replay.len(),
+            typed
+        );
+        screen.draw(replay.board(), replay.board().last_move(), &line, &prompt);
+        match screen.wait_key() {
+            Key::Right | Key::Char('n') | Key::Char('l') => {
+                replay.forward();
+            }
+            Key::Left | Key::Char('b') | Key::Char('h') => {
+                replay.back();
+            }
+            Key::Char('s') => replay.goto(0),
+            Key::Char('e') => replay.goto(replay.len()),
+            Key::Char(c) if c.is_ascii_digit() => typed.push(c),
+            Key::Enter if !typed.is_empty() => {
+                replay.goto(typed.parse().unwrap_or(0));
+                typed.clear();
+            }
+            Key::Char('q') | Key::Quit => return,
+            _ => typed.clear(),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::board::Board;
+use crate::clock::Clock;
+use crate::game::Game;
+use crate::hint::Analysis;
+use crate::roster::Roster;
+
+/// Where a local game is shown: printed line by line, or drawn in place
+/// by the terminal UI.
+pub trait View {
+    /// Shows the position before the next move.
+    fn show(&mut self, board: &Board);
+
+    /// Something the players should know, like the computer's last move.
+    fn message(&mut self, text: &str);
+
+    /// Shows the time everyone has left, before each move of a game
+    /// against the clock.
+    fn clock(&mut self, clock: &Clock) {
+        self.message(&clock.to_string());
+    }
+
+    /// Shows what the engine makes of each move the player could make.
+    fn hint(&mut self, board: &Board, analysis: &Analysis);
+
+    /// Shows the final position and who won.
+    fn finish(&mut self, game: &Game);
+}
+
+/// Prints every position, for pipes and plain terminals.
+#[derive(Default)]
+pub struct LineView {
+    roster: Roster,
+}
+
+impl LineView {
+    /// Draws the players' marks with the roster's symbols and colours.
+    pub fn new(roster: Roster) -> LineView {
+        LineView { roster }
+    }
+
+    fn render(&self, board: &Board, analysis: Option<&Analysis>) -> String {
+        let label = |mv| {
+            let verdict = analysis.and_then(|a| a.label(mv)).map(String::from);
+            verdict.or_else(|| self.roster.label(board, mv))
+        };
+        board.render_labelled("\n", &|_| None, &label)
+    }
+}
+
+impl View for LineView {
+    fn show(&mut self, board: &Board) {
+        println!("{}", self.render(board, None));
+    }
+
+    fn message(&mut self, text: &str) {
+        println!("{}", text);
+    }
+
+    fn hint(&mut self, board: &Board, analysis: &Analysis) {
+        println!("{}", self.render(board, Some(analysis)));
+        println!("{}", analysis);
+    }
+
+    fn finish(&mut self, game: &Game) {
+        println!("{}", self.render(game.board(), None));
+        if let Some(text) = game.result_text() {
+            println!("{}", text);
+        }
+    }
+}
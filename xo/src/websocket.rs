@@ -0,0 +1,218 @@
+//! Just enough of WebSockets (RFC 6455) for the server to push updates to
+//! browsers: the opening handshake, and unfragmented frames each way.
+//!
+//! The handshake needs SHA-1 and base64, which are written out here rather
+//! than pulled in, as with everything else in the crate.
+
+use std::io::{self, Read};
+
+/// Appended to the browser's key before hashing, so only a server that
+/// knows the protocol can answer.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Biggest message taken from a browser. Anything it needs to say to a
+/// game server is far smaller.
+const MAX_PAYLOAD: u64 = 1 << 16;
+
+/// One message, or a control frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+    Close,
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+}
+
+impl Frame {
+    fn opcode(&self) -> u8 {
+        match self {
+            Frame::Text(_) => 1,
+            Frame::Binary(_) => 2,
+            Frame::Close => 8,
+            Frame::Ping(_) => 9,
+            Frame::Pong(_) => 10,
+        }
+    }
+
+    fn payload(&self) -> &[u8] {
+        match self {
+            Frame::Text(text) => text.as_bytes(),
+            Frame::Binary(data) | Frame::Ping(data) | Frame::Pong(data) => data,
+            Frame::Close => &[],
+        }
+    }
+
+    /// The frame on the wire. Servers send them as they are; clients have
+    /// to `mask` theirs.
+    pub fn to_bytes(&self, mask: Option<[u8; 4]>) -> Vec<u8> {
+        let payload = self.payload();
+        let mut bytes = vec![0x80 | self.opcode()];
+        let masked = if mask.is_some() { 0x80 } else { 0 };
+        match payload.len() {
+            len if len < 126 => bytes.push(masked | len as u8),
+            len if len <= 0xffff => {
+                bytes.push(masked | 126);
+                bytes.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                bytes.push(masked | 127);
+                bytes.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        match mask {
+            Some(mask) => {
+                bytes.extend_from_slice(&mask);
+                bytes.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+            }
+            None => bytes.extend_from_slice(payload),
+        }
+        bytes
+    }
+
+    /// Reads the next frame, masked or not.
+    pub fn read(input: &mut dyn Read) -> io::Result<Frame> {
+        let mut head = [0; 2];
+        input.read_exact(&mut head)?;
+        let (fin, opcode) = (head[0] & 0x80 != 0, head[0] & 0x0f);
+        let masked = head[1] & 0x80 != 0;
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut len = [0; 2];
+                input.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as u64
+            }
+            127 => {
+                let mut len = [0; 8];
+                input.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => len as u64,
+        };
+        if len > MAX_PAYLOAD {
+            return Err(invalid("the message is too long"));
+        }
+        let mut mask = [0; 4];
+        if masked {
+            input.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; len as usize];
+        input.read_exact(&mut payload)?;
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+        if !fin || opcode == 0 {
+            return Err(invalid("messages in pieces aren't supported"));
+        }
+        match opcode {
+            1 => String::from_utf8(payload).map(Frame::Text).map_err(|_| invalid("the text isn't UTF-8")),
+            2 => Ok(Frame::Binary(payload)),
+            8 => Ok(Frame::Close),
+            9 => Ok(Frame::Ping(payload)),
+            10 => Ok(Frame::Pong(payload)),
+            _ => Err(invalid("unknown frame")),
+        }
+    }
+}
+
+fn invalid(text: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, text.to_owned())
+}
+
+/// What the server answers a browser's `Sec-WebSocket-Key` with.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+    // pad with a one bit, zeros, and the length in bits to a whole block
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 20];
+    for (out, word) in digest.chunks_mut(4).zip(h) {
+        out.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn hashes_and_encodes() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // two blocks once padded
+        let long = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(hex(&sha1(long)), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        assert_eq!([base64(b""), base64(b"f"), base64(b"fo"), base64(b"foo")], ["", "Zg==", "Zm8=", "Zm9v"]);
+        // the example in RFC 6455
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let long = Frame::Text("x".repeat(300));
+        for frame in [Frame::Text("MOVE b2".to_owned()), long, Frame::Ping(vec![1, 2]), Frame::Close] {
+            for mask in [None, Some([7, 0, 255, 42])] {
+                let bytes = frame.to_bytes(mask);
+                assert_eq!(Frame::read(&mut &bytes[..]).unwrap(), frame);
+            }
+        }
+        // a first piece of a longer message
+        assert!(Frame::read(&mut &[0x01, 0x01, b'x'][..]).is_err());
+    }
+}
@@ -0,0 +1,36 @@
+//! The `xo` binary's options: the ones that don't go together are
+//! turned away before anything starts.
+
+use std::process::Command;
+
+/// Runs `xo` with `args` and returns its exit code and what it said on
+/// stderr.
+fn xo(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_xo")).args(args).output().unwrap();
+    (output.status.code(), String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+#[test]
+fn tournaments_and_the_engine_are_for_two_players() {
+    let three = ["--size", "5", "--k", "4", "--player", "a", "--player", "b", "--player", "c"];
+    let (code, said) = xo(&[&["tournament"][..], &three, &["--players", "random,greedy"]].concat());
+    assert_eq!(code, Some(2));
+    assert!(said.starts_with("xo: tournaments are for two players"), "{}", said);
+    let (code, said) = xo(&[&["engine"][..], &three].concat());
+    assert_eq!(code, Some(2));
+    assert!(said.starts_with("xo: the engine plays two-player games"), "{}", said);
+}
+
+#[test]
+fn swap2_isnt_played_against_the_clock() {
+    let (code, said) = xo(&["--size", "15", "--rules", "renju", "--opening", "swap2", "--clock", "60"]);
+    assert_eq!(code, Some(2));
+    assert!(said.starts_with("xo: games against the clock don't have a swap2 opening"), "{}", said);
+}
+
+#[test]
+fn mcts_needs_a_playout() {
+    let (code, said) = xo(&["--engine", "mcts", "--playouts", "0"]);
+    assert_eq!(code, Some(2));
+    assert!(said.starts_with("xo: --playouts needs at least one playout"), "{}", said);
+}
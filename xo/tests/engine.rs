@@ -0,0 +1,69 @@
+//! The `xo engine` binary played over pipes, the way outside bots are.
+
+use std::net::TcpListener;
+use std::thread;
+
+use xo::opening::{Choice, Opening};
+use xo::tournament::{self, Entrant};
+use xo::{Board, Move, Rules, Strategy};
+
+fn engine_command() -> String {
+    format!("{} engine", env!("CARGO_BIN_EXE_xo"))
+}
+
+#[test]
+fn plays_over_pipes() {
+    let mut bot = xo::engine::External::new(&engine_command());
+    let mut board = Board::new(3, 3);
+    for &(row, col) in &[(1, 1), (0, 0), (0, 2)] {
+        board.play(Move::new(row, col)).unwrap();
+    }
+    // O has to block the diagonal, and knows it's not lost
+    assert_eq!(bot.choose_move(&board), Some(Move::new(2, 0)));
+    assert_eq!(bot.comment().as_deref(), Some("score +0"));
+}
+
+#[test]
+fn takes_part_in_tournaments() {
+    let entrants = [Entrant::Random, Entrant::External(engine_command())];
+    let table = tournament::run(&Board::new(3, 3), &entrants, 4, 7, Opening::Free);
+    assert_eq!(table.names[1], engine_command());
+    assert_eq!(table.scores[1][0][2], 0, "perfect play over pipes lost");
+}
+
+#[test]
+fn chooses_sides_over_pipes() {
+    let mut bot = xo::engine::External::new(&engine_command());
+    let mut board = Board::new(15, 5).with_rules(Rules::Renju);
+    for &(row, col) in &[(7, 7), (0, 0), (7, 8)] {
+        board.play(Move::new(row, col)).unwrap();
+    }
+    let choices = [Choice::PlayX, Choice::PlayO, Choice::PlaceTwo];
+    assert_eq!(bot.choose_swap(&board, &choices), Some(Choice::PlayX));
+    // and gives up rather than choose what isn't on offer
+    assert_eq!(bot.choose_swap(&board, &[Choice::PlaceTwo]), None);
+}
+
+#[test]
+fn a_missing_program_gives_up() {
+    let mut bot = xo::engine::External::new("./no-such-engine");
+    assert_eq!(bot.choose_move(&Board::new(3, 3)), None);
+}
+
+#[test]
+fn plays_over_the_network() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || xo::engine::listen(&listener));
+    // two at once, each with an engine of their own
+    let mut bots = [xo::engine::External::connect(&addr), xo::engine::External::connect(&addr)];
+    let mut board = Board::new(5, 3).with_players(3);
+    for &(row, col) in &[(0, 0), (4, 4), (4, 2), (0, 1)] {
+        board.play(Move::new(row, col)).unwrap();
+    }
+    // Y has to stop X's a1 b1 on c1
+    assert_eq!(bots[0].choose_move(&board), Some(Move::new(0, 2)));
+    board.play(Move::new(0, 2)).unwrap();
+    assert!(bots[1].choose_move(&board).is_some());
+    assert_eq!(bots[1].name(), addr);
+}
@@ -0,0 +1,117 @@
+//! Properties that should hold for every board and every line of play,
+//! checked over random games through the public API alone.
+
+use xo::rng::Rng;
+use xo::{Board, GameResult, Move, MoveError, Rules};
+
+/// One of each kind of board, under each set of rules it supports, and
+/// with more than two players where it takes them.
+fn boards() -> Vec<Board> {
+    vec![
+        Board::new(3, 3),
+        Board::new(3, 3).with_rules(Rules::Misere),
+        Board::new(3, 3).with_rules(Rules::Wild),
+        Board::new(3, 3).with_rules(Rules::Numerical),
+        Board::new(5, 4),
+        Board::new(9, 5),
+        Board::new(9, 5).with_rules(Rules::Renju),
+        Board::ultimate(),
+        Board::cube(3, 3),
+        Board::cube(4, 4),
+        Board::gravity(7, 4),
+        Board::gravity(5, 3).with_rules(Rules::Misere),
+        Board::new(6, 4).with_players(3),
+        Board::cube(4, 3).with_players(4),
+        Board::gravity(7, 4).with_players(3),
+    ]
+}
+
+/// Everything about a position, down to the cached bitboards and hash,
+/// plus what a player could ask of it.
+fn snapshot(board: &Board) -> String {
+    format!("{:?} {:?} {:?}", board, board.result(), board.legal_moves())
+}
+
+/// Plays random games on every board, calling `check` before each move
+/// with the position and the move about to be played.
+fn random_games(games: usize, seed: u64, check: &mut dyn FnMut(&mut Board, Move)) {
+    let mut rng = Rng::new(seed);
+    for board in &mut boards() {
+        for _ in 0..games {
+            while board.result().is_none() {
+                let moves = board.legal_moves();
+                let mv = moves[rng.below(moves.len())];
+                check(board, mv);
+                board.play(mv).unwrap();
+            }
+            while board.undo().is_some() {}
+        }
+    }
+}
+
+#[test]
+fn playing_then_undoing_restores_the_board() {
+    random_games(20, 1, &mut |board, mv| {
+        let before = snapshot(board);
+        board.play(mv).unwrap();
+        assert_eq!(board.last_move(), Some(mv));
+        assert_eq!(board.undo(), Some(mv));
+        assert_eq!(snapshot(board), before, "playing and undoing {} on\n{}", mv, board);
+    });
+}
+
+#[test]
+fn every_legal_move_checks_and_nothing_else_does() {
+    random_games(5, 2, &mut |board, _| {
+        let legal = board.legal_moves();
+        for layer in 0..board.layers() {
+            for row in 0..board.rows() {
+                for col in 0..board.size() {
+                    let square = board.square(layer, row, col);
+                    let playable = legal.iter().any(|mv| mv.with_piece(None) == square);
+                    // where pieces are chosen, the plain square isn't a whole move
+                    if !board.rules().picks_pieces() {
+                        assert_eq!(board.check(square).is_ok(), playable, "{} on\n{}", square, board);
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn every_finished_game_has_exactly_one_result() {
+    let mut rng = Rng::new(3);
+    for board in &mut boards() {
+        for _ in 0..50 {
+            let mut plies = 0;
+            while board.result().is_none() {
+                assert!(!board.legal_moves().is_empty(), "stuck with no result on\n{}", board);
+                let moves = board.legal_moves();
+                board.play(moves[rng.below(moves.len())]).unwrap();
+                plies += 1;
+            }
+            assert!(plies <= board.squares());
+            let result = board.result().unwrap();
+            match result {
+                GameResult::Win(p) => {
+                    assert_eq!(board.winner(), Some(p));
+                    assert!(board.winning_line().is_some());
+                }
+                GameResult::Draw => {
+                    assert_eq!(board.winner(), None);
+                    assert!(board.winning_line().is_none());
+                }
+            }
+            // and it stays that way: nothing more can be played
+            assert!(board.legal_moves().is_empty());
+            for i in 0..board.squares() {
+                let mv = board.square_at(i);
+                assert_eq!(board.play(mv), Err(MoveError::GameOver));
+            }
+            assert_eq!(board.result(), Some(result));
+            while board.undo().is_some() {}
+            assert_eq!(board.result(), None);
+        }
+    }
+}
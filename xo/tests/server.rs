@@ -0,0 +1,216 @@
+//! `xo serve`'s API and WebSockets, played on localhost the way the page
+//! plays them.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use xo::websocket::{accept_key, Frame};
+
+fn start() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || xo::server::listen(&listener));
+    addr
+}
+
+/// The status and body of one request.
+fn request(addr: &str, method: &str, path: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let form = "Content-Type: application/x-www-form-urlencoded";
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\n{}\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        addr,
+        form,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let body = response.split_once("\r\n\r\n").unwrap().1.to_owned();
+    (status, body)
+}
+
+/// A string field's value in some JSON.
+fn field(json: &str, name: &str) -> String {
+    let key = format!("\"{}\":\"", name);
+    let start = json.find(&key).unwrap_or_else(|| panic!("no {} in {}", name, json)) + key.len();
+    json[start..].split('"').next().unwrap().to_owned()
+}
+
+/// The game a seat or a game's JSON is for.
+fn game_id(json: &str) -> String {
+    json.strip_prefix("{\"id\":").unwrap().split(',').next().unwrap().to_owned()
+}
+
+/// A WebSocket on game `id`, once the server has agreed to it: a player's
+/// with their `token`, or a spectator's without.
+fn socket(addr: &str, id: &str, token: Option<&str>) -> BufReader<TcpStream> {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let key = "dGhlIHNhbXBsZSBub25jZQ==";
+    write!(
+        stream,
+        "GET /games/{}/socket{} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        id,
+        token.map_or(String::new(), |token| format!("?token={}", token)),
+        addr,
+        key
+    )
+    .unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut head = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        head.push(line.trim_end().to_owned());
+    }
+    assert_eq!(head[0], "HTTP/1.1 101 Switching Protocols");
+    assert!(head.contains(&format!("Sec-WebSocket-Accept: {}", accept_key(key))));
+    reader
+}
+
+fn update(socket: &mut BufReader<TcpStream>) -> String {
+    match Frame::read(socket).unwrap() {
+        Frame::Text(json) => json,
+        other => panic!("expected an update, got {:?}", other),
+    }
+}
+
+#[test]
+fn serves_the_page() {
+    let addr = start();
+    let (status, page) = request(&addr, "GET", "/", "");
+    assert_eq!(status, 200);
+    assert!(page.contains("<title>xo</title>"));
+    assert_eq!(request(&addr, "GET", "/nowhere", "").0, 404);
+    assert_eq!(request(&addr, "DELETE", "/games", "").0, 405);
+}
+
+#[test]
+fn plays_games_side_by_side_and_pushes_every_move() {
+    let addr = start();
+    let (status, ann) = request(&addr, "POST", "/games", "name=Ann");
+    assert_eq!(status, 201);
+    let (_, cy) = request(&addr, "POST", "/games", "name=Cy&variant=gravity");
+    let (first, second) = (game_id(&ann), game_id(&cy));
+    assert_ne!(first, second);
+
+    let mut watching = socket(&addr, &first, None);
+    assert_eq!(field(&update(&mut watching), "status"), "waiting");
+    let (_, bob) = request(&addr, "POST", &format!("/games/{}/join", first), "name=Bob");
+    assert_eq!(field(&update(&mut watching), "status"), "playing");
+    let mut other = socket(&addr, &second, None);
+    assert_eq!(field(&update(&mut other), "variant"), "gravity");
+
+    let (x, o) = (field(&ann, "token"), field(&bob, "token"));
+    let moves = [(&x, "a1"), (&o, "b1"), (&x, "a2"), (&o, "b2")];
+    for (token, mv) in &moves {
+        let (status, _) =
+            request(&addr, "POST", &format!("/games/{}/moves", first), &format!("token={}&move={}", token, mv));
+        assert_eq!(status, 200);
+        assert!(update(&mut watching).contains(&format!("\"{}\"]", mv)));
+    }
+    let (status, body) = request(&addr, "POST", &format!("/games/{}/moves", first), &format!("token={}&move=a3", o));
+    assert_eq!((status, field(&body, "error")), (409, "it's not your turn".to_owned()));
+    request(&addr, "POST", &format!("/games/{}/moves", first), &format!("token={}&move=a3", x));
+    let finished = update(&mut watching);
+    assert_eq!(field(&finished, "result"), "Ann wins!");
+    assert!(finished.contains("\"line\":[\"a1\",\"a2\",\"a3\"]"));
+
+    // nothing of that reached the other game
+    other.get_ref().set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    assert!(Frame::read(&mut other).is_err());
+    let (_, state) = request(&addr, "GET", &format!("/games/{}", second), "");
+    assert_eq!(field(&state, "status"), "waiting");
+}
+
+#[test]
+fn refuses_what_it_cant_do() {
+    let addr = start();
+    assert_eq!(request(&addr, "POST", "/games", "size=30").0, 400);
+    assert_eq!(request(&addr, "GET", "/games/99", "").0, 404);
+    let mut stream = TcpStream::connect(&addr).unwrap();
+    stream.write_all(b"nonsense\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 400 "));
+    // a socket without the upgrade is just a bad request
+    assert_eq!(request(&addr, "GET", "/games/1/socket", "").0, 400);
+}
+
+#[test]
+fn spectators_catch_up_then_follow_along() {
+    let addr = start();
+    let (_, ann) = request(&addr, "POST", "/games", "name=Ann");
+    let id = game_id(&ann);
+    let mut player = socket(&addr, &id, Some(&field(&ann, "token")));
+    update(&mut player);
+    let (_, bob) = request(&addr, "POST", &format!("/games/{}/join", id), "name=Bob");
+    update(&mut player);
+    let (x, o) = (field(&ann, "token"), field(&bob, "token"));
+    let moves = format!("/games/{}/moves", id);
+    for (token, mv) in [(&x, "b2"), (&o, "a1")] {
+        request(&addr, "POST", &moves, &format!("token={}&move={}", token, mv));
+        update(&mut player);
+    }
+
+    // the lobby lists the game, and nobody's watching it yet
+    let (_, lobby) = request(&addr, "GET", "/games", "");
+    assert!(lobby.starts_with(&format!("{{\"games\":[{{\"id\":{},", id)), "{}", lobby);
+    assert!(lobby.contains("\"players\":[\"Ann\",\"Bob\"],\"status\":\"playing\",\"spectators\":0"));
+
+    // a spectator turning up now is shown the game from the start
+    let mut spectator = socket(&addr, &id, None);
+    let replayed: Vec<_> = (0..4).map(|_| update(&mut spectator)).collect();
+    assert!(replayed.iter().all(|event| event.contains("\"replay\":true")));
+    let said: Vec<_> = replayed.iter().map(|event| (field(event, "event"), field(event, "side"))).collect();
+    let expected = [("joined", "X"), ("joined", "O"), ("move", "X"), ("move", "O")];
+    assert_eq!(said, expected.map(|(event, side)| (event.to_owned(), side.to_owned())));
+    assert_eq!(field(&replayed[1], "name"), "Bob");
+    assert_eq!(field(&replayed[2], "status"), "playing");
+    assert_eq!(field(&replayed[3], "move"), "a1");
+    let (_, lobby) = request(&addr, "GET", "/games", "");
+    assert!(lobby.contains("\"spectators\":1"));
+
+    // one who leaves stops being counted straight away, not at the next move
+    let spectators = || request(&addr, "GET", "/games", "").1.split("\"spectators\":").nth(1).unwrap()[..1].to_owned();
+    let leaving = socket(&addr, &id, None);
+    assert_eq!(spectators(), "2");
+    drop(leaving);
+    for _ in 0..50 {
+        if spectators() == "1" {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(spectators(), "1");
+
+    // then sees what happens as it happens, but can't take part
+    request(&addr, "POST", &moves, &format!("token={}&move=c3", x));
+    for socket in [&mut player, &mut spectator] {
+        let live = update(socket);
+        assert_eq!((field(&live, "move"), live.contains("\"replay\":false")), ("c3".to_owned(), true));
+    }
+    assert_eq!(request(&addr, "POST", &moves, "move=a2").0, 403);
+    spectator.get_mut().write_all(&Frame::Text("a2".to_owned()).to_bytes(Some([1, 2, 3, 4]))).unwrap();
+    request(&addr, "POST", &format!("/games/{}/resign", id), &format!("token={}", o));
+    let resigned = update(&mut spectator);
+    assert_eq!(
+        (field(&resigned, "event"), field(&resigned, "result")),
+        ("resigned".to_owned(), "Bob resigns. Ann wins!".to_owned())
+    );
+
+    // and a finished game leaves the lobby
+    assert_eq!(request(&addr, "GET", "/games", "").1, "{\"games\":[]}");
+}